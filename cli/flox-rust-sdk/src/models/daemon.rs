@@ -0,0 +1,177 @@
+//! Protocol of `floxd`, the per-user environment service daemon
+//!
+//! `floxd` keeps recently activated environments built,
+//! rebuilds them when their manifest changes
+//! and periodically fetches updates of managed environments from FloxHub.
+//!
+//! The daemon listens on a Unix socket in the flox cache directory,
+//! see [socket_path].
+//! Clients send a single [Request] serialized as one line of JSON
+//! and receive a single [Response] in the same format.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Directory in the flox cache directory used by `floxd`
+pub const FLOXD_DIR_NAME: &str = "floxd";
+pub const SOCKET_FILENAME: &str = "floxd.sock";
+
+/// Requests must not delay `flox activate` noticeably
+/// if the daemon is busy or unresponsive
+const CLIENT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Path of the socket `floxd` listens on
+pub fn socket_path(cache_dir: &Path) -> PathBuf {
+    cache_dir.join(FLOXD_DIR_NAME).join(SOCKET_FILENAME)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "kebab-case")]
+pub enum Request {
+    /// The environment at `dot_flox_path` is being activated.
+    ///
+    /// Registers the environment to be kept up to date
+    /// and returns its current status.
+    Used { dot_flox_path: PathBuf },
+    /// Return the status of all registered environments
+    List,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "kebab-case")]
+pub enum Response {
+    Environment {
+        status: EnvironmentStatus,
+    },
+    Environments {
+        environments: Vec<EnvironmentStatus>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// State of an environment registered with `floxd`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvironmentStatus {
+    pub dot_flox_path: PathBuf,
+    pub build: BuildState,
+    /// FloxHub has changes of a managed environment that are not yet pulled
+    pub upstream_changed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum BuildState {
+    /// The environment has not been built by the daemon yet
+    Pending,
+    Building,
+    /// The environment is built and up to date with its manifest
+    Ready {
+        activation_path: PathBuf,
+    },
+    Failed {
+        message: String,
+    },
+}
+
+/// Output of `flox prepare`, which `floxd` runs to build
+/// and fetch environments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PrepareResult {
+    pub activation_path: PathBuf,
+    /// [None] unless updates were fetched
+    pub upstream_changed: Option<bool>,
+}
+
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    #[error("floxd is not running")]
+    NotRunning(#[source] io::Error),
+    #[error("could not communicate with floxd")]
+    Io(#[source] io::Error),
+    #[error("could not serialize request to floxd")]
+    Serialize(#[source] serde_json::Error),
+    #[error("invalid response from floxd")]
+    Deserialize(#[source] serde_json::Error),
+}
+
+/// Send `request` to the daemon listening on `socket` and wait for its response
+pub fn request(socket: &Path, request: &Request) -> Result<Response, DaemonError> {
+    let stream = UnixStream::connect(socket).map_err(DaemonError::NotRunning)?;
+    stream
+        .set_read_timeout(Some(CLIENT_TIMEOUT))
+        .map_err(DaemonError::Io)?;
+    stream
+        .set_write_timeout(Some(CLIENT_TIMEOUT))
+        .map_err(DaemonError::Io)?;
+
+    let serialized = serde_json::to_string(request).map_err(DaemonError::Serialize)?;
+    writeln!(&stream, "{serialized}").map_err(DaemonError::Io)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(DaemonError::Io)?;
+
+    serde_json::from_str(&line).map_err(DaemonError::Deserialize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_request_roundtrip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let socket = socket_path(tempdir.path());
+        std::fs::create_dir_all(socket.parent().unwrap()).unwrap();
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let status = EnvironmentStatus {
+            dot_flox_path: "/project/.flox".into(),
+            build: BuildState::Ready {
+                activation_path: "/project/.flox/run/env".into(),
+            },
+            upstream_changed: false,
+        };
+
+        let server = {
+            let status = status.clone();
+            thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                let request: Request = serde_json::from_str(&line).unwrap();
+                let response = Response::Environment { status };
+                writeln!(&stream, "{}", serde_json::to_string(&response).unwrap()).unwrap();
+                request
+            })
+        };
+
+        let response = request(&socket, &Request::Used {
+            dot_flox_path: "/project/.flox".into(),
+        })
+        .unwrap();
+
+        assert_eq!(response, Response::Environment { status });
+        assert_eq!(server.join().unwrap(), Request::Used {
+            dot_flox_path: "/project/.flox".into(),
+        });
+    }
+
+    #[test]
+    fn test_request_not_running() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let result = request(&socket_path(tempdir.path()), &Request::List);
+        assert!(matches!(result, Err(DaemonError::NotRunning(_))));
+    }
+}
@@ -2,7 +2,9 @@ use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::str::FromStr;
 
+use fslock::LockFile;
 use log::debug;
 use thiserror::Error;
 
@@ -12,6 +14,7 @@ use super::{
     InstallationAttempt,
     UninstallationAttempt,
     UpdateResult,
+    ENV_DIR_NAME,
     LOCKFILE_FILENAME,
     MANIFEST_FILENAME,
 };
@@ -23,15 +26,69 @@ use crate::models::manifest::{
     insert_packages,
     remove_packages,
     Manifest,
+    ManifestError,
     PackageToInstall,
     TomlEditError,
 };
 use crate::models::pkgdb::{CallPkgDbError, UpgradeResult, UpgradeResultJSON, PKGDB_BIN};
 use crate::utils::CommandExt;
 
+/// Extension of the lock file held while an environment is modified,
+/// e.g. `.flox/env.transaction.lock` for `.flox/env`
+pub const TRANSACTION_LOCK_EXTENSION: &str = "transaction.lock";
+
 pub struct ReadOnly {}
 struct ReadWrite {}
 
+/// An exclusive lock on an environment, held while it is modified
+///
+/// Implementations of [super::Environment] acquire the lock on their `.flox` directory
+/// before reading the current state of the environment,
+/// and hold it until the new state is built, linked and recorded as a generation.
+/// The [CoreEnvironment] that is modified may be a temporary checkout,
+/// so the lock can't be tied to its directory.
+pub struct EnvironmentLock {
+    _lock: LockFile,
+}
+
+impl EnvironmentLock {
+    /// Lock the environment in `dot_flox_path`, e.g. `.flox/env.transaction.lock`
+    ///
+    /// Blocks until concurrent modifications of the same environment,
+    /// e.g. another `flox install` in the same project, have completed.
+    /// The lock is released when the returned [EnvironmentLock] is dropped.
+    pub fn acquire(dot_flox_path: impl AsRef<Path>) -> Result<Self, CoreEnvironmentError> {
+        let lock_path = dot_flox_path
+            .as_ref()
+            .join(ENV_DIR_NAME)
+            .with_extension(TRANSACTION_LOCK_EXTENSION);
+        let mut lock =
+            LockFile::open(&lock_path).map_err(CoreEnvironmentError::OpenTransactionLock)?;
+        if !lock
+            .try_lock()
+            .map_err(CoreEnvironmentError::AcquireTransactionLock)?
+        {
+            debug!(
+                "transaction: waiting for lock held by another process: {}",
+                lock_path.display()
+            );
+            lock.lock()
+                .map_err(CoreEnvironmentError::AcquireTransactionLock)?;
+        }
+        Ok(Self { _lock: lock })
+    }
+}
+
+/// The state of an environment directory at the start of a transaction
+///
+/// The manifest and lockfile are recorded when the transaction begins,
+/// so that changes made without taking the [EnvironmentLock] (e.g. by a text editor)
+/// are detected by [CoreEnvironment::replace_with] rather than overwritten.
+struct Transaction {
+    manifest: String,
+    lockfile: Option<String>,
+}
+
 /// A view of an environment directory
 /// that can be used to build, link, and edit the environment.
 ///
@@ -70,6 +127,15 @@ impl<State> CoreEnvironment<State> {
         fs::read_to_string(self.manifest_path()).map_err(CoreEnvironmentError::OpenManifest)
     }
 
+    /// Read the lockfile if it exists
+    fn lockfile_content(&self) -> Result<Option<String>, CoreEnvironmentError> {
+        match fs::read_to_string(self.lockfile_path()) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(CoreEnvironmentError::ReadLockfile(err)),
+        }
+    }
+
     /// Lock the environment.
     ///
     /// This re-writes the lock if it exists.
@@ -246,6 +312,47 @@ impl CoreEnvironment<ReadOnly> {
         }
     }
 
+    /// Lock a copy of the environment in a temporary directory
+    ///
+    /// Unlike [Self::lock], this does not write a lockfile to the environment,
+    /// so it can be used to inspect environments whose lockfile
+    /// may be missing or outdated.
+    pub fn lock_detached(&mut self, flox: &Flox) -> Result<LockedManifest, CoreEnvironmentError> {
+        let tempdir =
+            tempfile::tempdir_in(&flox.temp_dir).map_err(CoreEnvironmentError::MakeSandbox)?;
+        self.writable(tempdir.path())?.lock(flox)
+    }
+
+    /// Replace the environment atomically with a copy of `replacement`,
+    /// e.g. a generation of the environment
+    ///
+    /// The copy is built before it replaces the environment,
+    /// so a replacement that does not build leaves the environment unmodified.
+    /// Returns the store path of the built environment.
+    #[must_use = "don't discard the store path of built environments"]
+    pub fn replace_with_copy_of(
+        &mut self,
+        replacement: &mut CoreEnvironment,
+        flox: &Flox,
+    ) -> Result<PathBuf, CoreEnvironmentError> {
+        let transaction = self.begin_transaction()?;
+
+        let tempdir =
+            tempfile::tempdir_in(&flox.temp_dir).map_err(CoreEnvironmentError::MakeSandbox)?;
+        debug!(
+            "transaction: copying replacement to {}",
+            tempdir.path().display()
+        );
+        let mut temp_env = replacement.writable(tempdir.path())?;
+
+        debug!("transaction: building environment");
+        let store_path = temp_env.build(flox)?;
+
+        debug!("transaction: replacing environment");
+        self.replace_with(&transaction, temp_env)?;
+        Ok(store_path)
+    }
+
     /// Install packages to the environment atomically
     ///
     /// Returns the new manifest content if the environment was modified. Also
@@ -257,8 +364,8 @@ impl CoreEnvironment<ReadOnly> {
         packages: &[PackageToInstall],
         flox: &Flox,
     ) -> Result<InstallationAttempt, CoreEnvironmentError> {
-        let current_manifest_contents = self.manifest_content()?;
-        let mut installation = insert_packages(&current_manifest_contents, packages)
+        let transaction = self.begin_transaction()?;
+        let mut installation = insert_packages(&transaction.manifest, packages)
             .map(|insertion| InstallationAttempt {
                 new_manifest: insertion.new_toml.map(|toml| toml.to_string()),
                 already_installed: insertion.already_installed,
@@ -266,7 +373,8 @@ impl CoreEnvironment<ReadOnly> {
             })
             .map_err(CoreEnvironmentError::ModifyToml)?;
         if let Some(ref new_manifest) = installation.new_manifest {
-            let store_path = self.transact_with_manifest_contents(new_manifest, flox)?;
+            let store_path =
+                self.transact_with_manifest_contents(&transaction, new_manifest, flox)?;
             installation.store_path = Some(store_path);
         }
         Ok(installation)
@@ -282,10 +390,11 @@ impl CoreEnvironment<ReadOnly> {
         packages: Vec<String>,
         flox: &Flox,
     ) -> Result<UninstallationAttempt, CoreEnvironmentError> {
-        let current_manifest_contents = self.manifest_content()?;
-        let toml = remove_packages(&current_manifest_contents, &packages)
+        let transaction = self.begin_transaction()?;
+        let toml = remove_packages(&transaction.manifest, &packages)
             .map_err(CoreEnvironmentError::ModifyToml)?;
-        let store_path = self.transact_with_manifest_contents(toml.to_string(), flox)?;
+        let store_path =
+            self.transact_with_manifest_contents(&transaction, toml.to_string(), flox)?;
         Ok(UninstallationAttempt {
             new_manifest: Some(toml.to_string()),
             store_path: Some(store_path),
@@ -298,18 +407,22 @@ impl CoreEnvironment<ReadOnly> {
         flox: &Flox,
         contents: String,
     ) -> Result<EditResult, CoreEnvironmentError> {
-        let old_contents = self.manifest_content()?;
+        let transaction = self.begin_transaction()?;
+        let old_contents = &transaction.manifest;
 
         // skip the edit if the contents are unchanged
         // note: consumers of this function may call [Self::link] separately,
         //       causing an evaluation/build of the environment.
-        if contents == old_contents {
+        if &contents == old_contents {
             return Ok(EditResult::Unchanged);
         }
 
-        let store_path = self.transact_with_manifest_contents(&contents, flox)?;
+        // catch obvious mistakes before attempting a (slow) build
+        Manifest::from_str(&contents).map_err(CoreEnvironmentError::DeserializeManifest)?;
 
-        EditResult::new(&old_contents, &contents, Some(store_path))
+        let store_path = self.transact_with_manifest_contents(&transaction, &contents, flox)?;
+
+        EditResult::new(old_contents, &contents, Some(store_path))
     }
 
     /// Atomically edit this environment, without checking that it still builds
@@ -322,12 +435,13 @@ impl CoreEnvironment<ReadOnly> {
         flox: &Flox,
         contents: String,
     ) -> Result<Result<EditResult, CoreEnvironmentError>, CoreEnvironmentError> {
-        let old_contents = self.manifest_content()?;
+        let transaction = self.begin_transaction()?;
+        let old_contents = &transaction.manifest;
 
         // skip the edit if the contents are unchanged
         // note: consumers of this function may call [Self::link] separately,
         //       causing an evaluation/build of the environment.
-        if contents == old_contents {
+        if &contents == old_contents {
             return Ok(Ok(EditResult::Unchanged));
         }
 
@@ -349,10 +463,10 @@ impl CoreEnvironment<ReadOnly> {
         let build_attempt = temp_env.build(flox);
 
         debug!("transaction: replacing environment");
-        self.replace_with(temp_env)?;
+        self.replace_with(&transaction, temp_env)?;
 
         match build_attempt {
-            Ok(store_path) => Ok(EditResult::new(&old_contents, &contents, Some(store_path))),
+            Ok(store_path) => Ok(EditResult::new(old_contents, &contents, Some(store_path))),
             Err(err) => Ok(Err(err)),
         }
     }
@@ -363,6 +477,7 @@ impl CoreEnvironment<ReadOnly> {
         flox: &Flox,
         inputs: Vec<String>,
     ) -> Result<UpdateResult, CoreEnvironmentError> {
+        let transaction = self.begin_transaction()?;
         // TODO: double check canonicalization
         let UpdateResult {
             new_lockfile,
@@ -377,6 +492,7 @@ impl CoreEnvironment<ReadOnly> {
         .map_err(CoreEnvironmentError::LockedManifest)?;
 
         let store_path = self.transact_with_lockfile_contents(
+            &transaction,
             serde_json::to_string_pretty(&new_lockfile).unwrap(),
             flox,
         )?;
@@ -394,6 +510,7 @@ impl CoreEnvironment<ReadOnly> {
         flox: &Flox,
         groups_or_iids: &[String],
     ) -> Result<UpgradeResult, CoreEnvironmentError> {
+        let transaction = self.begin_transaction()?;
         // TODO double check canonicalization
         let manifest_path = self.manifest_path();
         let lockfile_path = self.lockfile_path();
@@ -428,7 +545,8 @@ impl CoreEnvironment<ReadOnly> {
         )
         .map_err(CoreEnvironmentError::ParseUpgradeOutput)?;
 
-        let store_path = self.transact_with_lockfile_contents(json.lockfile.to_string(), flox)?;
+        let store_path =
+            self.transact_with_lockfile_contents(&transaction, json.lockfile.to_string(), flox)?;
 
         Ok(UpgradeResult {
             packages: json.result.0,
@@ -436,6 +554,17 @@ impl CoreEnvironment<ReadOnly> {
         })
     }
 
+    /// Record the current state of this environment before modifying it
+    ///
+    /// Callers are expected to hold the [EnvironmentLock] of the environment
+    /// for the duration of the transaction.
+    fn begin_transaction(&self) -> Result<Transaction, CoreEnvironmentError> {
+        Ok(Transaction {
+            manifest: self.manifest_content()?,
+            lockfile: self.lockfile_content()?,
+        })
+    }
+
     /// Makes a temporary copy of the environment so modifications to the manifest
     /// can be applied without modifying the original environment.
     fn writable(
@@ -455,10 +584,21 @@ impl CoreEnvironment<ReadOnly> {
     /// with that of another environment.
     ///
     /// This will **not** set any out-links to updated versions of the environment.
+    ///
+    /// Fails if the manifest or lockfile were modified
+    /// since the `transaction` was started.
     fn replace_with(
         &mut self,
+        transaction: &Transaction,
         replacement: CoreEnvironment<ReadWrite>,
     ) -> Result<(), CoreEnvironmentError> {
+        if self.manifest_content()? != transaction.manifest
+            || self.lockfile_content()? != transaction.lockfile
+        {
+            debug!("transaction: environment was modified concurrently");
+            return Err(CoreEnvironmentError::ModifiedDuringTransaction);
+        }
+
         let transaction_backup = self.env_dir.with_extension("tmp");
 
         if transaction_backup.exists() {
@@ -502,6 +642,7 @@ impl CoreEnvironment<ReadOnly> {
     #[must_use = "don't discard the store path of built environments"]
     fn transact_with_manifest_contents(
         &mut self,
+        transaction: &Transaction,
         manifest_contents: impl AsRef<str>,
         flox: &Flox,
     ) -> Result<PathBuf, CoreEnvironmentError> {
@@ -522,7 +663,7 @@ impl CoreEnvironment<ReadOnly> {
         let store_path = temp_env.build(flox)?;
 
         debug!("transaction: replacing environment");
-        self.replace_with(temp_env)?;
+        self.replace_with(transaction, temp_env)?;
         Ok(store_path)
     }
 
@@ -538,6 +679,7 @@ impl CoreEnvironment<ReadOnly> {
     #[must_use = "don't discard the store path of built environments"]
     fn transact_with_lockfile_contents(
         &mut self,
+        transaction: &Transaction,
         lockfile_contents: impl AsRef<str>,
         flox: &Flox,
     ) -> Result<PathBuf, CoreEnvironmentError> {
@@ -558,7 +700,7 @@ impl CoreEnvironment<ReadOnly> {
         let store_path = temp_env.build(flox)?;
 
         debug!("transaction: replacing environment");
-        self.replace_with(temp_env)?;
+        self.replace_with(transaction, temp_env)?;
         Ok(store_path)
     }
 }
@@ -610,13 +752,12 @@ impl EditResult {
         if old_manifest == new_manifest {
             Ok(Self::Unchanged)
         } else {
-            // todo: use a single toml crate (toml_edit already implements serde traits)
             // TODO: use different error variants, users _can_ fix errors in the _new_ manifest
             //       but they _can't_ fix errors in the _old_ manifest
-            let old_manifest: Manifest =
-                toml::from_str(old_manifest).map_err(CoreEnvironmentError::DeserializeManifest)?;
-            let new_manifest: Manifest =
-                toml::from_str(new_manifest).map_err(CoreEnvironmentError::DeserializeManifest)?;
+            let old_manifest = Manifest::from_str(old_manifest)
+                .map_err(CoreEnvironmentError::DeserializeManifest)?;
+            let new_manifest = Manifest::from_str(new_manifest)
+                .map_err(CoreEnvironmentError::DeserializeManifest)?;
             // TODO: some modifications to `install` currently require re-activation
             if old_manifest.hook != new_manifest.hook || old_manifest.vars != new_manifest.vars {
                 Ok(Self::ReActivateRequired { store_path })
@@ -641,7 +782,7 @@ pub enum CoreEnvironmentError {
     #[error("could not modify manifest")]
     ModifyToml(#[source] TomlEditError),
     #[error("could not deserialize manifest")]
-    DeserializeManifest(#[source] toml::de::Error),
+    DeserializeManifest(#[source] ManifestError),
     // endregion
 
     // region: transaction errors
@@ -650,6 +791,16 @@ pub enum CoreEnvironmentError {
 
     #[error("couldn't write new lockfile contents")]
     WriteLockfile(#[source] std::io::Error),
+    #[error("couldn't read lockfile")]
+    ReadLockfile(#[source] std::io::Error),
+
+    #[error("could not open transaction lock")]
+    OpenTransactionLock(#[source] std::io::Error),
+    #[error("could not acquire transaction lock")]
+    AcquireTransactionLock(#[source] std::io::Error),
+    /// Thrown when the manifest or lockfile changed while a transaction was building
+    #[error("the environment was modified while this change was being applied -- retry to apply it to the latest version")]
+    ModifiedDuringTransaction,
 
     #[error("could not make temporary copy of environment")]
     MakeTemporaryEnv(#[source] std::io::Error),
@@ -791,16 +942,86 @@ mod tests {
         let sandbox_path = tempfile::tempdir_in(&tempdir).unwrap();
         fs::create_dir(env_path.path().with_extension("tmp")).unwrap();
 
+        fs::write(env_path.path().join(MANIFEST_FILENAME), "").unwrap();
+
         let mut env_view = CoreEnvironment::new(&env_path);
+        let transaction = env_view.begin_transaction().unwrap();
         let temp_env = env_view.writable(&sandbox_path).unwrap();
 
         let err = env_view
-            .replace_with(temp_env)
+            .replace_with(&transaction, temp_env)
             .expect_err("Should fail if backup exists");
 
         assert!(matches!(err, CoreEnvironmentError::PriorTransaction(_)));
     }
 
+    /// replacing an environment should fail if its manifest changed
+    /// after the transaction was started
+    #[test]
+    fn detects_concurrent_modification() {
+        let (_flox, tempdir) = flox_instance();
+
+        let env_path = tempfile::tempdir_in(&tempdir).unwrap();
+        let sandbox_path = tempfile::tempdir_in(&tempdir).unwrap();
+        fs::write(env_path.path().join(MANIFEST_FILENAME), "").unwrap();
+
+        let mut env_view = CoreEnvironment::new(&env_path);
+        let transaction = env_view.begin_transaction().unwrap();
+        let mut temp_env = env_view.writable(&sandbox_path).unwrap();
+        temp_env.update_manifest(&"# from transaction").unwrap();
+
+        fs::write(env_path.path().join(MANIFEST_FILENAME), "# from editor").unwrap();
+
+        let err = env_view
+            .replace_with(&transaction, temp_env)
+            .expect_err("Should fail if manifest changed");
+        assert!(matches!(
+            err,
+            CoreEnvironmentError::ModifiedDuringTransaction
+        ));
+        assert_eq!(env_view.manifest_content().unwrap(), "# from editor");
+    }
+
+    /// replacing an environment doesn't leave files of the previous state behind,
+    /// e.g. a lockfile when switching to a generation without one
+    #[test]
+    fn replace_removes_stale_files() {
+        let (_flox, tempdir) = flox_instance();
+
+        let env_path = tempfile::tempdir_in(&tempdir).unwrap();
+        let replacement_path = tempfile::tempdir_in(&tempdir).unwrap();
+        let sandbox_path = tempfile::tempdir_in(&tempdir).unwrap();
+        fs::write(env_path.path().join(MANIFEST_FILENAME), "# current").unwrap();
+        fs::write(env_path.path().join(LOCKFILE_FILENAME), "{}").unwrap();
+        fs::write(replacement_path.path().join(MANIFEST_FILENAME), "# old").unwrap();
+
+        let mut env_view = CoreEnvironment::new(&env_path);
+        let transaction = env_view.begin_transaction().unwrap();
+        let temp_env = CoreEnvironment::new(&replacement_path)
+            .writable(&sandbox_path)
+            .unwrap();
+        env_view.replace_with(&transaction, temp_env).unwrap();
+
+        assert_eq!(env_view.manifest_content().unwrap(), "# old");
+        assert!(!env_view.lockfile_path().exists());
+    }
+
+    /// only one process can hold the lock of an environment at a time
+    #[test]
+    fn environment_lock_is_exclusive() {
+        let (_flox, tempdir) = flox_instance();
+
+        let dot_flox_path = tempfile::tempdir_in(&tempdir).unwrap();
+        let lock = EnvironmentLock::acquire(&dot_flox_path).unwrap();
+
+        let lock_path = dot_flox_path.path().join("env.transaction.lock");
+        let mut other = LockFile::open(&lock_path).unwrap();
+        assert!(!other.try_lock().unwrap());
+
+        drop(lock);
+        assert!(other.try_lock().unwrap());
+    }
+
     /// creating backup should fail if env is readonly
     #[test]
     #[ignore = "On Ubuntu github runners this moving a read only directory succeeds.
@@ -810,6 +1031,7 @@ mod tests {
 
         let env_path = tempfile::tempdir_in(&tempdir).unwrap();
         let sandbox_path = tempfile::tempdir_in(&tempdir).unwrap();
+        fs::write(env_path.path().join(MANIFEST_FILENAME), "").unwrap();
 
         let mut env_path_permissions = fs::metadata(env_path.path()).unwrap().permissions();
         env_path_permissions.set_readonly(true);
@@ -818,12 +1040,15 @@ mod tests {
         fs::set_permissions(&env_path, env_path_permissions.clone()).unwrap();
 
         let mut env_view = CoreEnvironment::new(&env_path);
+        let transaction = env_view.begin_transaction().unwrap();
         let temp_env = env_view.writable(&sandbox_path).unwrap();
 
-        let err = env_view.replace_with(temp_env).expect_err(&format!(
-            "Should fail to create backup: dir is readonly: {:o}",
-            env_path_permissions.mode()
-        ));
+        let err = env_view
+            .replace_with(&transaction, temp_env)
+            .expect_err(&format!(
+                "Should fail to create backup: dir is readonly: {:o}",
+                env_path_permissions.mode()
+            ));
 
         assert!(
             matches!(err, CoreEnvironmentError::BackupTransaction(err) if err.kind() == std::io::ErrorKind::PermissionDenied)
@@ -30,6 +30,7 @@ use super::core_environment::CoreEnvironment;
 use super::{copy_dir_recursive, PathPointer, ENV_DIR_NAME};
 use crate::data::Version;
 use crate::models::environment::MANIFEST_FILENAME;
+use crate::models::lockfile::LockedManifest;
 use crate::providers::git::{
     GitCommandError,
     GitCommandOptions,
@@ -56,11 +57,16 @@ pub struct ReadOnly {}
 /// Mutating commands are commited and pushed to the [ReadOnly] branch.
 ///
 /// Instances of this type are created using [Generations::writable].
+/// Local generations without an upstream [ReadOnly] repo,
+/// created using [Generations::init_local], only commit changes.
 ///
 /// Todo: rename to `CheckedOut`, `Filesystem`, ...?
 ///
 /// /// See also: [ReadOnly]
-pub struct ReadWrite {}
+pub struct ReadWrite {
+    /// Whether commits are pushed to the `origin` the branch was cloned from
+    push_to_origin: bool,
+}
 
 /// A representation of the generations of an environment
 ///
@@ -83,8 +89,9 @@ pub struct Generations<State = ReadOnly> {
     /// The state of the generations view
     ///
     /// Should remain private to enforce the invariant that [ReadWrite]
-    /// always refers to a cloned branch of a [ReadOnly] instance.
-    _state: State,
+    /// always refers to a cloned branch of a [ReadOnly] instance,
+    /// or a local checkout created by [Generations::init_local].
+    state: State,
 }
 
 impl<S> Generations<S> {
@@ -131,7 +138,7 @@ impl Generations<ReadOnly> {
         Self {
             repo,
             branch,
-            _state: ReadOnly {},
+            state: ReadOnly {},
         }
     }
 
@@ -146,21 +153,7 @@ impl Generations<ReadOnly> {
         branch: String,
         pointer: &PathPointer,
     ) -> Result<Self, GenerationsError> {
-        let repo = GitCommandProvider::init_with(options.clone(), &checkedout_tempdir, false)
-            .map_err(GenerationsError::InitRepo)?;
-        repo.checkout(&branch, true)
-            .map_err(GenerationsError::CreateBranch)?;
-
-        let metadata = AllGenerationsMetadata::default();
-        write_metadata_file(metadata, repo.path())?;
-
-        repo.add(&[Path::new(GENERATIONS_METADATA_FILE)])
-            .map_err(GenerationsError::StageChanges)?;
-        repo.commit(&format!(
-            "Initialize generations branch for environment '{}'",
-            pointer.name
-        ))
-        .map_err(GenerationsError::CommitChanges)?;
+        init_branch(options.clone(), &checkedout_tempdir, &branch, pointer)?;
 
         let bare = GitCommandProvider::clone_branch_with(
             options,
@@ -189,12 +182,41 @@ impl Generations<ReadOnly> {
         Ok(Generations {
             repo,
             branch: self.branch,
-            _state: ReadWrite {},
+            state: ReadWrite {
+                push_to_origin: true,
+            },
         })
     }
 }
 
 impl Generations<ReadWrite> {
+    /// Initialize a new generations branch in a local, checked out repository
+    ///
+    /// Unlike [Generations::init], the repository is not cloned into a bare repository.
+    /// Changes are committed to the checked out branch directly,
+    /// so that modifying the generations doesn't require a clone of the repository.
+    pub fn init_local(
+        options: GitCommandOptions,
+        path: impl AsRef<Path>,
+        branch: String,
+        pointer: &PathPointer,
+    ) -> Result<Self, GenerationsError> {
+        fs::create_dir_all(&path).map_err(GenerationsError::CreateRepoDir)?;
+        let repo = init_branch(options, path, &branch, pointer)?;
+        Ok(Self::open_local(repo, branch))
+    }
+
+    /// Open generations in a local repository created by [Generations::init_local]
+    pub fn open_local(repo: GitCommandProvider, branch: String) -> Self {
+        Self {
+            repo,
+            branch,
+            state: ReadWrite {
+                push_to_origin: false,
+            },
+        }
+    }
+
     /// Return a mutable [CoreEnvironment] instance for a given generation
     /// contained in the generations branch.
     ///
@@ -208,6 +230,14 @@ impl Generations<ReadWrite> {
     ///   When a generation needs to be used again after being modified,
     ///   it is recommended to create a new [Generations<ReadWrite>] instance first.
     pub fn get_generation(&self, generation: usize) -> Result<CoreEnvironment, GenerationsError> {
+        if !self
+            .metadata()?
+            .generations
+            .contains_key(&generation.into())
+        {
+            return Err(GenerationsError::GenerationNotFound(generation));
+        }
+
         let environment = CoreEnvironment::new(
             self.repo
                 .path()
@@ -231,6 +261,35 @@ impl Generations<ReadWrite> {
         self.get_generation(*current_gen)
     }
 
+    /// Read the lockfiles of all generations
+    ///
+    /// Generations that were never locked map to `None`.
+    pub fn lockfiles(
+        &self,
+    ) -> Result<BTreeMap<GenerationId, Option<LockedManifest>>, GenerationsError> {
+        let mut lockfiles = BTreeMap::new();
+        for id in self.metadata()?.generations.into_keys() {
+            let lockfile = self.lockfile(*id)?;
+            lockfiles.insert(id, lockfile);
+        }
+        Ok(lockfiles)
+    }
+
+    /// Read the lockfile of a given generation
+    ///
+    /// Returns `None` if the generation was never locked.
+    pub fn lockfile(&self, generation: usize) -> Result<Option<LockedManifest>, GenerationsError> {
+        let lockfile_path = self.get_generation(generation)?.lockfile_path();
+        if !lockfile_path.exists() {
+            return Ok(None);
+        }
+
+        let contents = fs::read(lockfile_path).map_err(GenerationsError::ReadLockfile)?;
+        let lockfile =
+            serde_json::from_slice(&contents).map_err(GenerationsError::ParseLockfile)?;
+        Ok(Some(lockfile))
+    }
+
     /// Import an existing environment into a generation
     ///
     /// Assumes the invariant that the [CoreEnvironment] instance is valid.
@@ -285,9 +344,7 @@ impl Generations<ReadWrite> {
                 generation, description
             ))
             .map_err(GenerationsError::CommitChanges)?;
-        self.repo
-            .push("origin", false)
-            .map_err(GenerationsError::CompleteTransaction)?;
+        self.complete_transaction()?;
 
         Ok(())
     }
@@ -325,15 +382,16 @@ impl Generations<ReadWrite> {
     ///
     /// This method will not perform any validation of the generation switched to.
     /// If validation (e.g. proving that the environment builds) is required,
-    /// it should first be realized using [Self::realize_generation].
-    #[allow(unused)]
-    fn set_current_generation(&mut self, generation: usize) -> Result<(), GenerationsError> {
+    /// it should first be realized using [Self::get_generation].
+    ///
+    /// Switching to a generation updates its [SingleGenerationMetadata::last_active] timestamp.
+    pub fn set_current_generation(&mut self, generation: usize) -> Result<(), GenerationsError> {
         let mut metadata = self.metadata()?;
 
-        let generation_metadata = metadata.generations.contains_key(&generation.into());
-        if !generation_metadata {
+        let Some(generation_metadata) = metadata.generations.get_mut(&generation.into()) else {
             return Err(GenerationsError::GenerationNotFound(generation));
-        }
+        };
+        generation_metadata.last_active = Some(Utc::now());
 
         metadata.current_gen = Some(generation.into());
 
@@ -341,19 +399,120 @@ impl Generations<ReadWrite> {
 
         self.repo
             .add(&[Path::new(GENERATIONS_METADATA_FILE)])
-            .unwrap();
+            .map_err(GenerationsError::StageChanges)?;
         self.repo
             .commit(&format!("Set current generation to {}", generation))
-            .unwrap();
-        self.repo.push("origin", false).unwrap();
+            .map_err(GenerationsError::CommitChanges)?;
+        self.complete_transaction()?;
+
+        Ok(())
+    }
+
+    /// Delete generations from the generations branch
+    ///
+    /// Removes both the generation folders and their metadata.
+    /// The current generation can not be removed.
+    pub fn remove_generations(
+        &mut self,
+        generations: &[GenerationId],
+    ) -> Result<(), GenerationsError> {
+        if generations.is_empty() {
+            return Ok(());
+        }
+
+        let mut metadata = self.metadata()?;
+        for generation in generations {
+            if metadata.current_gen.as_ref() == Some(generation) {
+                return Err(GenerationsError::RemoveCurrentGeneration(**generation));
+            }
+            if metadata.generations.remove(generation).is_none() {
+                return Err(GenerationsError::GenerationNotFound(**generation));
+            }
+        }
+
+        write_metadata_file(metadata, self.repo.path())?;
+
+        let mut paths = vec![PathBuf::from(GENERATIONS_METADATA_FILE)];
+        for generation in generations {
+            let generation_path = self.repo.path().join(generation.to_string());
+            fs::remove_dir_all(&generation_path).map_err(GenerationsError::RemoveGeneration)?;
+            paths.push(generation_path);
+        }
+
+        self.repo
+            .add(&paths.iter().map(PathBuf::as_path).collect::<Vec<_>>())
+            .map_err(GenerationsError::StageChanges)?;
+        self.repo
+            .commit(&format!(
+                "Remove generations {}",
+                generations
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+            .map_err(GenerationsError::CommitChanges)?;
+        self.complete_transaction()?;
 
         Ok(())
     }
+
+    /// Copy all generations of `other` into this generations branch
+    ///
+    /// Generation numbers, their metadata and the current generation are preserved.
+    /// Existing generations with the same numbers are overwritten.
+    pub fn import_generations(
+        &mut self,
+        other: &Generations<ReadWrite>,
+    ) -> Result<(), GenerationsError> {
+        let imported = other.metadata()?;
+        let mut metadata = self.metadata()?;
+
+        let mut paths = vec![PathBuf::from(GENERATIONS_METADATA_FILE)];
+        for (generation, generation_metadata) in imported.generations {
+            let generation_path = self.repo.path().join(generation.to_string());
+            copy_dir_recursive(
+                &other.repo.path().join(generation.to_string()),
+                &generation_path,
+                true,
+            )
+            .map_err(GenerationsError::WriteManifest)?;
+            metadata.generations.insert(generation, generation_metadata);
+            paths.push(generation_path);
+        }
+        metadata.current_gen = imported.current_gen.or(metadata.current_gen);
+
+        write_metadata_file(metadata, self.repo.path())?;
+
+        self.repo
+            .add(&paths.iter().map(PathBuf::as_path).collect::<Vec<_>>())
+            .map_err(GenerationsError::StageChanges)?;
+        self.repo
+            .commit("Import generations")
+            .map_err(GenerationsError::CommitChanges)?;
+        self.complete_transaction()?;
+
+        Ok(())
+    }
+
+    /// Push committed changes to the [ReadOnly] branch this branch was cloned from
+    ///
+    /// Local generations don't have an upstream to push to.
+    fn complete_transaction(&self) -> Result<(), GenerationsError> {
+        if !self.state.push_to_origin {
+            return Ok(());
+        }
+        self.repo
+            .push("origin", false)
+            .map_err(GenerationsError::CompleteTransaction)
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum GenerationsError {
     // region: initialization errors
+    #[error("could not create generations repo directory")]
+    CreateRepoDir(#[source] std::io::Error),
     #[error("could not initialize generations repo")]
     InitRepo(#[source] GitCommandError),
     #[error("could not create generations branch")]
@@ -378,6 +537,14 @@ pub enum GenerationsError {
     // region: generation errors
     #[error("generation {0} not found")]
     GenerationNotFound(usize),
+    #[error("cannot remove current generation {0}")]
+    RemoveCurrentGeneration(usize),
+    #[error("could not remove generation")]
+    RemoveGeneration(#[source] std::io::Error),
+    #[error("could not read lockfile of generation")]
+    ReadLockfile(#[source] std::io::Error),
+    #[error("could not parse lockfile of generation")]
+    ParseLockfile(#[source] serde_json::Error),
     #[error("no generations found in environment")]
     NoGenerations,
     // endregion
@@ -401,6 +568,33 @@ pub enum GenerationsError {
     // endregion
 }
 
+/// Create a repository at `path` with a generations branch
+/// containing an initial metadata file
+fn init_branch(
+    options: GitCommandOptions,
+    path: impl AsRef<Path>,
+    branch: &str,
+    pointer: &PathPointer,
+) -> Result<GitCommandProvider, GenerationsError> {
+    let repo =
+        GitCommandProvider::init_with(options, &path, false).map_err(GenerationsError::InitRepo)?;
+    repo.checkout(branch, true)
+        .map_err(GenerationsError::CreateBranch)?;
+
+    let metadata = AllGenerationsMetadata::default();
+    write_metadata_file(metadata, repo.path())?;
+
+    repo.add(&[Path::new(GENERATIONS_METADATA_FILE)])
+        .map_err(GenerationsError::StageChanges)?;
+    repo.commit(&format!(
+        "Initialize generations branch for environment '{}'",
+        pointer.name
+    ))
+    .map_err(GenerationsError::CommitChanges)?;
+
+    Ok(repo)
+}
+
 /// Realize the generations branch into a temporary directory
 fn checkout_to_tempdir(
     repo: &GitCommandProvider,
@@ -465,6 +659,46 @@ pub struct AllGenerationsMetadata {
     version: Version<1>,
 }
 
+impl AllGenerationsMetadata {
+    /// Select the generations to remove when wiping the history
+    ///
+    /// A generation is kept if it is the current or the most recent generation,
+    /// one of the `keep` most recent generations,
+    /// or if it was created after `newer_than`.
+    /// All other generations are returned, oldest first.
+    pub fn wipe_candidates(
+        &self,
+        keep: Option<usize>,
+        newer_than: Option<DateTime<Utc>>,
+    ) -> Vec<GenerationId> {
+        let keep = keep.unwrap_or_default().max(1);
+        self.generations
+            .iter()
+            .rev()
+            .skip(keep)
+            .filter(|(id, _)| self.current_gen.as_ref() != Some(*id))
+            .filter(|(_, generation)| match newer_than {
+                Some(newer_than) => generation.created <= newer_than,
+                None => true,
+            })
+            .map(|(id, _)| id.clone())
+            .rev()
+            .collect()
+    }
+
+    /// The generation preceding the current generation, if any
+    ///
+    /// This is the generation with the highest number lower than
+    /// the current generation, which is the target of a rollback.
+    pub fn previous_generation(&self) -> Option<&GenerationId> {
+        let current_gen = self.current_gen.as_ref()?;
+        self.generations
+            .range(..current_gen)
+            .next_back()
+            .map(|(id, _)| id)
+    }
+}
+
 /// Metadata for a single generation of an environment
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -517,6 +751,175 @@ pub struct GenerationId(usize);
 
 #[cfg(test)]
 mod tests {
-    // todo: tests for this will be easier with the `init` method implemented
-    // in https://github.com/flox/flox/pull/563
+    use std::str::FromStr;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::flox::EnvironmentName;
+
+    /// Create a generations branch with `count` generations,
+    /// the last one being the current generation
+    fn generations_with(tempdir: &TempDir, count: usize) -> Generations<ReadWrite> {
+        let generations = Generations::init(
+            GitCommandOptions::default(),
+            tempfile::tempdir_in(tempdir).unwrap().into_path(),
+            tempfile::tempdir_in(tempdir).unwrap().into_path(),
+            "generations".to_string(),
+            &PathPointer::new(EnvironmentName::from_str("name").unwrap()),
+        )
+        .unwrap();
+        let mut generations = generations.writable(tempdir.path()).unwrap();
+
+        let env_path = tempfile::tempdir_in(tempdir).unwrap().into_path();
+        for n in 1..=count {
+            fs::write(env_path.join(MANIFEST_FILENAME), format!("# {n}")).unwrap();
+            generations
+                .add_generation(&mut CoreEnvironment::new(&env_path), format!("gen {n}"))
+                .unwrap();
+        }
+        generations
+    }
+
+    #[test]
+    fn set_current_generation_updates_metadata() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut generations = generations_with(&tempdir, 2);
+
+        let before = generations.metadata().unwrap();
+        assert_eq!(before.current_gen, Some(2.into()));
+        assert!(before.generations[&1.into()].last_active.is_some());
+
+        generations.set_current_generation(1).unwrap();
+
+        let after = generations.metadata().unwrap();
+        assert_eq!(after.current_gen, Some(1.into()));
+        assert!(
+            after.generations[&1.into()].last_active >= before.generations[&1.into()].last_active
+        );
+        let current = generations.get_current_generation().unwrap();
+        assert_eq!(fs::read_to_string(current.manifest_path()).unwrap(), "# 1");
+    }
+
+    #[test]
+    fn set_current_generation_fails_for_missing_generation() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut generations = generations_with(&tempdir, 1);
+
+        let err = generations.set_current_generation(5).unwrap_err();
+        assert!(matches!(err, GenerationsError::GenerationNotFound(5)));
+        assert_eq!(generations.metadata().unwrap().current_gen, Some(1.into()));
+    }
+
+    #[test]
+    fn previous_generation_is_the_one_before_current() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut generations = generations_with(&tempdir, 3);
+
+        assert_eq!(
+            generations.metadata().unwrap().previous_generation(),
+            Some(&2.into())
+        );
+
+        generations.set_current_generation(1).unwrap();
+        assert_eq!(generations.metadata().unwrap().previous_generation(), None);
+    }
+
+    #[test]
+    fn remove_generations_deletes_folders_and_metadata() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut generations = generations_with(&tempdir, 3);
+
+        generations
+            .remove_generations(&[1.into(), 2.into()])
+            .unwrap();
+
+        let metadata = generations.metadata().unwrap();
+        assert_eq!(metadata.generations.keys().collect::<Vec<_>>(), vec![
+            &3.into()
+        ]);
+        assert!(!generations.git().path().join("1").exists());
+        assert!(matches!(
+            generations.manifest(1),
+            Err(GenerationsError::GenerationNotFound(1))
+        ));
+    }
+
+    #[test]
+    fn remove_generations_keeps_current_generation() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut generations = generations_with(&tempdir, 2);
+
+        let err = generations.remove_generations(&[2.into()]).unwrap_err();
+        assert!(matches!(err, GenerationsError::RemoveCurrentGeneration(2)));
+        assert_eq!(generations.metadata().unwrap().generations.len(), 2);
+    }
+
+    #[test]
+    fn wipe_candidates_by_count_and_age() {
+        let now = Utc::now();
+        let mut metadata = AllGenerationsMetadata {
+            current_gen: Some(2.into()),
+            ..Default::default()
+        };
+        for (id, days_ago) in [(1, 40), (2, 30), (3, 20), (4, 10), (5, 0)] {
+            metadata
+                .generations
+                .insert(id.into(), SingleGenerationMetadata {
+                    created: now - chrono::Duration::days(days_ago),
+                    last_active: None,
+                    description: format!("gen {id}"),
+                });
+        }
+
+        // the current generation 2 is always kept
+        assert_eq!(metadata.wipe_candidates(Some(2), None), vec![
+            1.into(),
+            3.into()
+        ]);
+        // the most recent generation is always kept
+        assert_eq!(metadata.wipe_candidates(Some(0), None), vec![
+            1.into(),
+            3.into(),
+            4.into()
+        ]);
+        assert_eq!(
+            metadata.wipe_candidates(None, Some(now - chrono::Duration::days(15))),
+            vec![1.into(), 3.into()]
+        );
+        // generations are kept if either criterion applies
+        assert_eq!(
+            metadata.wipe_candidates(Some(1), Some(now - chrono::Duration::days(25))),
+            vec![1.into()]
+        );
+    }
+
+    #[test]
+    fn import_local_generations() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let pointer = PathPointer::new(EnvironmentName::from_str("name").unwrap());
+
+        let mut local = Generations::init_local(
+            GitCommandOptions::default(),
+            tempfile::tempdir_in(&tempdir).unwrap().into_path(),
+            "generations".to_string(),
+            &pointer,
+        )
+        .unwrap();
+        let env_path = tempfile::tempdir_in(&tempdir).unwrap().into_path();
+        for n in 1..=2 {
+            fs::write(env_path.join(MANIFEST_FILENAME), format!("# {n}")).unwrap();
+            local
+                .add_generation(&mut CoreEnvironment::new(&env_path), format!("gen {n}"))
+                .unwrap();
+        }
+
+        let mut generations = generations_with(&tempdir, 0);
+        generations.import_generations(&local).unwrap();
+
+        let metadata = generations.metadata().unwrap();
+        assert_eq!(metadata.current_gen, Some(2.into()));
+        assert_eq!(metadata.generations[&1.into()].description, "gen 1");
+        assert_eq!(generations.manifest(2).unwrap(), "# 2");
+    }
 }
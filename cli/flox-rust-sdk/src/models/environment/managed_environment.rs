@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::os::unix::prelude::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::{fs, io};
@@ -6,8 +7,14 @@ use log::debug;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use super::core_environment::CoreEnvironment;
-use super::generations::{Generations, GenerationsError};
+use super::core_environment::{CoreEnvironment, EnvironmentLock};
+use super::generations::{
+    AllGenerationsMetadata,
+    GenerationId,
+    Generations,
+    GenerationsError,
+    ReadWrite,
+};
 use super::path_environment::PathEnvironment;
 use super::{
     gcroots_dir,
@@ -23,6 +30,7 @@ use super::{
     UpdateResult,
     CACHE_DIR_NAME,
     ENVIRONMENT_POINTER_FILENAME,
+    GENERATIONS_DIR_NAME,
 };
 use crate::data::Version;
 use crate::flox::{EnvironmentRef, Flox};
@@ -35,6 +43,7 @@ use crate::models::pkgdb::UpgradeResult;
 use crate::providers::git::{
     GitCommandBranchHashError,
     GitCommandError,
+    GitCommandOpenError,
     GitProvider,
     GitRemoteCommandError,
 };
@@ -129,10 +138,34 @@ pub enum ManagedEnvironmentError {
 
     #[error("could not build environment")]
     Build(#[source] CoreEnvironmentError),
+    #[error("could not lock environment")]
+    Lock(#[source] CoreEnvironmentError),
+
+    #[error("could not open local history of environment")]
+    OpenLocalGenerations(#[source] GitCommandOpenError),
+    #[error("could not import local history of environment")]
+    ImportLocalGenerations(#[source] GenerationsError),
+    #[error("could not delete local history of environment")]
+    DeleteLocalGenerations(#[source] std::io::Error),
 
     #[error("could not read manifest")]
     ReadManifest(#[source] GenerationsError),
 
+    #[error("could not read generations metadata")]
+    ReadGenerationsMetadata(#[source] GenerationsError),
+
+    #[error("could not switch generation")]
+    SwitchGeneration(#[source] GenerationsError),
+
+    #[error("could not read lockfiles of generations")]
+    ReadGenerationLockfiles(#[source] GenerationsError),
+
+    #[error("could not wipe history")]
+    WipeHistory(#[source] GenerationsError),
+
+    #[error("could not read links directory {0:?}")]
+    ReadLinksDir(PathBuf, #[source] std::io::Error),
+
     #[error("could not canonicalize environment path")]
     CanonicalizePath(#[source] CanonicalizeError),
 
@@ -173,7 +206,7 @@ impl Environment for ManagedEnvironment {
             .map_err(ManagedEnvironmentError::CreateGenerationFiles)?;
 
         let store_path = temporary.build(flox)?;
-        temporary.link(flox, &self.out_link, &Some(store_path))?;
+        self.link_current_generation(flox, &generations, &mut temporary, &Some(store_path))?;
 
         Ok(())
     }
@@ -209,6 +242,7 @@ impl Environment for ManagedEnvironment {
         packages: &[PackageToInstall],
         flox: &Flox,
     ) -> Result<InstallationAttempt, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut generations = self
             .generations()
             .writable(flox.temp_dir.clone())
@@ -224,7 +258,7 @@ impl Environment for ManagedEnvironment {
             .add_generation(&mut temporary, metadata)
             .map_err(ManagedEnvironmentError::CommitGeneration)?;
         self.lock_pointer()?;
-        temporary.link(flox, &self.out_link, &result.store_path)?;
+        self.link_current_generation(flox, &generations, &mut temporary, &result.store_path)?;
 
         Ok(result)
     }
@@ -235,6 +269,7 @@ impl Environment for ManagedEnvironment {
         packages: Vec<String>,
         flox: &Flox,
     ) -> Result<UninstallationAttempt, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut generations = self
             .generations()
             .writable(flox.temp_dir.clone())
@@ -250,13 +285,14 @@ impl Environment for ManagedEnvironment {
             .add_generation(&mut temporary, metadata)
             .map_err(ManagedEnvironmentError::CommitGeneration)?;
         self.lock_pointer()?;
-        temporary.link(flox, &self.out_link, &result.store_path)?;
+        self.link_current_generation(flox, &generations, &mut temporary, &result.store_path)?;
 
         Ok(result)
     }
 
     /// Atomically edit this environment, ensuring that it still builds
     fn edit(&mut self, flox: &Flox, contents: String) -> Result<EditResult, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut generations = self
             .generations()
             .writable(flox.temp_dir.clone())
@@ -279,7 +315,7 @@ impl Environment for ManagedEnvironment {
             .add_generation(&mut temporary, "manually edited".to_string())
             .map_err(ManagedEnvironmentError::CommitGeneration)?;
         self.lock_pointer()?;
-        temporary.link(flox, &self.out_link, &store_path)?;
+        self.link_current_generation(flox, &generations, &mut temporary, &store_path)?;
 
         Ok(result)
     }
@@ -290,6 +326,7 @@ impl Environment for ManagedEnvironment {
         flox: &Flox,
         inputs: Vec<String>,
     ) -> Result<UpdateResult, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut generations = self
             .generations()
             .writable(flox.temp_dir.clone())
@@ -308,7 +345,7 @@ impl Environment for ManagedEnvironment {
             .add_generation(&mut temporary, metadata)
             .map_err(ManagedEnvironmentError::CommitGeneration)?;
         self.lock_pointer()?;
-        temporary.link(flox, &self.out_link, &result.store_path)?;
+        self.link_current_generation(flox, &generations, &mut temporary, &result.store_path)?;
 
         Ok(result)
     }
@@ -319,6 +356,7 @@ impl Environment for ManagedEnvironment {
         flox: &Flox,
         groups_or_iids: &[String],
     ) -> Result<UpgradeResult, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut generations = self
             .generations()
             .writable(flox.temp_dir.clone())
@@ -424,6 +462,11 @@ impl Environment for ManagedEnvironment {
             .delete_branch(&branch_name(&self.pointer, &self.path), true)
             .map_err(ManagedEnvironmentError::DeleteBranch)?;
 
+        for (_, link) in all_generation_links(&self.out_link)? {
+            fs::remove_file(&link)
+                .map_err(|e| ManagedEnvironmentError::DeleteEnvironmentLink(link, e))?;
+        }
+
         let out_link_path = self.out_link;
         if out_link_path.exists() {
             std::fs::remove_file(&out_link_path)
@@ -753,6 +796,7 @@ impl ManagedEnvironment {
         flox: &Flox,
         contents: String,
     ) -> Result<Result<EditResult, CoreEnvironmentError>, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut generations = self
             .generations()
             .writable(flox.temp_dir.clone())
@@ -779,6 +823,110 @@ impl ManagedEnvironment {
         Ok(result)
     }
 
+    /// Switch the environment to an existing generation
+    ///
+    /// The generation is built before it is set as the current generation,
+    /// so switching to a generation that does not build on this system fails
+    /// without modifying the environment.
+    pub fn switch_generation(
+        &mut self,
+        flox: &Flox,
+        generation: usize,
+    ) -> Result<(), EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
+        let mut generations = self
+            .generations()
+            .writable(flox.temp_dir.clone())
+            .map_err(ManagedEnvironmentError::CreateFloxmetaDir)?;
+        let mut temporary = generations
+            .get_generation(generation)
+            .map_err(ManagedEnvironmentError::SwitchGeneration)?;
+
+        let store_path = temporary
+            .build(flox)
+            .map_err(ManagedEnvironmentError::Build)?;
+
+        generations
+            .set_current_generation(generation)
+            .map_err(ManagedEnvironmentError::SwitchGeneration)?;
+        self.lock_pointer()?;
+        self.link_current_generation(flox, &generations, &mut temporary, &Some(store_path))?;
+
+        Ok(())
+    }
+
+    /// Remove generations from the history of this environment
+    ///
+    /// See [Generations::remove_generations].
+    /// Also removes the [generation_link]s of the removed generations,
+    /// so that nix can garbage collect their builds.
+    /// Returns the removed links.
+    pub fn wipe_history(
+        &mut self,
+        flox: &Flox,
+        generations: &[GenerationId],
+    ) -> Result<Vec<PathBuf>, ManagedEnvironmentError> {
+        let _lock = EnvironmentLock::acquire(&self.path).map_err(ManagedEnvironmentError::Lock)?;
+        self.generations()
+            .writable(flox.temp_dir.clone())
+            .map_err(ManagedEnvironmentError::CreateFloxmetaDir)?
+            .remove_generations(generations)
+            .map_err(ManagedEnvironmentError::WipeHistory)?;
+        self.lock_pointer()?;
+        remove_generation_links(&self.out_link, generations)
+    }
+
+    /// The existing [generation_link]s of the given generations of this environment
+    pub fn generation_links(&self, generations: &[GenerationId]) -> Vec<PathBuf> {
+        existing_generation_links(&self.out_link, generations)
+    }
+
+    /// Link the build of the current generation to the out link of the environment
+    /// and to the [generation_link] of the current generation
+    ///
+    /// Generation links keep the builds of the [PINNED_GENERATIONS] most recent
+    /// generations alive, e.g. for a quick rollback.
+    /// Links of older generations are removed,
+    /// so that nix can garbage collect their builds.
+    fn link_current_generation(
+        &self,
+        flox: &Flox,
+        generations: &Generations<ReadWrite>,
+        temporary: &mut CoreEnvironment,
+        store_path: &Option<PathBuf>,
+    ) -> Result<(), EnvironmentError2> {
+        temporary.link(flox, &self.out_link, store_path)?;
+
+        let metadata = generations
+            .metadata()
+            .map_err(ManagedEnvironmentError::ReadGenerationsMetadata)?;
+        let Some(current_gen) = metadata.current_gen else {
+            return Ok(());
+        };
+        temporary.link(
+            flox,
+            generation_link(&self.out_link, &current_gen),
+            store_path,
+        )?;
+
+        let pinned = metadata
+            .generations
+            .keys()
+            .rev()
+            .take(PINNED_GENERATIONS)
+            .chain([&current_gen])
+            .collect::<Vec<_>>();
+        for (generation, link) in all_generation_links(&self.out_link)? {
+            if pinned.contains(&&generation) {
+                continue;
+            }
+            fs::remove_file(&link)
+                .map_err(|e| ManagedEnvironmentError::DeleteEnvironmentLink(link, e))?;
+        }
+
+        Ok(())
+    }
+
     /// Lock the environment to the current revision
     fn lock_pointer(&self) -> Result<(), ManagedEnvironmentError> {
         let lock_path = self.path.join(GENERATION_LOCK_FILENAME);
@@ -810,6 +958,58 @@ impl ManagedEnvironment {
         )
     }
 
+    /// Read the metadata of all generations of this environment
+    ///
+    /// The metadata is read from the environment's branch in floxmeta
+    /// and reflects the local state of the environment,
+    /// i.e. it may include generations that have not yet been pushed.
+    pub fn generations_metadata(&self) -> Result<AllGenerationsMetadata, ManagedEnvironmentError> {
+        self.generations()
+            .metadata()
+            .map_err(ManagedEnvironmentError::ReadGenerationsMetadata)
+    }
+
+    /// Read the lockfiles of all generations of this environment
+    pub fn generations_lockfiles(
+        &self,
+        flox: &Flox,
+    ) -> Result<BTreeMap<GenerationId, Option<LockedManifest>>, ManagedEnvironmentError> {
+        self.generations()
+            .writable(flox.temp_dir.clone())
+            .map_err(ManagedEnvironmentError::CreateFloxmetaDir)?
+            .lockfiles()
+            .map_err(ManagedEnvironmentError::ReadGenerationLockfiles)
+    }
+
+    /// Read the lockfile of the current generation of the upstream environment
+    ///
+    /// Fetches the latest state of the environment from FloxHub
+    /// without applying it to the local environment.
+    pub fn upstream_lockfile(
+        &self,
+        flox: &Flox,
+    ) -> Result<Option<LockedManifest>, ManagedEnvironmentError> {
+        let sync_branch = remote_branch_name(&self.pointer);
+        self.floxmeta
+            .git
+            .fetch_ref("dynamicorigin", &format!("+{sync_branch}:{sync_branch}"))
+            .map_err(ManagedEnvironmentError::FetchUpdates)?;
+
+        let generations = Generations::new(self.floxmeta.git.clone(), sync_branch)
+            .writable(flox.temp_dir.clone())
+            .map_err(ManagedEnvironmentError::CreateFloxmetaDir)?;
+        let metadata = generations
+            .metadata()
+            .map_err(ManagedEnvironmentError::ReadGenerationsMetadata)?;
+        let Some(current_gen) = metadata.current_gen else {
+            return Ok(None);
+        };
+
+        generations
+            .lockfile(*current_gen)
+            .map_err(ManagedEnvironmentError::ReadGenerationLockfiles)
+    }
+
     fn get_current_generation(
         &self,
         flox: &Flox,
@@ -947,6 +1147,80 @@ fn reverse_links_dir(flox: &Flox) -> PathBuf {
     flox.data_dir.join("links")
 }
 
+/// The out link that keeps the build of a single generation alive
+///
+/// Like nix profile generations, these are named `<out link>-<generation>-link`
+/// and placed next to the out link of the environment.
+fn generation_link(out_link: &Path, generation: &GenerationId) -> PathBuf {
+    let mut name = out_link.file_name().unwrap_or_default().to_os_string();
+    name.push(format!("-{generation}-link"));
+    out_link.with_file_name(name)
+}
+
+/// The number of most recent generations that are kept alive by [generation_link]s
+///
+/// The current generation is always kept alive.
+const PINNED_GENERATIONS: usize = 3;
+
+/// All existing [generation_link]s of the environment linked to `out_link`
+fn all_generation_links(
+    out_link: &Path,
+) -> Result<Vec<(GenerationId, PathBuf)>, ManagedEnvironmentError> {
+    let Some(dir) = out_link.parent() else {
+        return Ok(vec![]);
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(ManagedEnvironmentError::ReadLinksDir(dir.to_path_buf(), e)),
+    };
+    let prefix = format!(
+        "{}-",
+        out_link.file_name().unwrap_or_default().to_string_lossy()
+    );
+
+    let mut links = vec![];
+    for entry in entries {
+        let path = entry
+            .map_err(|e| ManagedEnvironmentError::ReadLinksDir(dir.to_path_buf(), e))?
+            .path();
+        let generation = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(&prefix))
+            .and_then(|name| name.strip_suffix("-link"))
+            .and_then(|generation| generation.parse::<GenerationId>().ok());
+        if let Some(generation) = generation {
+            links.push((generation, path));
+        }
+    }
+    links.sort();
+    Ok(links)
+}
+
+/// The [generation_link]s of `generations` that exist
+fn existing_generation_links(out_link: &Path, generations: &[GenerationId]) -> Vec<PathBuf> {
+    generations
+        .iter()
+        .map(|generation| generation_link(out_link, generation))
+        // `Path::exists` follows the link and is false if the build was collected already
+        .filter(|link| link.symlink_metadata().is_ok())
+        .collect()
+}
+
+/// Remove the [generation_link]s of `generations`
+fn remove_generation_links(
+    out_link: &Path,
+    generations: &[GenerationId],
+) -> Result<Vec<PathBuf>, ManagedEnvironmentError> {
+    let links = existing_generation_links(out_link, generations);
+    for link in &links {
+        fs::remove_file(link)
+            .map_err(|e| ManagedEnvironmentError::DeleteEnvironmentLink(link.clone(), e))?;
+    }
+    Ok(links)
+}
+
 pub enum PullResult {
     /// The environment was already up to date
     UpToDate,
@@ -969,6 +1243,17 @@ impl ManagedEnvironment {
         let path_pointer = path_environment.pointer.clone();
         let name = path_environment.name();
 
+        // The local history is moved to FloxHub along with the environment
+        let local_generations = if path_environment.has_history() {
+            Some(
+                path_environment
+                    .generations()
+                    .map_err(ManagedEnvironmentError::OpenLocalGenerations)?,
+            )
+        } else {
+            None
+        };
+
         let mut core_environment = path_environment.into_core_environment();
 
         // Ensure the environment builds before we push it
@@ -1003,8 +1288,18 @@ impl ManagedEnvironment {
             .writable(flox.temp_dir.clone())
             .map_err(ManagedEnvironmentError::CreateFloxmetaDir)?;
 
+        let description = match local_generations {
+            Some(ref local_generations) => {
+                generations
+                    .import_generations(local_generations)
+                    .map_err(ManagedEnvironmentError::ImportLocalGenerations)?;
+                "Push local environment"
+            },
+            None => "Add first generation",
+        };
+
         generations
-            .add_generation(&mut core_environment, "Add first generation".to_string())
+            .add_generation(&mut core_environment, description.to_string())
             .map_err(ManagedEnvironmentError::CommitGeneration)?;
 
         temp_floxmeta_git
@@ -1036,6 +1331,12 @@ impl ManagedEnvironment {
             None,
         )?;
 
+        // History is now kept by the managed environment
+        if local_generations.is_some() {
+            fs::remove_dir_all(dot_flox_path.join(GENERATIONS_DIR_NAME))
+                .map_err(ManagedEnvironmentError::DeleteLocalGenerations)?;
+        }
+
         let env = ManagedEnvironment::open(flox, pointer, dot_flox_path)?;
 
         Ok(env)
@@ -1096,6 +1397,29 @@ impl ManagedEnvironment {
         Ok(())
     }
 
+    /// Fetch the environment from FloxHub without applying any changes
+    ///
+    /// Returns whether FloxHub has changes that are not yet pulled.
+    /// The changes can be applied with [ManagedEnvironment::pull].
+    pub fn fetch_upstream(&self) -> Result<bool, ManagedEnvironmentError> {
+        let sync_branch = remote_branch_name(&self.pointer);
+        let project_branch = branch_name(&self.pointer, &self.path);
+
+        self.floxmeta
+            .git
+            .fetch_ref("dynamicorigin", &format!("+{sync_branch}:{sync_branch}"))
+            .map_err(ManagedEnvironmentError::FetchUpdates)?;
+
+        // Local changes that are not yet pushed are not upstream changes
+        let pulled = self
+            .floxmeta
+            .git
+            .branch_contains_commit(&sync_branch, &project_branch)
+            .map_err(ManagedEnvironmentError::Git)?;
+
+        Ok(!pulled)
+    }
+
     pub fn pull(&mut self, force: bool) -> Result<PullResult, ManagedEnvironmentError> {
         let sync_branch = remote_branch_name(&self.pointer);
         let project_branch = branch_name(&self.pointer, &self.path);
@@ -1765,6 +2089,66 @@ mod test {
         assert!(links_dir.exists());
     }
 
+    #[test]
+    fn removes_only_links_of_removed_generations() {
+        let (flox, _tmp_dir) = flox_instance();
+        let owner_gcroots = gcroots_dir(&flox, &EnvironmentOwner::from_str("owner").unwrap());
+        fs::create_dir_all(&owner_gcroots).unwrap();
+        let target = owner_gcroots.join("target");
+        fs::create_dir(&target).unwrap();
+
+        let out_link = owner_gcroots.join("name.aaa");
+        let other_out_link = owner_gcroots.join("name.bbb");
+        for link in [
+            &out_link,
+            &generation_link(&out_link, &1.into()),
+            &generation_link(&out_link, &2.into()),
+            &other_out_link,
+            &generation_link(&other_out_link, &1.into()),
+        ] {
+            std::os::unix::fs::symlink(&target, link).unwrap();
+        }
+        // links of other environments survive even if their `.flox` is missing
+        let links_dir = reverse_links_dir(&flox);
+        fs::create_dir_all(&links_dir).unwrap();
+        std::os::unix::fs::symlink(owner_gcroots.join("missing"), links_dir.join("bbb")).unwrap();
+
+        let removed = remove_generation_links(&out_link, &[1.into(), 3.into()]).unwrap();
+        assert_eq!(removed, vec![owner_gcroots.join("name.aaa-1-link")]);
+
+        assert!(!generation_link(&out_link, &1.into()).exists());
+        assert!(generation_link(&out_link, &2.into()).exists());
+        assert!(out_link.exists());
+        assert!(other_out_link.exists());
+        assert!(generation_link(&other_out_link, &1.into()).exists());
+        assert!(links_dir.join("bbb").symlink_metadata().is_ok());
+    }
+
+    #[test]
+    fn lists_all_generation_links() {
+        let (flox, _tmp_dir) = flox_instance();
+        let owner_gcroots = gcroots_dir(&flox, &EnvironmentOwner::from_str("owner").unwrap());
+        fs::create_dir_all(&owner_gcroots).unwrap();
+        let target = owner_gcroots.join("target");
+        fs::create_dir(&target).unwrap();
+
+        let out_link = owner_gcroots.join("name.aaa");
+        for link in [
+            &out_link,
+            &generation_link(&out_link, &2.into()),
+            &generation_link(&out_link, &10.into()),
+            &generation_link(&owner_gcroots.join("name.aaa-1"), &1.into()),
+            &owner_gcroots.join("name.bbb-1-link"),
+        ] {
+            std::os::unix::fs::symlink(&target, link).unwrap();
+        }
+
+        assert_eq!(all_generation_links(&out_link).unwrap(), vec![
+            (2.into(), generation_link(&out_link, &2.into())),
+            (10.into(), generation_link(&out_link, &10.into())),
+        ]);
+    }
+
     #[test]
     fn creates_reverse_link() {
         let (flox, tmp_dir) = flox_instance();
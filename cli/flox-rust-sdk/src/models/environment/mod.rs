@@ -8,6 +8,8 @@ use thiserror::Error;
 use url::Url;
 use walkdir::WalkDir;
 
+use self::core_environment::CoreEnvironment;
+use self::generations::GenerationsError;
 use self::managed_environment::ManagedEnvironmentError;
 use self::remote_environment::RemoteEnvironmentError;
 use super::container_builder::ContainerBuilder;
@@ -20,6 +22,7 @@ use crate::flox::{Flox, Floxhub};
 use crate::models::pkgdb::call_pkgdb;
 use crate::providers::git::{
     GitCommandDiscoverError,
+    GitCommandOpenError,
     GitCommandProvider,
     GitDiscoverError,
     GitProvider,
@@ -49,6 +52,7 @@ pub const MANIFEST_FILENAME: &str = "manifest.toml";
 pub const LOCKFILE_FILENAME: &str = "manifest.lock";
 pub const GCROOTS_DIR_NAME: &str = "run";
 pub const CACHE_DIR_NAME: &str = "cache";
+pub const GENERATIONS_DIR_NAME: &str = "generations";
 pub const ENV_DIR_NAME: &str = "env";
 pub const FLOX_ENV_VAR: &str = "FLOX_ENV";
 pub const FLOX_ENV_CACHE_VAR: &str = "FLOX_ENV_CACHE";
@@ -460,6 +464,22 @@ pub enum EnvironmentError2 {
 
     #[error("could not get current directory")]
     GetCurrentDir(#[source] std::io::Error),
+
+    #[error("could not open local generations")]
+    OpenGenerations(#[source] GitCommandOpenError),
+
+    #[error("could not update local generations")]
+    Generations(#[source] GenerationsError),
+}
+
+/// Lock the environment definition in `env_dir` without modifying it
+///
+/// See [CoreEnvironment::lock_detached].
+pub fn lock_detached(
+    flox: &Flox,
+    env_dir: impl AsRef<Path>,
+) -> Result<LockedManifest, EnvironmentError2> {
+    Ok(CoreEnvironment::new(env_dir).lock_detached(flox)?)
 }
 
 /// Copy a whole directory recursively ignoring the original permissions
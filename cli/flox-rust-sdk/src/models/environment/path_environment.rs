@@ -8,11 +8,19 @@
 //!         LOCKFILE_FILENAME
 //!     PATH_ENV_GCROOTS_DIR_NAME/
 //!         $system.$name (out link)
+//!     GENERATIONS_DIR_NAME/ (optional)
+//!         <git repository>
 //! ```
 //!
 //! `ENVIRONMENT_DIR_NAME` contains the environment definition
 //! and is modified using [CoreEnvironment].
+//!
+//! `GENERATIONS_DIR_NAME` keeps an opt-in local history of the environment
+//! in the same format as the generations of managed environments,
+//! see [PathEnvironment::enable_history].
+//! Modifications of environments without this directory are not recorded.
 
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::fs::{self};
 use std::io::Write;
@@ -21,7 +29,14 @@ use std::path::{Path, PathBuf};
 use indoc::{formatdoc, indoc};
 use log::debug;
 
-use super::core_environment::CoreEnvironment;
+use super::core_environment::{CoreEnvironment, EnvironmentLock, TRANSACTION_LOCK_EXTENSION};
+use super::generations::{
+    AllGenerationsMetadata,
+    GenerationId,
+    Generations,
+    GenerationsError,
+    ReadWrite,
+};
 use super::{
     copy_dir_recursive,
     CanonicalPath,
@@ -38,6 +53,7 @@ use super::{
     DOT_FLOX,
     ENVIRONMENT_POINTER_FILENAME,
     GCROOTS_DIR_NAME,
+    GENERATIONS_DIR_NAME,
     LOCKFILE_FILENAME,
 };
 use crate::data::System;
@@ -55,8 +71,12 @@ use crate::models::environment_ref::EnvironmentName;
 use crate::models::lockfile::LockedManifest;
 use crate::models::manifest::PackageToInstall;
 use crate::models::pkgdb::UpgradeResult;
+use crate::providers::git::{GitCommandOpenError, GitCommandOptions, GitCommandProvider};
 use crate::utils::mtime_of;
 
+/// The branch of the local generations repository containing the generations
+const GENERATIONS_BRANCH: &str = "generations";
+
 /// Struct representing a local environment
 ///
 /// This environment performs transactional edits by first copying the environment
@@ -159,6 +179,182 @@ impl PathEnvironment {
         CoreEnvironment::new(self.path.join(ENV_DIR_NAME))
     }
 
+    /// Whether the environment keeps a local history,
+    /// see [Self::enable_history]
+    pub fn has_history(&self) -> bool {
+        self.path.join(GENERATIONS_DIR_NAME).exists()
+    }
+
+    /// Open the local generations of this environment
+    ///
+    /// Assumes that the history has been enabled, see [Self::has_history].
+    /// The generations are a checked out repository,
+    /// so they can be modified without cloning them first.
+    pub(super) fn generations(&self) -> Result<Generations<ReadWrite>, GitCommandOpenError> {
+        let repo = GitCommandProvider::open_with(
+            generations_git_options(),
+            self.path.join(GENERATIONS_DIR_NAME),
+        )?;
+        Ok(Generations::open_local(
+            repo,
+            GENERATIONS_BRANCH.to_string(),
+        ))
+    }
+
+    /// Start keeping a local history of this environment
+    ///
+    /// The current state of the environment is recorded as the first generation,
+    /// subsequent modifications add further generations.
+    /// Environments created before local generations were introduced
+    /// get the generations directory added to their `.gitignore`.
+    ///
+    /// Does nothing if the history is already enabled.
+    pub fn enable_history(&self, description: &str) -> Result<(), EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
+        if self.has_history() {
+            return Ok(());
+        }
+
+        let gitignore_path = self.path.join(".gitignore");
+        if let Ok(gitignore) = fs::read_to_string(&gitignore_path) {
+            let entry = format!("{GENERATIONS_DIR_NAME}/");
+            if !gitignore.lines().any(|line| line == entry) {
+                let mut file = fs::OpenOptions::new()
+                    .append(true)
+                    .open(&gitignore_path)
+                    .map_err(EnvironmentError2::WriteGitignore)?;
+                let separator = if gitignore.is_empty() || gitignore.ends_with('\n') {
+                    ""
+                } else {
+                    "\n"
+                };
+                writeln!(file, "{separator}{entry}").map_err(EnvironmentError2::WriteGitignore)?;
+            }
+        }
+
+        let generations_path = self.path.join(GENERATIONS_DIR_NAME);
+        let initialized = Generations::init_local(
+            generations_git_options(),
+            &generations_path,
+            GENERATIONS_BRANCH.to_string(),
+            &self.pointer,
+        )
+        .and_then(|mut generations| {
+            generations.add_generation(
+                &mut CoreEnvironment::new(self.path.join(ENV_DIR_NAME)),
+                description.to_string(),
+            )
+        });
+
+        // Don't leave a partially initialized history behind,
+        // which would be mistaken for an enabled history
+        if let Err(err) = initialized {
+            debug!("removing partially initialized generations: {err}");
+            let _ = fs::remove_dir_all(&generations_path);
+            return Err(EnvironmentError2::Generations(err));
+        }
+
+        Ok(())
+    }
+
+    /// Record the environment definition in `env_view` as a new generation,
+    /// if the environment keeps a history
+    fn add_generation(
+        &self,
+        env_view: &mut CoreEnvironment,
+        description: String,
+    ) -> Result<(), EnvironmentError2> {
+        if !self.has_history() {
+            return Ok(());
+        }
+
+        self.generations()
+            .map_err(EnvironmentError2::OpenGenerations)?
+            .add_generation(env_view, description)
+            .map_err(EnvironmentError2::Generations)
+    }
+
+    /// Read the metadata of the local generations of this environment
+    ///
+    /// Environments without local generations have an empty history.
+    pub fn generations_metadata(&self) -> Result<AllGenerationsMetadata, EnvironmentError2> {
+        if !self.has_history() {
+            return Ok(AllGenerationsMetadata::default());
+        }
+
+        self.generations()
+            .map_err(EnvironmentError2::OpenGenerations)?
+            .metadata()
+            .map_err(EnvironmentError2::Generations)
+    }
+
+    /// Read the lockfiles of the local generations of this environment
+    pub fn generations_lockfiles(
+        &self,
+    ) -> Result<BTreeMap<GenerationId, Option<LockedManifest>>, EnvironmentError2> {
+        if !self.has_history() {
+            return Ok(BTreeMap::new());
+        }
+
+        self.generations()
+            .map_err(EnvironmentError2::OpenGenerations)?
+            .lockfiles()
+            .map_err(EnvironmentError2::Generations)
+    }
+
+    /// Remove generations from the local history of this environment
+    ///
+    /// See [Generations::remove_generations].
+    pub fn wipe_history(&mut self, generations: &[GenerationId]) -> Result<(), EnvironmentError2> {
+        if generations.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = EnvironmentLock::acquire(&self.path)?;
+        self.generations()
+            .map_err(EnvironmentError2::OpenGenerations)?
+            .remove_generations(generations)
+            .map_err(EnvironmentError2::Generations)
+    }
+
+    /// Switch the environment to an existing generation
+    ///
+    /// The generation is built before it replaces the current environment definition,
+    /// so switching to a generation that does not build on this system fails
+    /// without modifying the environment.
+    pub fn switch_generation(
+        &mut self,
+        flox: &Flox,
+        generation: usize,
+    ) -> Result<(), EnvironmentError2> {
+        if !self.has_history() {
+            return Err(EnvironmentError2::Generations(
+                GenerationsError::GenerationNotFound(generation),
+            ));
+        }
+
+        let _lock = EnvironmentLock::acquire(&self.path)?;
+        let mut generations = self
+            .generations()
+            .map_err(EnvironmentError2::OpenGenerations)?;
+        let mut generation_view = generations
+            .get_generation(generation)
+            .map_err(EnvironmentError2::Generations)?;
+
+        // The generation is built in a copy,
+        // locking it must not modify the checked out generations
+        let mut env_view = CoreEnvironment::new(self.path.join(ENV_DIR_NAME));
+        let store_path = env_view.replace_with_copy_of(&mut generation_view, flox)?;
+
+        generations
+            .set_current_generation(generation)
+            .map_err(EnvironmentError2::Generations)?;
+
+        env_view.link(flox, self.out_link(&flox.system)?, &Some(store_path))?;
+
+        Ok(())
+    }
+
     pub fn rename(&mut self, new_name: EnvironmentName) -> Result<(), EnvironmentError2> {
         self.pointer.name = new_name;
         let pointer_content = serde_json::to_string_pretty(&self.pointer)
@@ -218,9 +414,12 @@ impl Environment for PathEnvironment {
         packages: &[PackageToInstall],
         flox: &Flox,
     ) -> Result<InstallationAttempt, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut env_view = CoreEnvironment::new(self.path.join(ENV_DIR_NAME));
+        let metadata = format!("installed packages: {:?}", &packages);
         let result = env_view.install(packages, flox)?;
         env_view.link(flox, self.out_link(&flox.system)?, &result.store_path)?;
+        self.add_generation(&mut env_view, metadata)?;
 
         Ok(result)
     }
@@ -235,19 +434,24 @@ impl Environment for PathEnvironment {
         packages: Vec<String>,
         flox: &Flox,
     ) -> Result<UninstallationAttempt, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut env_view = CoreEnvironment::new(self.path.join(ENV_DIR_NAME));
+        let metadata = format!("uninstalled packages: {:?}", &packages);
         let result = env_view.uninstall(packages, flox)?;
         env_view.link(flox, self.out_link(&flox.system)?, &result.store_path)?;
+        self.add_generation(&mut env_view, metadata)?;
 
         Ok(result)
     }
 
     /// Atomically edit this environment, ensuring that it still builds
     fn edit(&mut self, flox: &Flox, contents: String) -> Result<EditResult, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut env_view = CoreEnvironment::new(self.path.join(ENV_DIR_NAME));
         let result = env_view.edit(flox, contents)?;
         if result != EditResult::Unchanged {
             env_view.link(flox, self.out_link(&flox.system)?, &result.store_path())?;
+            self.add_generation(&mut env_view, "manually edited".to_string())?;
         }
         Ok(result)
     }
@@ -258,9 +462,11 @@ impl Environment for PathEnvironment {
         flox: &Flox,
         inputs: Vec<String>,
     ) -> Result<UpdateResult, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut env_view = CoreEnvironment::new(self.path.join(ENV_DIR_NAME));
         let result = env_view.update(flox, inputs)?;
         env_view.link(flox, self.out_link(&flox.system)?, &result.store_path)?;
+        self.add_generation(&mut env_view, "updated environment".to_string())?;
 
         Ok(result)
     }
@@ -271,9 +477,12 @@ impl Environment for PathEnvironment {
         flox: &Flox,
         groups_or_iids: &[String],
     ) -> Result<UpgradeResult, EnvironmentError2> {
+        let _lock = EnvironmentLock::acquire(&self.path)?;
         let mut env_view = CoreEnvironment::new(self.path.join(ENV_DIR_NAME));
         let result = env_view.upgrade(flox, groups_or_iids)?;
         env_view.link(flox, self.out_link(&flox.system)?, &result.store_path)?;
+        let metadata = format!("upgraded packages: {}", result.packages.join(", "));
+        self.add_generation(&mut env_view, metadata)?;
 
         Ok(result)
     }
@@ -435,6 +644,8 @@ impl PathEnvironment {
         fs::write(dot_flox_path.join(".gitignore"), formatdoc! {"
             {GCROOTS_DIR_NAME}/
             {CACHE_DIR_NAME}/
+            {GENERATIONS_DIR_NAME}/
+            {ENV_DIR_NAME}.{TRANSACTION_LOCK_EXTENSION}
             "})
         .map_err(EnvironmentError2::WriteGitignore)?;
 
@@ -531,6 +742,19 @@ impl PathEnvironment {
     }
 }
 
+/// Git options for the local generations repository of a path environment
+///
+/// Like floxmeta, generations are committed as a generic user
+/// independent of the user's git configuration.
+fn generations_git_options() -> GitCommandOptions {
+    let mut options = GitCommandOptions::default();
+    options.add_config_flag("user.name", "Flox User");
+    options.add_config_flag("user.email", "floxuser@example.invalid");
+    options.add_env_var("GIT_CONFIG_GLOBAL", "/dev/null");
+    options.add_env_var("GIT_CONFIG_SYSTEM", "/dev/null");
+    options
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -580,6 +804,47 @@ mod tests {
         assert!(actual.path.is_absolute());
     }
 
+    #[test]
+    fn enabling_history_imports_existing_environment() {
+        let (_flox, temp_dir) = flox_instance();
+        let dot_flox_path = tempfile::tempdir_in(&temp_dir).unwrap().into_path();
+        fs::create_dir(dot_flox_path.join(ENV_DIR_NAME)).unwrap();
+        fs::write(
+            dot_flox_path.join(ENV_DIR_NAME).join(MANIFEST_FILENAME),
+            "# manifest",
+        )
+        .unwrap();
+        fs::write(dot_flox_path.join(".gitignore"), "run/\ncache/").unwrap();
+
+        let env = PathEnvironment::open(
+            PathPointer::new("test".parse().unwrap()),
+            &dot_flox_path,
+            temp_dir.path(),
+        )
+        .unwrap();
+
+        assert!(env.generations_metadata().unwrap().generations.is_empty());
+
+        assert!(!env.has_history());
+        env.enable_history("Import existing environment").unwrap();
+        assert!(env.has_history());
+
+        let metadata = env.generations_metadata().unwrap();
+        assert_eq!(metadata.current_gen, Some(1.into()));
+        assert_eq!(
+            metadata.generations[&1.into()].description,
+            "Import existing environment"
+        );
+        assert_eq!(
+            env.generations().unwrap().manifest(1).unwrap(),
+            "# manifest"
+        );
+        assert_eq!(
+            fs::read_to_string(dot_flox_path.join(".gitignore")).unwrap(),
+            "run/\ncache/\ngenerations/\n"
+        );
+    }
+
     /// Write a manifest file with invalid toml to ensure we can catch
     #[test]
     fn cache_activation_path() {
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -5,6 +6,7 @@ use log::debug;
 use tempfile::TempDir;
 use thiserror::Error;
 
+use super::generations::{AllGenerationsMetadata, GenerationId};
 use super::managed_environment::{remote_branch_name, ManagedEnvironment, ManagedEnvironmentError};
 use super::{
     gcroots_dir,
@@ -143,6 +145,68 @@ impl RemoteEnvironment {
         self.inner.pointer()
     }
 
+    /// Read the metadata of all generations of the upstream environment
+    ///
+    /// Remote environments are reset to their upstream state when opened,
+    /// so this reflects the generations available on FloxHub.
+    pub fn generations_metadata(&self) -> Result<AllGenerationsMetadata, ManagedEnvironmentError> {
+        self.inner.generations_metadata()
+    }
+
+    /// Read the lockfiles of all generations of this environment
+    pub fn generations_lockfiles(
+        &self,
+        flox: &Flox,
+    ) -> Result<BTreeMap<GenerationId, Option<LockedManifest>>, ManagedEnvironmentError> {
+        self.inner.generations_lockfiles(flox)
+    }
+
+    /// Read the lockfile of the current generation of the upstream environment
+    ///
+    /// See [ManagedEnvironment::upstream_lockfile].
+    pub fn upstream_lockfile(
+        &self,
+        flox: &Flox,
+    ) -> Result<Option<LockedManifest>, ManagedEnvironmentError> {
+        self.inner.upstream_lockfile(flox)
+    }
+
+    /// Switch the upstream environment to an existing generation
+    ///
+    /// See [ManagedEnvironment::switch_generation].
+    /// The switch is pushed to FloxHub before the local out link is updated.
+    pub fn switch_generation(
+        &mut self,
+        flox: &Flox,
+        generation: usize,
+    ) -> Result<(), EnvironmentError2> {
+        self.inner.switch_generation(flox, generation)?;
+        self.inner
+            .push(flox, false)
+            .map_err(|e| RemoteEnvironmentError::UpdateUpstream(e).into())
+            .and_then(|_| Self::update_out_link(flox, &self.out_link, &mut self.inner))
+    }
+
+    /// Remove generations from the history of the upstream environment
+    ///
+    /// See [ManagedEnvironment::wipe_history].
+    pub fn wipe_history(
+        &mut self,
+        flox: &Flox,
+        generations: &[GenerationId],
+    ) -> Result<Vec<PathBuf>, EnvironmentError2> {
+        let removed_links = self.inner.wipe_history(flox, generations)?;
+        self.inner
+            .push(flox, false)
+            .map_err(RemoteEnvironmentError::UpdateUpstream)?;
+        Ok(removed_links)
+    }
+
+    /// See [ManagedEnvironment::generation_links].
+    pub fn generation_links(&self, generations: &[GenerationId]) -> Vec<PathBuf> {
+        self.inner.generation_links(generations)
+    }
+
     /// Update the out link to point to the current version of the environment
     ///
     /// The inner out link points to the latest version of the managed environment.
@@ -3,7 +3,8 @@ use serde_json::Value;
 
 pub type FlakeRef = Value;
 
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -21,16 +22,36 @@ use crate::models::environment::{
     global_manifest_path,
     CanonicalPath,
 };
+use crate::models::manifest::Manifest;
 use crate::models::pkgdb::{call_pkgdb, BuildEnvResult, PKGDB_BIN};
+use crate::models::search::Subtree;
 use crate::utils::CommandExt;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Input {
     pub from: FlakeRef,
+    /// The package sets provided by the input, as detected by pkgdb
+    /// or configured in the manifest's registry.
+    ///
+    /// Lockfiles written by older versions of pkgdb may not record them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtrees: Option<Vec<Subtree>>,
     #[serde(flatten)]
     _json: Value,
 }
 
+impl Input {
+    /// The package sets that may be scraped for this input
+    ///
+    /// Inputs locked without subtrees may provide any of the subtrees
+    /// pkgdb detects, in the order pkgdb prefers them.
+    pub fn subtrees(&self) -> &[Subtree] {
+        self.subtrees
+            .as_deref()
+            .unwrap_or(&[Subtree::Packages, Subtree::LegacyPackages])
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Registry {
     pub inputs: BTreeMap<String, Input>,
@@ -236,6 +257,12 @@ impl LockedManifest {
 
         Ok(warnings)
     }
+
+    /// The manifest that was locked to produce this lockfile
+    pub fn manifest(&self) -> Result<Manifest, LockedManifestError> {
+        serde_json::from_value(self.0["manifest"].clone())
+            .map_err(LockedManifestError::ParseLockedManifest)
+    }
 }
 
 impl ToString for LockedManifest {
@@ -274,6 +301,9 @@ pub struct TypedLockedManifest {
     lockfile_version: Version<0>,
     packages: BTreeMap<System, BTreeMap<String, Option<LockedPackage>>>,
     registry: Registry,
+    /// The manifest that was locked, kept untyped as it is only compared
+    #[serde(default)]
+    manifest: Value,
 }
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
@@ -335,6 +365,25 @@ impl TypedLockedManifest {
         }
         packages
     }
+
+    /// The systems packages were locked for
+    pub fn systems(&self) -> Vec<&System> {
+        self.packages.keys().collect()
+    }
+
+    /// Install IDs of packages that could not be locked for a given system
+    pub fn unavailable_packages(&self, system: &System) -> Vec<&str> {
+        self.packages
+            .get(system)
+            .map(|packages| {
+                packages
+                    .iter()
+                    .filter(|(_, package)| package.is_none())
+                    .map(|(install_id, _)| install_id.as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 }
 
 pub struct InstalledPackage {
@@ -344,6 +393,233 @@ pub struct InstalledPackage {
     pub priority: usize,
 }
 
+/// Changes between two locked manifests for a single system
+///
+/// Packages are matched by their install id.
+/// A package whose path or version changed is considered upgraded,
+/// unless its new version is older than the previous one,
+/// as compared by [compare_versions].
+/// Changes to the rest of the manifest are reported per variable or section.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LockedManifestDiff {
+    /// Packages only locked in the new manifest
+    pub added: Vec<PackageVersion>,
+    /// Packages only locked in the old manifest
+    pub removed: Vec<PackageVersion>,
+    /// Packages locked to a different path or a newer version
+    pub upgraded: Vec<PackageUpgrade>,
+    /// Packages locked to an older version
+    pub downgraded: Vec<PackageUpgrade>,
+    /// Names of inputs that were newly locked, removed
+    /// or locked to a different revision
+    pub inputs: Vec<String>,
+    /// Names of variables that were added, removed or set to a different value
+    pub vars: Vec<String>,
+    /// Other sections of the manifest that changed, out of [Self::SECTIONS]
+    pub sections: Vec<String>,
+}
+
+/// A locked package as shown in a [LockedManifestDiff]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageVersion {
+    pub install_id: String,
+    pub rel_path: String,
+    pub version: Option<String>,
+}
+
+/// A package locked to a different path or version in a [LockedManifestDiff]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageUpgrade {
+    pub install_id: String,
+    pub old: PackageVersion,
+    pub new: PackageVersion,
+}
+
+impl From<InstalledPackage> for PackageVersion {
+    fn from(package: InstalledPackage) -> Self {
+        Self {
+            install_id: package.name,
+            rel_path: package.rel_path,
+            version: package.info.version,
+        }
+    }
+}
+
+impl LockedManifestDiff {
+    /// Sections of the manifest that are compared as a whole
+    pub const SECTIONS: [&'static str; 3] = ["hook", "profile", "options"];
+
+    /// Compare the packages locked for `system`, the inputs
+    /// and the manifests of two locked manifests
+    ///
+    /// A missing manifest is treated as a manifest without packages and inputs.
+    pub fn new(
+        old: Option<&TypedLockedManifest>,
+        new: Option<&TypedLockedManifest>,
+        system: &System,
+    ) -> Self {
+        let packages = |lockfile: Option<&TypedLockedManifest>| {
+            lockfile
+                .map(|lockfile| lockfile.list_packages(system))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|package| (package.name.clone(), PackageVersion::from(package)))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let mut old_packages = packages(old);
+        let new_packages = packages(new);
+
+        let mut diff = Self::default();
+
+        for (install_id, new_package) in new_packages {
+            match old_packages.remove(&install_id) {
+                None => diff.added.push(new_package),
+                Some(old_package) if old_package == new_package => {},
+                Some(old_package) => {
+                    let downgraded = match (&old_package.version, &new_package.version) {
+                        (Some(old), Some(new)) => compare_versions(new, old) == Ordering::Less,
+                        _ => false,
+                    };
+                    let change = PackageUpgrade {
+                        install_id,
+                        old: old_package,
+                        new: new_package,
+                    };
+                    if downgraded {
+                        diff.downgraded.push(change);
+                    } else {
+                        diff.upgraded.push(change);
+                    }
+                },
+            }
+        }
+        diff.removed = old_packages.into_values().collect();
+
+        let old_inputs = old.map(|lockfile| &lockfile.registry().inputs);
+        let new_inputs = new.map(|lockfile| &lockfile.registry().inputs);
+        diff.inputs = [old_inputs, new_inputs]
+            .into_iter()
+            .flatten()
+            .flat_map(|inputs| inputs.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|name| {
+                old_inputs.and_then(|inputs| inputs.get(*name))
+                    != new_inputs.and_then(|inputs| inputs.get(*name))
+            })
+            .map(|name| name.to_string())
+            .collect();
+
+        // Missing and empty sections are equivalent
+        let section = |lockfile: Option<&TypedLockedManifest>, name: &str| {
+            lockfile
+                .and_then(|lockfile| lockfile.manifest.get(name))
+                .filter(|value| !value.is_null() && value.as_object() != Some(&Default::default()))
+                .cloned()
+        };
+
+        let old_vars = section(old, "vars");
+        let new_vars = section(new, "vars");
+        let var = |vars: &Option<Value>, name: &str| {
+            vars.as_ref().and_then(|vars| vars.get(name)).cloned()
+        };
+        let names = [&old_vars, &new_vars]
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_object)
+            .flat_map(|vars| vars.keys())
+            .collect::<BTreeSet<_>>();
+        diff.vars = names
+            .into_iter()
+            .filter(|name| var(&old_vars, name) != var(&new_vars, name))
+            .cloned()
+            .collect();
+
+        diff.sections = Self::SECTIONS
+            .into_iter()
+            .filter(|name| section(old, name) != section(new, name))
+            .map(String::from)
+            .collect();
+
+        diff
+    }
+
+    /// Whether neither packages, inputs nor the rest of the manifest changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.upgraded.is_empty()
+            && self.downgraded.is_empty()
+            && self.inputs.is_empty()
+            && self.vars.is_empty()
+            && self.sections.is_empty()
+    }
+}
+
+/// Compare two package versions like `builtins.compareVersions` of nix
+///
+/// Versions are split into numeric and alphabetic components at `.`, `-`
+/// and the boundaries between digits and other characters.
+/// Numeric components are compared as numbers and are newer than
+/// alphabetic ones, missing components are older than both,
+/// and `pre` is older than any other component,
+/// e.g. `1.0pre1 < 1.0 < 1.0a < 1.0.1 < 1.1`.
+pub fn compare_versions(left: &str, right: &str) -> Ordering {
+    fn components(version: &str) -> Vec<&str> {
+        let mut components = Vec::new();
+        let mut rest = version;
+        loop {
+            rest = rest.trim_start_matches(['.', '-']);
+            let Some(first) = rest.chars().next() else {
+                return components;
+            };
+            let end = rest
+                .find(|c: char| {
+                    c == '.' || c == '-' || c.is_ascii_digit() != first.is_ascii_digit()
+                })
+                .unwrap_or(rest.len());
+            components.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+
+    fn is_numeric(component: &str) -> bool {
+        !component.is_empty() && component.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    fn compare_components(left: &str, right: &str) -> Ordering {
+        if is_numeric(left) && is_numeric(right) {
+            let left = left.trim_start_matches('0');
+            let right = right.trim_start_matches('0');
+            return left.len().cmp(&right.len()).then_with(|| left.cmp(right));
+        }
+        match (left, right) {
+            _ if left == right => Ordering::Equal,
+            ("", _) if is_numeric(right) => Ordering::Less,
+            (_, "") if is_numeric(left) => Ordering::Greater,
+            ("pre", _) => Ordering::Less,
+            (_, "pre") => Ordering::Greater,
+            _ if is_numeric(left) => Ordering::Greater,
+            _ if is_numeric(right) => Ordering::Less,
+            _ => left.cmp(right),
+        }
+    }
+
+    let left = components(left);
+    let right = components(right);
+    (0..left.len().max(right.len()))
+        .map(|i| {
+            compare_components(
+                left.get(i).copied().unwrap_or_default(),
+                right.get(i).copied().unwrap_or_default(),
+            )
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
 #[derive(Debug, Error)]
 pub enum LockedManifestError {
     #[error("failed to lock manifest")]
@@ -395,6 +671,28 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn input_subtrees_default_to_detected_subtrees() {
+        let input: Input = serde_json::from_value(serde_json::json!({
+            "from": { "type": "github", "owner": "acme", "repo": "tools" }
+        }))
+        .unwrap();
+        assert_eq!(input.subtrees(), &[
+            Subtree::Packages,
+            Subtree::LegacyPackages
+        ]);
+
+        let input: Input = serde_json::from_value(serde_json::json!({
+            "from": { "type": "github", "owner": "acme", "repo": "tools" },
+            "subtrees": ["packages", "legacyPackages"]
+        }))
+        .unwrap();
+        assert_eq!(input.subtrees(), &[
+            Subtree::Packages,
+            Subtree::LegacyPackages
+        ]);
+    }
+
     /// Validate that the parser for the locked manifest can handle null values
     /// for the `version`, `license`, and `description` fields.
     #[test]
@@ -481,4 +779,152 @@ mod tests {
             None
         );
     }
+
+    /// Create a locked manifest for `x86_64-linux`
+    /// with the given `(install id, version)` pairs and nixpkgs revision
+    fn locked_manifest(packages: &[(&str, &str)], rev: &str) -> TypedLockedManifest {
+        let packages = packages
+            .iter()
+            .map(|(install_id, version)| {
+                (
+                    install_id.to_string(),
+                    serde_json::json!({
+                        "info": {
+                            "description": null,
+                            "broken": false,
+                            "license": null,
+                            "pname": install_id,
+                            "unfree": false,
+                            "version": version
+                        },
+                        "attr-path": ["legacyPackages", "x86_64-linux", install_id],
+                        "priority": 5
+                    }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        serde_json::from_value(serde_json::json!({
+            "lockfile-version": 0,
+            "packages": { "x86_64-linux": packages },
+            "registry": {
+                "inputs": {
+                    "nixpkgs": {
+                        "from": { "type": "github", "owner": "NixOS", "repo": "nixpkgs", "rev": rev }
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn diff_locked_manifests() {
+        let system = "x86_64-linux".to_string();
+        let old = locked_manifest(&[("hello", "2.12"), ("curl", "8.4.0")], "a");
+        let new = locked_manifest(&[("hello", "2.12"), ("curl", "8.5.0"), ("vim", "9.0")], "b");
+
+        let diff = LockedManifestDiff::new(Some(&old), Some(&new), &system);
+
+        assert_eq!(diff.added, vec![PackageVersion {
+            install_id: "vim".to_string(),
+            rel_path: "vim".to_string(),
+            version: Some("9.0".to_string()),
+        }]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.upgraded.len(), 1);
+        assert_eq!(diff.upgraded[0].install_id, "curl");
+        assert_eq!(diff.upgraded[0].old.version.as_deref(), Some("8.4.0"));
+        assert_eq!(diff.upgraded[0].new.version.as_deref(), Some("8.5.0"));
+        assert_eq!(diff.inputs, vec!["nixpkgs".to_string()]);
+
+        let reverse = LockedManifestDiff::new(Some(&new), Some(&old), &system);
+        assert_eq!(reverse.removed.len(), 1);
+        assert_eq!(reverse.removed[0].install_id, "vim");
+        assert!(reverse.upgraded.is_empty());
+        assert_eq!(reverse.downgraded.len(), 1);
+        assert_eq!(reverse.downgraded[0].install_id, "curl");
+        assert_eq!(reverse.downgraded[0].new.version.as_deref(), Some("8.4.0"));
+    }
+
+    #[test]
+    fn diff_removed_inputs() {
+        let system = "x86_64-linux".to_string();
+        let old = locked_manifest(&[("hello", "2.12")], "a");
+        let mut new = old.clone();
+        new.registry.inputs.clear();
+
+        let diff = LockedManifestDiff::new(Some(&old), Some(&new), &system);
+        assert_eq!(diff.inputs, vec!["nixpkgs".to_string()]);
+
+        let diff = LockedManifestDiff::new(Some(&old), None, &system);
+        assert_eq!(diff.inputs, vec!["nixpkgs".to_string()]);
+    }
+
+    #[test]
+    fn compares_versions_like_nix() {
+        for (older, newer) in [
+            ("1.0", "2.3"),
+            ("2.1", "2.3"),
+            ("2.3", "2.3.1"),
+            ("2.3", "2.3a"),
+            ("2.3a", "2.3.1"),
+            ("2.3pre1", "2.3"),
+            ("2.3pre3", "2.3pre12"),
+            ("2.3a", "2.3c"),
+            ("2.3pre1", "2.3c"),
+            ("8.4.0", "8.10.0"),
+            ("2024-01-01", "2024-02-01"),
+        ] {
+            assert_eq!(
+                compare_versions(older, newer),
+                Ordering::Less,
+                "{older} < {newer}"
+            );
+            assert_eq!(
+                compare_versions(newer, older),
+                Ordering::Greater,
+                "{newer} > {older}"
+            );
+        }
+        assert_eq!(compare_versions("2.3.0", "2.3.0"), Ordering::Equal);
+        assert_eq!(compare_versions("2.03", "2.3"), Ordering::Equal);
+    }
+
+    #[test]
+    fn diff_against_missing_manifest() {
+        let system = "x86_64-linux".to_string();
+        let new = locked_manifest(&[("hello", "2.12")], "a");
+
+        let diff = LockedManifestDiff::new(None, Some(&new), &system);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.inputs, vec!["nixpkgs".to_string()]);
+
+        assert!(LockedManifestDiff::new(Some(&new), Some(&new), &system).is_empty());
+        assert!(LockedManifestDiff::new(None, None, &system).is_empty());
+    }
+
+    #[test]
+    fn diff_vars_and_sections() {
+        let system = "x86_64-linux".to_string();
+        let mut old = locked_manifest(&[("hello", "2.12")], "a");
+        old.manifest = serde_json::json!({
+            "vars": { "FOO": "1", "BAR": "1" },
+            "hook": null,
+            "profile": {},
+            "options": { "systems": ["x86_64-linux"] }
+        });
+        let mut new = old.clone();
+        new.manifest = serde_json::json!({
+            "vars": { "FOO": "2", "BAZ": "1" },
+            "hook": { "on-activate": "echo hi" },
+            "options": { "systems": ["x86_64-linux"] }
+        });
+
+        let diff = LockedManifestDiff::new(Some(&old), Some(&new), &system);
+        assert!(diff.added.is_empty() && diff.upgraded.is_empty() && diff.inputs.is_empty());
+        assert_eq!(diff.vars, vec!["BAR", "BAZ", "FOO"]);
+        assert_eq!(diff.sections, vec!["hook"]);
+        assert!(!diff.is_empty());
+    }
 }
@@ -1,33 +1,406 @@
-use std::collections::HashMap;
-use std::process::Command;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
 use std::str::FromStr;
 
 use log::debug;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_with::skip_serializing_none;
 use toml_edit::{self, Document, Formatted, InlineTable, Item, Table, Value};
 
-use crate::models::pkgdb::PKGDB_BIN;
-
 #[derive(Debug, thiserror::Error)]
 pub enum ManifestError {
-    #[error("couldn't parse descriptor '{}': {}", desc, msg)]
-    MalformedStringDescriptor { msg: String, desc: String },
-    /// FIXME: This is a temporary error variant until `flox` parses descriptors on its own
-    #[error("failed while calling pkgdb")]
-    PkgDbCall(#[source] std::io::Error),
+    #[error("couldn't parse descriptor '{desc}': {err}")]
+    MalformedStringDescriptor { desc: String, err: DescriptorError },
+    #[error(
+        "invalid manifest{}: {message}",
+        .location.map(|location| format!(" at {location}")).unwrap_or_default()
+    )]
+    Invalid {
+        message: String,
+        location: Option<Location>,
+    },
+}
+
+/// A position in a manifest, both line and column start at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// Translate a byte offset into `contents` into a line and column
+    fn from_offset(contents: &str, offset: usize) -> Self {
+        let before = &contents[..offset.min(contents.len())];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// An error encountered while parsing a shorthand descriptor.
+///
+/// Positions are 0-based character offsets into the descriptor.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DescriptorError {
+    #[error("descriptor is empty")]
+    Empty,
+    #[error("expected an input name before ':' at position {0}")]
+    EmptyInput(usize),
+    #[error("expected an attribute name at position {0}")]
+    EmptyAttribute(usize),
+    #[error("unterminated quote starting at position {0}")]
+    UnterminatedQuote(usize),
+    #[error("unexpected character '{1}' at position {0}")]
+    UnexpectedCharacter(usize, char),
+    #[error("expected a version after '@' at position {0}")]
+    EmptyVersion(usize),
+    #[error("expected a flake reference before '#'")]
+    EmptyFlakeRef,
+}
+
+impl DescriptorError {
+    /// The position in the descriptor at which parsing failed
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            DescriptorError::Empty | DescriptorError::EmptyFlakeRef => None,
+            DescriptorError::EmptyInput(position)
+            | DescriptorError::EmptyAttribute(position)
+            | DescriptorError::UnterminatedQuote(position)
+            | DescriptorError::UnexpectedCharacter(position, _)
+            | DescriptorError::EmptyVersion(position) => Some(*position),
+        }
+    }
+
+    /// Shift the position of this error by `offset` characters
+    fn offset(self, offset: usize) -> Self {
+        match self {
+            DescriptorError::Empty | DescriptorError::EmptyFlakeRef => self,
+            DescriptorError::EmptyInput(position) => DescriptorError::EmptyInput(position + offset),
+            DescriptorError::EmptyAttribute(position) => {
+                DescriptorError::EmptyAttribute(position + offset)
+            },
+            DescriptorError::UnterminatedQuote(position) => {
+                DescriptorError::UnterminatedQuote(position + offset)
+            },
+            DescriptorError::UnexpectedCharacter(position, c) => {
+                DescriptorError::UnexpectedCharacter(position + offset, c)
+            },
+            DescriptorError::EmptyVersion(position) => {
+                DescriptorError::EmptyVersion(position + offset)
+            },
+        }
+    }
 }
 
-/// A subset of the manifest used to check what type of edits users make. We
-/// don't use this struct for making our own edits.
+/// A typed representation of the manifest
 ///
-/// The authoritative form of the manifest is in
-/// https://github.com/flox/pkgdb/blob/main/include/flox/resolver/manifest-raw.hh#L263
-#[derive(Debug, Deserialize)]
+/// Mirrors `ManifestRaw` in pkgdb:
+/// https://github.com/flox/pkgdb/blob/main/include/flox/resolver/manifest-raw.hh
+///
+/// Parsing a manifest with [Manifest::from_str] catches most mistakes
+/// that would otherwise only be reported by pkgdb when locking the environment.
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
 pub struct Manifest {
-    pub vars: Option<toml::Table>,
-    pub hook: Option<toml::Table>,
-    #[serde(flatten)]
-    _toml: toml::Table,
+    #[serde(default, deserialize_with = "deserialize_checked")]
+    pub env_base: Option<ManifestEnvBase>,
+    #[serde(default, deserialize_with = "deserialize_install")]
+    pub install: Option<BTreeMap<String, ManifestPackageDescriptor>>,
+    pub vars: Option<BTreeMap<String, String>>,
+    pub profile: Option<ManifestProfile>,
+    #[serde(default, deserialize_with = "deserialize_checked")]
+    pub hook: Option<ManifestHook>,
+    #[serde(default, deserialize_with = "deserialize_services")]
+    pub services: Option<BTreeMap<String, ManifestService>>,
+    pub options: Option<ManifestOptions>,
+    /// Not yet modelled in detail
+    pub registry: Option<toml::Table>,
+}
+
+impl FromStr for Manifest {
+    type Err = ManifestError;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        toml_edit::de::from_str(contents).map_err(|err| ManifestError::Invalid {
+            message: err.message().trim_end().to_string(),
+            location: err
+                .span()
+                .map(|span| Location::from_offset(contents, span.start)),
+        })
+    }
+}
+
+/// The environment a manifest extends
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEnvBase {
+    pub floxhub: Option<String>,
+    pub dir: Option<String>,
+}
+
+/// A package in the `[install]` table
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ManifestPackageDescriptor {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub pkg_path: Option<ManifestAttrPath>,
+    pub abspath: Option<ManifestAttrPath>,
+    pub systems: Option<Vec<String>>,
+    pub optional: Option<bool>,
+    pub pkg_group: Option<String>,
+    pub package_repository: Option<ManifestPackageRepository>,
+    pub priority: Option<u64>,
+}
+
+/// An attribute path given either as dot-separated string or as list of attributes
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ManifestAttrPath {
+    Dotted(String),
+    Attributes(Vec<String>),
+}
+
+impl ManifestAttrPath {
+    /// The attributes of this path with quotes removed
+    pub fn attributes(&self) -> Vec<String> {
+        match self {
+            ManifestAttrPath::Dotted(path) => split_attr_path(path),
+            ManifestAttrPath::Attributes(attributes) => attributes.clone(),
+        }
+    }
+}
+
+/// The input a package is resolved from, either by name or as flake reference attributes
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ManifestPackageRepository {
+    Name(String),
+    Attrs(toml::Table),
+}
+
+/// Scripts sourced by the user's shell on activation
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestProfile {
+    pub common: Option<String>,
+    pub bash: Option<String>,
+    pub zsh: Option<String>,
+    pub fish: Option<String>,
+}
+
+/// Scripts run in a subshell on activation
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ManifestHook {
+    /// Deprecated in favor of `on-activate`
+    pub script: Option<String>,
+    pub on_activate: Option<String>,
+}
+
+/// A long running process started by `flox services start`
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ManifestService {
+    /// Shell command running the service in the foreground
+    pub command: String,
+    /// Variables set for the service in addition to `[vars]`
+    pub vars: Option<BTreeMap<String, String>>,
+    /// Shell command that succeeds once the service is ready
+    pub ready_check: Option<String>,
+    pub restart: Option<ManifestRestartPolicy>,
+}
+
+/// Whether a service is restarted when it exits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ManifestRestartPolicy {
+    #[default]
+    Never,
+    /// Restart if the service exits with a non-zero status or is killed
+    OnFailure,
+    Always,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ManifestOptions {
+    pub systems: Option<Vec<String>>,
+    pub allow: Option<ManifestAllows>,
+    pub semver: Option<ManifestSemverOptions>,
+    pub package_grouping_strategy: Option<String>,
+    pub activation_strategy: Option<String>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestAllows {
+    pub unfree: Option<bool>,
+    pub broken: Option<bool>,
+    pub licenses: Option<Vec<String>>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ManifestSemverOptions {
+    pub prefer_pre_releases: Option<bool>,
+}
+
+/// Consistency checks that can't be expressed by the structure of the manifest,
+/// equivalent to the `check()` methods in pkgdb.
+trait Check {
+    fn check(&self) -> Result<(), String>;
+}
+
+impl Check for ManifestEnvBase {
+    fn check(&self) -> Result<(), String> {
+        if self.floxhub.is_some() && self.dir.is_some() {
+            return Err(
+                "manifest may only define one of 'env-base.floxhub' or 'env-base.dir'".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Check for ManifestHook {
+    fn check(&self) -> Result<(), String> {
+        if self.script.is_some() && self.on_activate.is_some() {
+            return Err(
+                "hook may only define one of 'hook.script' or 'hook.on-activate'".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Check for ManifestService {
+    fn check(&self) -> Result<(), String> {
+        if self.command.trim().is_empty() {
+            return Err("service 'command' must not be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Check for ManifestPackageDescriptor {
+    fn check(&self) -> Result<(), String> {
+        let Some(ref abspath) = self.abspath else {
+            return Ok(());
+        };
+        let abspath = abspath.attributes();
+
+        if abspath.len() < 3 {
+            return Err("'abspath' must have at least three parts".to_string());
+        }
+        if !["packages", "legacyPackages"].contains(&abspath[0].as_str()) {
+            return Err("'abspath' must have a subtree as its first element".to_string());
+        }
+        if abspath[2..].iter().any(|attribute| attribute == "*") {
+            return Err("'abspath' may only have a glob as its second element".to_string());
+        }
+        if self.pkg_path.is_some() {
+            return Err("'pkg-path' conflicts with 'abspath'".to_string());
+        }
+        if let Some(ref systems) = self.systems {
+            if abspath[1] != "*" && !systems.contains(&abspath[1]) {
+                return Err(
+                    "'systems' list conflicts with 'abspath' system specification".to_string(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deserialize an optional value and run its [Check]s
+///
+/// Errors are reported at the location of the value.
+fn deserialize_checked<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Check,
+{
+    let value = Option::<T>::deserialize(deserializer)?;
+    if let Some(ref value) = value {
+        value.check().map_err(serde::de::Error::custom)?;
+    }
+    Ok(value)
+}
+
+/// Deserialize the `[install]` table, checking each descriptor individually
+fn deserialize_install<'de, D>(
+    deserializer: D,
+) -> Result<Option<BTreeMap<String, ManifestPackageDescriptor>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Checked(
+        #[serde(deserialize_with = "deserialize_checked")] Option<ManifestPackageDescriptor>,
+    );
+
+    let install = Option::<BTreeMap<String, Checked>>::deserialize(deserializer)?;
+    Ok(install.map(|install| {
+        install
+            .into_iter()
+            .map(|(id, Checked(descriptor))| (id, descriptor.unwrap_or_default()))
+            .collect()
+    }))
+}
+
+/// Deserialize the `[services]` table, checking each service individually
+fn deserialize_services<'de, D>(
+    deserializer: D,
+) -> Result<Option<BTreeMap<String, ManifestService>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Checked(#[serde(deserialize_with = "deserialize_checked")] Option<ManifestService>);
+
+    let services = Option::<BTreeMap<String, Checked>>::deserialize(deserializer)?;
+    Ok(services.map(|services| {
+        services
+            .into_iter()
+            .filter_map(|(name, Checked(service))| Some((name, service?)))
+            .collect()
+    }))
+}
+
+/// Split a dot-separated attribute path, removing quotes from quoted attributes
+///
+/// Malformed attributes are kept verbatim, pkgdb reports them when locking.
+fn split_attr_path(path: &str) -> Vec<String> {
+    let chars = path.chars().collect::<Vec<_>>();
+    let mut attributes = Vec::new();
+    let mut position = 0;
+    while let Ok((attribute, next)) = parse_attribute(&chars, position) {
+        attributes.push(attribute);
+        match chars.get(next) {
+            Some('.') => position = next + 1,
+            _ => return attributes,
+        }
+    }
+    vec![path.to_string()]
 }
 
 /// An error encountered while installing packages.
@@ -50,6 +423,8 @@ pub enum TomlEditError {
     MalformedOptionsTable(String),
     #[error("'options' must be an array, but found {0} instead")]
     MalformedOptionsSystemsArray(String),
+    #[error("'registry' and 'registry.inputs' must be tables, but found {0} instead")]
+    MalformedRegistryTable(String),
 }
 
 /// Records the result of trying to install a collection of packages to the
@@ -70,14 +445,25 @@ pub struct PackageToInstall {
     pub id: String,
     pub pkg_path: String,
     pub version: Option<String>,
+    /// Either the name of a registry input or a flake reference,
+    /// which [insert_packages] adds to the registry of the manifest
     pub input: Option<String>,
 }
 
 impl FromStr for PackageToInstall {
     type Err = ManifestError;
 
+    /// Parse either a flake installable or a shorthand descriptor
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        temporary_parse_descriptor(s)
+        let parsed = if is_flake_installable(s) {
+            parse_flake_installable(s)
+        } else {
+            parse_descriptor(s)
+        };
+        parsed.map_err(|err| ManifestError::MalformedStringDescriptor {
+            desc: s.to_string(),
+            err,
+        })
     }
 }
 
@@ -87,6 +473,9 @@ impl FromStr for PackageToInstall {
 /// that should be interpreted as relative paths under whatever input they're
 /// coming from. For this reason we put them under the `<descriptor>.path` key
 /// rather than `<descriptor>.name`.
+///
+/// Packages installed from a flake reference get a named input in the
+/// `[registry]` of the manifest, see [insert_registry_input].
 pub fn insert_packages(
     manifest_contents: &str,
     pkgs: &[PackageToInstall],
@@ -97,6 +486,18 @@ pub fn insert_packages(
         .parse::<Document>()
         .map_err(TomlEditError::ParseManifest)?;
 
+    // Flake references of new packages mapped to the names of their inputs
+    let mut registry_inputs: HashMap<&str, String> = HashMap::new();
+    for pkg in pkgs {
+        let Some(flake_ref) = pkg.input.as_deref().filter(|input| is_flake_ref(input)) else {
+            continue;
+        };
+        if !registry_inputs.contains_key(flake_ref) && !contains_package(&toml, &pkg.id)? {
+            let name = insert_registry_input(&mut toml, flake_ref)?;
+            registry_inputs.insert(flake_ref, name);
+        }
+    }
+
     let install_table = {
         let install_field = toml
             .entry("install")
@@ -119,7 +520,11 @@ pub fn insert_packages(
                 descriptor_table.insert("version", Value::String(Formatted::new(version.clone())));
             }
             if let Some(ref input) = pkg.input {
-                descriptor_table.insert("input", Value::String(Formatted::new(input.clone())));
+                let input = registry_inputs.get(input.as_str()).unwrap_or(input);
+                descriptor_table.insert(
+                    "package-repository",
+                    Value::String(Formatted::new(input.clone())),
+                );
             }
             descriptor_table.set_dotted(true);
             install_table.insert(&pkg.id, Item::Value(Value::InlineTable(descriptor_table)));
@@ -144,6 +549,114 @@ pub fn insert_packages(
     })
 }
 
+/// Whether a package input is a flake reference rather than the name of a
+/// registry input
+///
+/// Input names are bare attribute names, which can't contain any of
+/// the characters separating the parts of a URL or a path.
+/// Indirect flake references such as `nixpkgs` in `nixpkgs#hello`
+/// are bare identifiers too, so [parse_flake_installable] records them
+/// in their explicit `flake:<id>` form.
+fn is_flake_ref(input: &str) -> bool {
+    input.contains([':', '/', '.'])
+}
+
+/// Names of the inputs of the GA registry,
+/// which inputs added for flake references must not shadow
+const GA_INPUT_NAMES: [&str; 1] = ["nixpkgs"];
+
+/// Add an input for `flake_ref` to the `[registry.inputs]` of a manifest
+///
+/// Returns the name of the input, which is derived from the flake reference,
+/// e.g. `tools` for `github:our-org/tools`.
+/// An existing input with the same flake reference is reused,
+/// while an existing input with a different flake reference but the same name
+/// causes a numeric suffix to be added to the name.
+pub fn insert_registry_input(
+    toml: &mut Document,
+    flake_ref: &str,
+) -> Result<String, TomlEditError> {
+    let implicit_table = || {
+        let mut table = Table::new();
+        table.set_implicit(true);
+        Item::Table(table)
+    };
+
+    let registry = toml.entry("registry").or_insert_with(implicit_table);
+    let registry_type = registry.type_name().into();
+    let inputs = registry
+        .as_table_like_mut()
+        .ok_or(TomlEditError::MalformedRegistryTable(registry_type))?
+        .entry("inputs")
+        .or_insert_with(implicit_table);
+    let inputs_type = inputs.type_name().into();
+    let inputs = inputs
+        .as_table_like_mut()
+        .ok_or(TomlEditError::MalformedRegistryTable(inputs_type))?;
+
+    if let Some((name, _)) = inputs
+        .iter()
+        .find(|(_, input)| input.get("from").and_then(Item::as_str) == Some(flake_ref))
+    {
+        debug!("reusing registry input '{name}' for {flake_ref}");
+        return Ok(name.to_string());
+    }
+
+    let base_name = flake_input_name(flake_ref);
+    let name = std::iter::once(base_name.clone())
+        .chain((2..).map(|suffix| format!("{base_name}-{suffix}")))
+        .find(|name| !inputs.contains_key(name) && !GA_INPUT_NAMES.contains(&name.as_str()))
+        .expect("an unused name is eventually found");
+
+    let mut input = Table::new();
+    input.insert("from", toml_edit::value(flake_ref));
+    inputs.insert(&name, Item::Table(input));
+    debug!("added registry input '{name}' for {flake_ref}");
+    Ok(name)
+}
+
+/// Derive the name of a registry input from a flake reference
+///
+/// This is the repository name for `github:`, `gitlab:` and `sourcehut:`
+/// references, and the last path component otherwise,
+/// without extensions such as `.git` or `.tar.gz`.
+fn flake_input_name(flake_ref: &str) -> String {
+    let url = flake_ref.split(['?', '#']).next().unwrap_or_default();
+    let (scheme, path) = match url.split_once(':') {
+        Some((scheme, path)) if !scheme.contains('/') => (scheme, path),
+        _ => ("path", url),
+    };
+    let mut segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..");
+    let segment = match scheme {
+        "github" | "gitlab" | "sourcehut" => segments.nth(1),
+        _ => segments.next_back(),
+    }
+    .unwrap_or_default();
+    let segment = [".git", ".tar.gz", ".tar.xz", ".tar.bz2", ".tgz", ".zip"]
+        .iter()
+        .find_map(|extension| segment.strip_suffix(extension))
+        .unwrap_or(segment);
+
+    let name = segment
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    let name = name.trim_matches('-');
+    if name.is_empty() {
+        "flake".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
 /// Remove package names from the `[install]` table of a manifest
 pub fn remove_packages(
     manifest_contents: &str,
@@ -239,92 +752,225 @@ pub fn add_system(toml: &str, system: &str) -> Result<Document, TomlEditError> {
     Ok(doc)
 }
 
-/// A parsed descriptor from `pkgdb parse descriptor --manifest`
+/// Parse a shorthand descriptor into structured data
 ///
-/// FIXME: this is currently a hack using a tool in `pkgdb` only meant for debugging.
-#[derive(Debug, Deserialize)]
-pub struct ParsedDescriptor {
-    pub name: Option<String>,
-    #[serde(rename = "pkg-path")]
-    pub pkg_path: Option<Vec<String>>,
-    pub input: Option<Input>,
-    pub version: Option<String>,
-    pub semver: Option<String>,
+/// Descriptors have the form `[<input>:]<attr-path>[@<version>]`:
+///
+/// - `<input>` names the input to search for the package.
+/// - `<attr-path>` is a dot-separated attribute path,
+///   components containing special characters may be quoted, e.g. `rubyPackages."http_parser.rb"`.
+///   The last component becomes the install ID.
+/// - `<version>` is either an exact version prefixed with `=`, e.g. `=1.2.3`,
+///   or a semver range, e.g. `^1.2`.
+///
+/// Attribute path components are re-quoted where necessary,
+/// such that the `pkg-path` written by [insert_packages] parses to the same attribute path.
+pub fn parse_descriptor(descriptor: &str) -> Result<PackageToInstall, DescriptorError> {
+    let chars = descriptor.chars().collect::<Vec<_>>();
+    if chars.is_empty() {
+        return Err(DescriptorError::Empty);
+    }
+
+    let mut position = 0;
+
+    let input = match find_input_separator(&chars) {
+        Some(0) => return Err(DescriptorError::EmptyInput(0)),
+        Some(separator) => {
+            if let Some((offset, c)) = chars[..separator]
+                .iter()
+                .enumerate()
+                .find(|(_, c)| !is_bare_attribute_char(**c))
+            {
+                return Err(DescriptorError::UnexpectedCharacter(offset, *c));
+            }
+            position = separator + 1;
+            Some(chars[..separator].iter().collect::<String>())
+        },
+        None => None,
+    };
+
+    let mut attr_path = Vec::new();
+    loop {
+        let (attribute, next) = parse_attribute(&chars, position)?;
+        attr_path.push(attribute);
+        position = next;
+        match chars.get(position) {
+            None | Some('@') => break,
+            Some('.') => position += 1,
+            Some(c) => return Err(DescriptorError::UnexpectedCharacter(position, *c)),
+        }
+    }
+
+    let version = match chars.get(position) {
+        Some('@') => {
+            let version = chars[position + 1..].iter().collect::<String>();
+            match version.strip_prefix('=') {
+                None if version.is_empty() => {
+                    return Err(DescriptorError::EmptyVersion(position + 1))
+                },
+                Some("") => return Err(DescriptorError::EmptyVersion(position + 2)),
+                _ => Some(version),
+            }
+        },
+        _ => None,
+    };
+
+    let attr_path = attr_path
+        .iter()
+        .map(|attribute| quote_attribute(attribute))
+        .collect::<Vec<_>>();
+    let id = attr_path
+        .last()
+        .cloned()
+        .expect("attribute path has at least one component");
+
+    Ok(PackageToInstall {
+        id,
+        pkg_path: attr_path.join("."),
+        version,
+        input,
+    })
+}
+
+/// Whether a package argument is a flake installable, i.e. contains a `#`
+/// outside of a quoted attribute name
+pub fn is_flake_installable(installable: &str) -> bool {
+    installable
+        .find('#')
+        .is_some_and(|separator| !installable[..separator].contains('"'))
 }
 
-/// A parsed input
+/// Parse a flake installable into structured data
 ///
-/// FIXME: this is currently a hack using a tool in `pkgdb` only meant for debugging.
-#[derive(Debug, Deserialize, PartialEq)]
-pub struct Input {
-    pub id: String,
+/// Flake installables have the form `<flake-ref>#[<attr-path>[@<version>]]`,
+/// e.g. `github:our-org/tools#linter`:
+///
+/// - `<flake-ref>` becomes the input of the package,
+///   which [insert_packages] adds to the registry of the manifest.
+///   A bare identifier, e.g. `myflake` in `myflake#tool`, is an indirect
+///   flake reference and becomes `flake:myflake`.
+/// - The fragment is parsed like a shorthand descriptor without an input,
+///   see [parse_descriptor].
+///   An empty fragment installs the `default` package of the flake,
+///   which is installed with the name of its input as install ID.
+pub fn parse_flake_installable(installable: &str) -> Result<PackageToInstall, DescriptorError> {
+    let (flake_ref, fragment) = installable
+        .split_once('#')
+        .ok_or(DescriptorError::EmptyFlakeRef)?;
+    if flake_ref.is_empty() {
+        return Err(DescriptorError::EmptyFlakeRef);
+    }
+    let offset = flake_ref.chars().count() + 1;
+    let flake_ref = if is_flake_ref(flake_ref) {
+        flake_ref.to_string()
+    } else {
+        format!("flake:{flake_ref}")
+    };
+
+    if fragment.is_empty() {
+        return Ok(PackageToInstall {
+            id: flake_input_name(&flake_ref),
+            pkg_path: "default".to_string(),
+            version: None,
+            input: Some(flake_ref),
+        });
+    }
+
+    let package = parse_descriptor(fragment).map_err(|err| err.offset(offset))?;
+    if let Some(input) = package.input {
+        return Err(DescriptorError::UnexpectedCharacter(
+            offset + input.chars().count(),
+            ':',
+        ));
+    }
+    Ok(PackageToInstall {
+        input: Some(flake_ref),
+        ..package
+    })
 }
 
-/// Parse a shorthand descriptor into structured data
+/// Characters that may appear in unquoted attribute names and input names
+fn is_bare_attribute_char(c: char) -> bool {
+    !matches!(c, '.' | '"' | ':' | '@') && !c.is_whitespace()
+}
+
+/// Find the `:` separating the input from the attribute path
+///
+/// Only a `:` outside of quotes and before the version counts as separator.
+fn find_input_separator(chars: &[char]) -> Option<usize> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (position, c) in chars.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => return Some(position),
+            '@' if !in_quotes => return None,
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Parse a single, possibly quoted, attribute name starting at `start`
 ///
-/// FIXME: this is currently a hack using a tool in `pkgdb` only meant for debugging.
-pub fn temporary_parse_descriptor(descriptor: &str) -> Result<PackageToInstall, ManifestError> {
-    let output = Command::new(&*PKGDB_BIN)
-        .arg("parse")
-        .arg("descriptor")
-        .arg("--to")
-        .arg("manifest")
-        .arg(descriptor)
-        .output()
-        .map_err(ManifestError::PkgDbCall)?;
-    let parsed: Result<ParsedDescriptor, _> = serde_json::from_slice(&output.stdout);
-    if let Ok(parsed) = parsed {
-        let (id, path) = if let Some(mut path) = parsed.pkg_path {
-            // Quote any path components that need quoting
-            path.iter_mut().for_each(|attr| {
-                if attr.contains('.') || attr.contains('"') {
-                    *attr = format!("\"{}\"", attr);
+/// Returns the unquoted attribute name and the position following it.
+fn parse_attribute(chars: &[char], start: usize) -> Result<(String, usize), DescriptorError> {
+    match chars.get(start) {
+        Some('"') => {
+            let mut attribute = String::new();
+            let mut position = start + 1;
+            loop {
+                match chars.get(position) {
+                    None => return Err(DescriptorError::UnterminatedQuote(start)),
+                    Some('"') => break,
+                    Some('\\') => match chars.get(position + 1) {
+                        Some(escaped) => {
+                            attribute.push(*escaped);
+                            position += 2;
+                        },
+                        None => return Err(DescriptorError::UnterminatedQuote(start)),
+                    },
+                    Some(c) => {
+                        attribute.push(*c);
+                        position += 1;
+                    },
                 }
-            });
-            let id_part = path.last().cloned().map(Ok).unwrap_or_else(|| {
-                Err(ManifestError::MalformedStringDescriptor {
-                    msg: "descriptor had an empty path".to_string(),
-                    desc: descriptor.to_string(),
-                })
-            })?;
-            (id_part, path.join("."))
-        } else if let Some(name) = parsed.name {
-            (name.clone(), name)
-        } else {
-            // This should have been caught by `pkgdb parse descriptor`,
-            // it's more likely that if we hit this we got unexpected output
-            // that we didn't know how to parse. It's possible, but unlikely,
-            // and something ignored for the sake of time when we can write
-            // a parser in `flox` instead of leaning on `pkgdb` debugging tools.
-            return Err(ManifestError::MalformedStringDescriptor {
-                msg: "descriptor had no name or path".to_string(),
-                desc: descriptor.to_string(),
-            });
-        };
-        let version = if let Some(version) = parsed.version {
-            let mut v = "=".to_string();
-            v.push_str(&version);
-            Some(v)
-        } else {
-            parsed.semver
-        };
-        let input = parsed.input.map(|input| input.id);
-        Ok(PackageToInstall {
-            id,
-            pkg_path: path,
-            version,
-            input,
-        })
-    } else {
-        Err(ManifestError::MalformedStringDescriptor {
-            msg: String::from_utf8_lossy(&output.stdout).to_string(),
-            desc: descriptor.to_string(),
-        })
+            }
+            if attribute.is_empty() {
+                return Err(DescriptorError::EmptyAttribute(start));
+            }
+            Ok((attribute, position + 1))
+        },
+        None | Some('.') | Some('@') => Err(DescriptorError::EmptyAttribute(start)),
+        Some(c) if !is_bare_attribute_char(*c) => {
+            Err(DescriptorError::UnexpectedCharacter(start, *c))
+        },
+        Some(_) => {
+            let end = chars[start..]
+                .iter()
+                .position(|c| !is_bare_attribute_char(*c))
+                .map_or(chars.len(), |offset| start + offset);
+            Ok((chars[start..end].iter().collect(), end))
+        },
+    }
+}
+
+/// Quote an attribute name if it contains characters
+/// that can't appear in a bare attribute name
+fn quote_attribute(attribute: &str) -> String {
+    if attribute.chars().all(is_bare_attribute_char) {
+        return attribute.to_string();
     }
+    let escaped = attribute.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{escaped}\"")
 }
 
 #[cfg(test)]
 mod test {
+    use indoc::indoc;
+
     use super::*;
 
     const DUMMY_MANIFEST: &str = r#"
@@ -458,29 +1104,28 @@ ripgrep = {}
 
     #[test]
     fn parses_string_descriptor() {
-        // FIXME: remove or update this test when `flox` can parse descriptors on its own
-        let parsed = temporary_parse_descriptor("hello").unwrap();
+        let parsed = parse_descriptor("hello").unwrap();
         assert_eq!(parsed, PackageToInstall {
             id: "hello".to_string(),
             pkg_path: "hello".to_string(),
             version: None,
             input: None,
         });
-        let parsed = temporary_parse_descriptor("nixpkgs:foo.bar@=1.2.3").unwrap();
+        let parsed = parse_descriptor("nixpkgs:foo.bar@=1.2.3").unwrap();
         assert_eq!(parsed, PackageToInstall {
             id: "bar".to_string(),
             pkg_path: "foo.bar".to_string(),
             version: Some("=1.2.3".to_string()),
             input: Some("nixpkgs".to_string())
         });
-        let parsed = temporary_parse_descriptor("nixpkgs:foo.bar@23.11").unwrap();
+        let parsed = parse_descriptor("nixpkgs:foo.bar@23.11").unwrap();
         assert_eq!(parsed, PackageToInstall {
             id: "bar".to_string(),
             pkg_path: "foo.bar".to_string(),
             version: Some("23.11".to_string()),
             input: Some("nixpkgs".to_string())
         });
-        let parsed = temporary_parse_descriptor("nixpkgs:rubyPackages.\"http_parser.rb\"").unwrap();
+        let parsed = parse_descriptor("nixpkgs:rubyPackages.\"http_parser.rb\"").unwrap();
         assert_eq!(parsed, PackageToInstall {
             id: "\"http_parser.rb\"".to_string(),
             pkg_path: "rubyPackages.\"http_parser.rb\"".to_string(),
@@ -488,4 +1133,437 @@ ripgrep = {}
             input: Some("nixpkgs".to_string())
         });
     }
+
+    #[test]
+    fn parses_semver_ranges() {
+        let parsed = parse_descriptor("python3@^3.11").unwrap();
+        assert_eq!(parsed.version.as_deref(), Some("^3.11"));
+        let parsed = parse_descriptor("python3@>=3.10 <3.12").unwrap();
+        assert_eq!(parsed.version.as_deref(), Some(">=3.10 <3.12"));
+    }
+
+    #[test]
+    fn parses_special_characters_in_quotes() {
+        let parsed = parse_descriptor(r#"foo."a:b@c d".baz@1"#).unwrap();
+        assert_eq!(parsed.pkg_path, r#"foo."a:b@c d".baz"#);
+        assert_eq!(parsed.id, "baz");
+        assert_eq!(parsed.version.as_deref(), Some("1"));
+        assert_eq!(parsed.input, None);
+    }
+
+    #[test]
+    fn unquotes_unnecessarily_quoted_attributes() {
+        let parsed = parse_descriptor(r#""foo"."bar""#).unwrap();
+        assert_eq!(parsed.pkg_path, "foo.bar");
+        assert_eq!(parsed.id, "bar");
+    }
+
+    #[test]
+    fn parses_escaped_quotes() {
+        let parsed = parse_descriptor(r#"foo."say \"hi\"""#).unwrap();
+        assert_eq!(parsed.pkg_path, r#"foo."say \"hi\"""#);
+        assert_eq!(parsed.id, r#""say \"hi\"""#);
+    }
+
+    #[test]
+    fn quoted_paths_roundtrip_through_insert_packages() {
+        for descriptor in [
+            "hello",
+            "nixpkgs:foo.bar@=1.2.3",
+            r#"rubyPackages."http_parser.rb""#,
+            r#"foo."say \"hi\"".bar"#,
+            r#"foo."back\\slash""#,
+        ] {
+            let parsed = parse_descriptor(descriptor).unwrap();
+            let insertion = insert_packages("", &[parsed]).unwrap();
+            let toml = insertion.new_toml.unwrap();
+            let (id, descriptor_table) = toml["install"].as_table().unwrap().iter().next().unwrap();
+            let pkg_path = descriptor_table["pkg-path"].as_str().unwrap();
+
+            let reparsed = parse_descriptor(pkg_path).unwrap();
+            assert_eq!(reparsed.pkg_path, pkg_path, "{descriptor}");
+            assert_eq!(reparsed.id, id, "{descriptor}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_descriptors() {
+        for (descriptor, expected) in [
+            ("", DescriptorError::Empty),
+            (":hello", DescriptorError::EmptyInput(0)),
+            ("nixpkgs:", DescriptorError::EmptyAttribute(8)),
+            ("foo..bar", DescriptorError::EmptyAttribute(4)),
+            ("foo.", DescriptorError::EmptyAttribute(4)),
+            (".foo", DescriptorError::EmptyAttribute(0)),
+            ("@1.0", DescriptorError::EmptyAttribute(0)),
+            (r#"foo."""#, DescriptorError::EmptyAttribute(4)),
+            ("foo@", DescriptorError::EmptyVersion(4)),
+            ("foo@=", DescriptorError::EmptyVersion(5)),
+            (r#"foo."bar"#, DescriptorError::UnterminatedQuote(4)),
+            (r#""foo\"#, DescriptorError::UnterminatedQuote(0)),
+            ("foo bar", DescriptorError::UnexpectedCharacter(3, ' ')),
+            ("a:b:c", DescriptorError::UnexpectedCharacter(3, ':')),
+            (r#"foo"bar""#, DescriptorError::UnexpectedCharacter(3, '"')),
+            (r#""foo"bar"#, DescriptorError::UnexpectedCharacter(5, 'b')),
+            (
+                "nix.pkgs:hello",
+                DescriptorError::UnexpectedCharacter(3, '.'),
+            ),
+            (
+                "nix pkgs:hello",
+                DescriptorError::UnexpectedCharacter(3, ' '),
+            ),
+        ] {
+            assert_eq!(parse_descriptor(descriptor), Err(expected), "{descriptor}");
+        }
+    }
+
+    #[test]
+    fn parses_flake_installables() {
+        let parsed = PackageToInstall::from_str("github:our-org/tools#linter").unwrap();
+        assert_eq!(parsed, PackageToInstall {
+            id: "linter".to_string(),
+            pkg_path: "linter".to_string(),
+            version: None,
+            input: Some("github:our-org/tools".to_string()),
+        });
+        let parsed = PackageToInstall::from_str("./tools#go.linters.lint@^1.2").unwrap();
+        assert_eq!(parsed, PackageToInstall {
+            id: "lint".to_string(),
+            pkg_path: "go.linters.lint".to_string(),
+            version: Some("^1.2".to_string()),
+            input: Some("./tools".to_string()),
+        });
+        let parsed = PackageToInstall::from_str("git+https://example.com/tools.git#").unwrap();
+        assert_eq!(parsed, PackageToInstall {
+            id: "tools".to_string(),
+            pkg_path: "default".to_string(),
+            version: None,
+            input: Some("git+https://example.com/tools.git".to_string()),
+        });
+        assert!(!is_flake_installable(r#"foo."bar#baz""#));
+    }
+
+    #[test]
+    fn parses_indirect_flake_installables() {
+        let parsed = PackageToInstall::from_str("myflake#tool").unwrap();
+        assert_eq!(parsed, PackageToInstall {
+            id: "tool".to_string(),
+            pkg_path: "tool".to_string(),
+            version: None,
+            input: Some("flake:myflake".to_string()),
+        });
+        let parsed = PackageToInstall::from_str("myflake#").unwrap();
+        assert_eq!(parsed.id, "myflake");
+        assert_eq!(parsed.input.as_deref(), Some("flake:myflake"));
+
+        let packages = [
+            PackageToInstall::from_str("nixpkgs#hello").unwrap(),
+            PackageToInstall::from_str("myflake#tool").unwrap(),
+        ];
+        let toml = insert_packages("", &packages).unwrap().new_toml.unwrap();
+        let inputs = toml["registry"]["inputs"].as_table().unwrap();
+        assert_eq!(inputs["nixpkgs-2"]["from"].as_str(), Some("flake:nixpkgs"));
+        assert_eq!(inputs["myflake"]["from"].as_str(), Some("flake:myflake"));
+        assert_eq!(
+            toml["install"]["hello"]["package-repository"].as_str(),
+            Some("nixpkgs-2")
+        );
+        assert_eq!(
+            toml["install"]["tool"]["package-repository"].as_str(),
+            Some("myflake")
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_flake_installables() {
+        for (installable, expected) in [
+            ("#hello", DescriptorError::EmptyFlakeRef),
+            ("github:a/b#foo..bar", DescriptorError::EmptyAttribute(15)),
+            (
+                "github:a/b#nixpkgs:hello",
+                DescriptorError::UnexpectedCharacter(18, ':'),
+            ),
+        ] {
+            assert_eq!(
+                parse_flake_installable(installable),
+                Err(expected),
+                "{installable}"
+            );
+        }
+    }
+
+    #[test]
+    fn derives_flake_input_names() {
+        for (flake_ref, expected) in [
+            ("github:our-org/tools", "tools"),
+            ("github:our-org/tools/v1.2?dir=sub", "tools"),
+            ("sourcehut:~user/tools", "tools"),
+            ("git+ssh://git@example.com/our-org/tools.git", "tools"),
+            ("https://example.com/tools-1.0.tar.gz", "tools-1-0"),
+            ("/home/user/my.tools/", "my-tools"),
+            ("path:.", "flake"),
+            ("flake:myflake", "myflake"),
+        ] {
+            assert_eq!(flake_input_name(flake_ref), expected, "{flake_ref}");
+        }
+    }
+
+    #[test]
+    fn inserts_registry_inputs_for_flakes() {
+        let manifest = indoc! {r#"
+            [install]
+
+            [registry.inputs.tools]
+            from = "github:other-org/tools"
+        "#};
+        let packages = [
+            PackageToInstall::from_str("github:our-org/tools#linter").unwrap(),
+            PackageToInstall::from_str("github:our-org/tools#formatter").unwrap(),
+            PackageToInstall::from_str("github:other-org/tools#tester").unwrap(),
+            PackageToInstall::from_str("nixpkgs:hello").unwrap(),
+        ];
+        let toml = insert_packages(manifest, &packages)
+            .unwrap()
+            .new_toml
+            .unwrap();
+
+        let inputs = toml["registry"]["inputs"].as_table().unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(
+            inputs["tools-2"]["from"].as_str(),
+            Some("github:our-org/tools")
+        );
+        for (id, input) in [
+            ("linter", "tools-2"),
+            ("formatter", "tools-2"),
+            ("tester", "tools"),
+            ("hello", "nixpkgs"),
+        ] {
+            assert_eq!(
+                toml["install"][id]["package-repository"].as_str(),
+                Some(input),
+                "{id}"
+            );
+        }
+        assert!(Manifest::from_str(&toml.to_string()).is_ok());
+    }
+
+    #[test]
+    fn error_positions_count_characters() {
+        let err = parse_descriptor("föö bar").unwrap_err();
+        assert_eq!(err, DescriptorError::UnexpectedCharacter(3, ' '));
+        assert_eq!(err.position(), Some(3));
+    }
+
+    #[test]
+    fn from_str_reports_descriptor() {
+        let err = PackageToInstall::from_str("foo@").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "couldn't parse descriptor 'foo@': expected a version after '@' at position 4"
+        );
+    }
+
+    #[test]
+    fn parses_complete_manifest() {
+        let manifest = Manifest::from_str(indoc! {r#"
+            [install]
+            hello.pkg-path = "hello"
+            python = { pkg-path = ["python311Packages", "python"], version = "^3.11", priority = 3 }
+            rg = { abspath = "legacyPackages.*.ripgrep", systems = ["x86_64-linux"] }
+
+            [vars]
+            FOO = "bar"
+
+            [profile]
+            common = "echo common"
+            zsh = "echo zsh"
+            fish = "echo fish"
+
+            [hook]
+            on-activate = "echo hook"
+
+            [services.postgres]
+            command = "postgres -D $FLOX_ENV_CACHE/postgres"
+            vars = { PGPORT = "15432" }
+            ready-check = "pg_isready"
+            restart = "on-failure"
+
+            [options]
+            systems = ["x86_64-linux", "aarch64-darwin"]
+            allow.unfree = true
+            allow.licenses = ["MIT"]
+            semver.prefer-pre-releases = false
+            package-grouping-strategy = "auto-merge"
+            activation-strategy = "default"
+        "#})
+        .unwrap();
+
+        let install = manifest.install.unwrap();
+        assert_eq!(
+            install["hello"].pkg_path,
+            Some(ManifestAttrPath::Dotted("hello".to_string()))
+        );
+        assert_eq!(install["python"].priority, Some(3));
+        assert_eq!(install["rg"].abspath.as_ref().unwrap().attributes(), vec![
+            "legacyPackages",
+            "*",
+            "ripgrep"
+        ]);
+        assert_eq!(manifest.vars.unwrap()["FOO"], "bar");
+        let profile = manifest.profile.unwrap();
+        assert_eq!(profile.zsh.as_deref(), Some("echo zsh"));
+        assert_eq!(profile.fish.as_deref(), Some("echo fish"));
+        assert_eq!(
+            manifest.hook.unwrap().on_activate.as_deref(),
+            Some("echo hook")
+        );
+        let postgres = &manifest.services.unwrap()["postgres"];
+        assert_eq!(postgres.ready_check.as_deref(), Some("pg_isready"));
+        assert_eq!(postgres.restart, Some(ManifestRestartPolicy::OnFailure));
+        assert_eq!(postgres.vars.as_ref().unwrap()["PGPORT"], "15432");
+        let options = manifest.options.unwrap();
+        assert_eq!(options.allow.unwrap().unfree, Some(true));
+        assert_eq!(options.semver.unwrap().prefer_pre_releases, Some(false));
+        assert_eq!(options.activation_strategy.as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn parses_empty_manifest() {
+        assert_eq!(Manifest::from_str("").unwrap(), Manifest::default());
+    }
+
+    /// Parse `contents` expecting an error and return its message and location
+    fn invalid(contents: &str) -> (String, Option<(usize, usize)>) {
+        match Manifest::from_str(contents) {
+            Err(ManifestError::Invalid { message, location }) => (
+                message,
+                location.map(|Location { line, column }| (line, column)),
+            ),
+            other => panic!("expected an invalid manifest, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_location_of_unknown_fields() {
+        let (message, location) = invalid(indoc! {r#"
+            [install]
+            hello.pkg-path = "hello"
+
+            [optoins]
+            systems = []
+        "#});
+        assert!(message.starts_with("unknown field `optoins`"), "{message}");
+        assert_eq!(location, Some((4, 2)));
+
+        let (message, location) = invalid(indoc! {r#"
+            [install]
+            hello.pkg-pth = "hello"
+        "#});
+        assert!(message.starts_with("unknown field `pkg-pth`"), "{message}");
+        assert_eq!(location, Some((2, 7)));
+    }
+
+    #[test]
+    fn reports_location_of_invalid_values() {
+        let (message, location) = invalid(indoc! {r#"
+            [vars]
+            FOO = 1
+        "#});
+        assert!(message.contains("invalid type: integer `1`"), "{message}");
+        assert_eq!(location, Some((2, 7)));
+
+        let (message, location) = invalid(indoc! {r#"
+            [options]
+            allow.unfree = "yes"
+        "#});
+        assert!(message.contains("expected a boolean"), "{message}");
+        assert_eq!(location, Some((2, 16)));
+    }
+
+    #[test]
+    fn reports_toml_syntax_errors() {
+        let (_, location) = invalid(indoc! {r#"
+            [install]
+            hello.pkg-path = "hello
+        "#});
+        assert_eq!(location.map(|(line, _)| line), Some(2));
+    }
+
+    #[test]
+    fn rejects_conflicting_hooks() {
+        let (message, location) = invalid(indoc! {r#"
+            [hook]
+            script = "echo script"
+            on-activate = "echo on-activate"
+        "#});
+        assert_eq!(
+            message,
+            "hook may only define one of 'hook.script' or 'hook.on-activate'"
+        );
+        assert_eq!(location.map(|(line, _)| line), Some(1));
+    }
+
+    #[test]
+    fn rejects_invalid_services() {
+        let (message, location) = invalid(indoc! {r#"
+            [services.postgres]
+            command = " "
+        "#});
+        assert_eq!(message, "service 'command' must not be empty");
+        assert_eq!(location.map(|(line, _)| line), Some(1));
+
+        let (message, _) = invalid(indoc! {r#"
+            [services.postgres]
+            command = "postgres"
+            restart = "sometimes"
+        "#});
+        assert!(message.contains("unknown variant `sometimes`"), "{message}");
+    }
+
+    #[test]
+    fn rejects_conflicting_env_base() {
+        let (message, _) = invalid(indoc! {r#"
+            [env-base]
+            floxhub = "owner/name"
+            dir = "../base"
+        "#});
+        assert_eq!(
+            message,
+            "manifest may only define one of 'env-base.floxhub' or 'env-base.dir'"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_abspaths() {
+        for (descriptor, expected) in [
+            (
+                r#"abspath = "legacyPackages.hello""#,
+                "'abspath' must have at least three parts",
+            ),
+            (
+                r#"abspath = "foo.x86_64-linux.hello""#,
+                "'abspath' must have a subtree as its first element",
+            ),
+            (
+                r#"abspath = "packages.*.*""#,
+                "'abspath' may only have a glob as its second element",
+            ),
+            (
+                r#"abspath = "packages.*.hello", pkg-path = "hello""#,
+                "'pkg-path' conflicts with 'abspath'",
+            ),
+            (
+                r#"abspath = "packages.x86_64-linux.hello", systems = ["aarch64-darwin"]"#,
+                "'systems' list conflicts with 'abspath' system specification",
+            ),
+        ] {
+            let (message, location) = invalid(&format!(
+                "[install]\nfirst = {{}}\nhello = {{ {descriptor} }}\n"
+            ));
+            assert_eq!(message, expected, "{descriptor}");
+            assert_eq!(location, Some((3, 9)), "{descriptor}");
+        }
+    }
 }
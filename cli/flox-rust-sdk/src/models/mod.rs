@@ -1,5 +1,6 @@
 //# An attempt at defining a domain model for flox
 pub mod container_builder;
+pub mod daemon;
 pub mod environment;
 pub mod environment_ref;
 pub mod floxmetav2;
@@ -7,3 +8,4 @@ pub mod lockfile;
 pub mod manifest;
 pub mod pkgdb;
 pub mod search;
+pub mod search_index;
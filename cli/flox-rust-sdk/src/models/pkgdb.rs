@@ -1,8 +1,11 @@
 use std::env;
 use std::fmt::Display;
 use std::io::{BufRead, BufReader};
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 use log::debug;
 use once_cell::sync::Lazy;
@@ -10,7 +13,7 @@ use serde::Deserialize;
 use serde_json::Value;
 use thiserror::Error;
 
-use super::lockfile::FlakeRef;
+use super::lockfile::Input;
 
 // This is the `PKGDB` path that we actually use.
 // This is set once and prefers the `PKGDB` env variable, but will use
@@ -235,15 +238,123 @@ pub enum ScrapeError {
     #[error("couldn't serialize FlakeRef")]
     ParseJSON(#[source] serde_json::Error),
 }
-pub fn scrape_input(input: &FlakeRef) -> Result<(), ScrapeError> {
-    let mut pkgdb_cmd = Command::new(Path::new(&*PKGDB_BIN));
-    // TODO: this works for nixpkgs, but it won't work for anything else that is not exposing "legacyPackages"
-    pkgdb_cmd
-        .args(["scrape"])
-        .arg(serde_json::to_string(&input).map_err(ScrapeError::ParseJSON)?)
-        .arg("legacyPackages");
+/// Scrape every subtree of a locked input for the current system
+///
+/// If the lockfile doesn't record the input's subtrees,
+/// pkgdb scrapes every subtree the flake provides.
+pub fn scrape_input(input: &Input) -> Result<(), ScrapeError> {
+    let flake_ref = serde_json::to_string(&input.from).map_err(ScrapeError::ParseJSON)?;
+    let attr_paths: Vec<Vec<String>> = match &input.subtrees {
+        Some(subtrees) => subtrees
+            .iter()
+            .map(|subtree| vec![subtree.to_string()])
+            .collect(),
+        None => vec![vec![]],
+    };
+    for attr_path in attr_paths {
+        let mut pkgdb_cmd = Command::new(Path::new(&*PKGDB_BIN));
+        pkgdb_cmd.args(["scrape"]).arg(&flake_ref).args(attr_path);
 
-    debug!("scraping input: {pkgdb_cmd:?}");
-    call_pkgdb(pkgdb_cmd)?;
+        debug!("scraping input: {pkgdb_cmd:?}");
+        call_pkgdb(pkgdb_cmd)?;
+    }
     Ok(())
 }
+
+/// Scraping of one or more inputs failed
+#[derive(Debug, Error)]
+#[error("failed to generate databases for {}", .0.iter().map(|(name, _)| format!("'{name}'")).collect::<Vec<_>>().join(", "))]
+pub struct ScrapeInputsError(pub Vec<(String, ScrapeError)>);
+
+/// Scrape multiple inputs concurrently
+///
+/// At most `parallelism` inputs are scraped at the same time.
+/// `on_scraped` is called as soon as each input has been scraped,
+/// e.g. to report progress.
+/// Failing inputs don't stop the remaining ones from being scraped,
+/// instead the errors of all failed inputs are returned together.
+pub fn scrape_inputs<'a>(
+    inputs: impl IntoIterator<Item = (&'a str, &'a Input)>,
+    parallelism: NonZeroUsize,
+    on_scraped: impl Fn(&str, &Result<(), ScrapeError>) + Sync,
+) -> Result<(), ScrapeInputsError> {
+    let inputs = inputs.into_iter().collect::<Vec<_>>();
+    let errors = for_each_bounded(&inputs, parallelism, |(name, input)| {
+        let result = scrape_input(input);
+        on_scraped(name, &result);
+        result.map_err(|err| (name.to_string(), err))
+    });
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ScrapeInputsError(errors))
+    }
+}
+
+/// Call `f` for every item, running at most `parallelism` calls at a time
+///
+/// Returns the errors of all failed calls in the order of `items`.
+fn for_each_bounded<T: Sync, E: Send>(
+    items: &[T],
+    parallelism: NonZeroUsize,
+    f: impl Fn(&T) -> Result<(), E> + Sync,
+) -> Vec<E> {
+    let next = AtomicUsize::new(0);
+    let errors = Mutex::new(Vec::new());
+
+    std::thread::scope(|scope| {
+        for _ in 0..parallelism.get().min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                if let Err(err) = f(item) {
+                    errors.lock().unwrap().push((index, err));
+                }
+            });
+        }
+    });
+
+    let mut errors = errors.into_inner().unwrap();
+    errors.sort_by_key(|(index, _)| *index);
+    errors.into_iter().map(|(_, err)| err).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn for_each_bounded_limits_parallelism() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        let errors = for_each_bounded(&[(); 8], NonZeroUsize::new(3).unwrap(), |_| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok::<(), ()>(())
+        });
+
+        assert!(errors.is_empty());
+        assert!(max_running.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn for_each_bounded_collects_all_errors() {
+        let errors = for_each_bounded(&[1, 2, 3, 4], NonZeroUsize::new(2).unwrap(), |n| {
+            if n % 2 == 0 {
+                Err(*n)
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(errors, vec![2, 4]);
+    }
+}
@@ -5,11 +5,13 @@ use std::sync::mpsc::SendError;
 use std::thread::ScopedJoinHandle;
 
 use log::{debug, trace};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_with::skip_serializing_none;
 
 use super::pkgdb::PkgDbError;
+use crate::data::System;
 use crate::models::pkgdb::PKGDB_BIN;
 use crate::utils::CommandExt;
 
@@ -121,8 +123,12 @@ impl std::fmt::Display for PathOrJson {
 #[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 pub enum SearchStrategy {
+    /// Match the package name and description, ranking name matches first
     Match,
+    /// Match the package name only
     MatchName,
+    /// Match the package name or the `.` joined attribute path,
+    /// e.g. `python310Packages.flask`
     #[default]
     MatchNameOrRelPath,
 }
@@ -164,6 +170,27 @@ pub struct Query {
     /// Return a single result for each package descriptor used by `search` and
     /// `install`.
     pub deduplicate: bool,
+    /// Only return packages with one of these licenses
+    ///
+    /// Overrides `options.allow.licenses` of the manifest.
+    pub licenses: Option<Vec<String>>,
+    /// Whether to return packages with an unfree license
+    ///
+    /// Overrides `options.allow.unfree` of the manifest.
+    pub allow_unfree: Option<bool>,
+    /// Whether to return packages marked as broken
+    ///
+    /// Overrides `options.allow.broken` of the manifest.
+    pub allow_broken: Option<bool>,
+    /// Only return packages available for one of these systems
+    /// rather than for the systems of the manifest
+    pub systems: Option<Vec<System>>,
+    /// Only return packages with a description matching this regular expression
+    ///
+    /// `pkgdb` can't match regular expressions,
+    /// so this is applied to its results by [do_search].
+    #[serde(skip)]
+    pub description: Option<Regex>,
 }
 
 impl Query {
@@ -243,6 +270,16 @@ pub enum Subtree {
     Packages,
 }
 
+impl std::fmt::Display for Subtree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Subtree::Catalog => write!(f, "catalog"),
+            Subtree::LegacyPackages => write!(f, "legacyPackages"),
+            Subtree::Packages => write!(f, "packages"),
+        }
+    }
+}
+
 /// The deserialized search results.
 ///
 /// Note that the JSON results are returned by `pkgdb` one result per line
@@ -305,6 +342,34 @@ impl TryFrom<&[u8]> for SearchResults {
 /// Calls `pkgdb` and reads a stream of search records.
 #[allow(clippy::type_complexity)]
 pub fn do_search(search_params: &SearchParams) -> Result<(SearchResults, ExitStatus), SearchError> {
+    // Filter descriptions before applying the limit
+    if let Some(description) = &search_params.query.description {
+        let mut unfiltered_params = search_params.clone();
+        unfiltered_params.query.description = None;
+        unfiltered_params.query.limit = None;
+        let (unfiltered, exit_status) = do_search(&unfiltered_params)?;
+
+        let mut results = unfiltered
+            .results
+            .into_iter()
+            .filter(|result| {
+                result
+                    .description
+                    .as_deref()
+                    .is_some_and(|text| description.is_match(text))
+            })
+            .collect::<Vec<_>>();
+        let count = results.len() as u64;
+        if let Some(limit) = search_params.query.limit {
+            results.truncate(limit.into());
+        }
+        let results = SearchResults {
+            results,
+            count: search_params.query.limit.map(|_| count),
+        };
+        return Ok((results, exit_status));
+    }
+
     let json = serde_json::to_string(search_params).map_err(SearchError::Serialize)?;
 
     let mut pkgdb_command = Command::new(PKGDB_BIN.as_str());
@@ -0,0 +1,1094 @@
+//! Search the package databases scraped by `pkgdb` without calling `pkgdb`
+//!
+//! `pkgdb search` evaluates the environment's registry and scrapes missing
+//! inputs before running its query, which makes every search take hundreds
+//! of milliseconds even if nothing needs to be scraped.
+//! A [SearchIndex] instead opens the existing databases of a lockfile's inputs
+//! read-only and copies their packages into a full-text search index,
+//! once per database and scraped `<subtree>.<system>`.
+//! Searches query the index, and matches are ranked in Rust.
+//!
+//! The index only answers queries it can answer faithfully.
+//! If an input has not been scraped for the current system yet,
+//! or the query uses a semver range, it returns an error
+//! and callers should fall back to [super::search::do_search].
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs};
+
+use log::debug;
+use rusqlite::types::Type;
+use rusqlite::{
+    params,
+    params_from_iter,
+    Connection,
+    OpenFlags,
+    OptionalExtension,
+    Transaction,
+    TransactionBehavior,
+};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::environment::CanonicalPath;
+use super::lockfile::{LockedManifest, LockedManifestError, TypedLockedManifest};
+use super::manifest::ManifestAllows;
+use super::search::{Query, SearchResult, SearchResults, SearchStrategy, Subtree};
+use crate::data::System;
+
+/// The version of the `pkgdb` tables schema,
+/// must match `sqlVersions.tables` in `pkgdb/include/flox/pkgdb/read.hh`
+const PKGDB_TABLES_VERSION: u32 = 3;
+
+/// The version of the search index schema,
+/// indexes are stored in `search-index-v<version>` in the pkgdb cache directory
+const SEARCH_INDEX_VERSION: u32 = 1;
+
+/// The schema of the search index of a single package database
+///
+/// `Roots` are the `<subtree>.<system>` attribute sets that have been indexed.
+/// `Packages` is an FTS5 table of the packages below them,
+/// only names, paths and descriptions are indexed.
+/// The trigram tokenizer matches any substring of at least three characters.
+const INDEX_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS Roots (
+      id INTEGER PRIMARY KEY, subtree TEXT NOT NULL, system TEXT NOT NULL,
+      UNIQUE ( subtree, system )
+    );
+    CREATE VIRTUAL TABLE IF NOT EXISTS Packages USING fts5 (
+      pname, attrName, relPath, description,
+      rootId UNINDEXED, packageId UNINDEXED, attrPath UNINDEXED, name UNINDEXED,
+      version UNINDEXED, semver UNINDEXED, license UNINDEXED, broken UNINDEXED,
+      unfree UNINDEXED,
+      tokenize = 'trigram'
+    );
+";
+
+/// Weights of the indexed columns of `Packages` for `bm25()`,
+/// in the order of [Rank]'s partial matches
+const INDEX_WEIGHTS: &str = "8.0, 4.0, 2.0, 1.0";
+
+#[derive(Debug, Error)]
+pub enum SearchIndexError {
+    #[error("couldn't determine the package database directory")]
+    NoCacheDir,
+    #[error("couldn't read package database directory")]
+    ReadCacheDir(#[source] std::io::Error),
+    #[error("couldn't read lockfile")]
+    ReadLockfile(#[source] LockedManifestError),
+    #[error("input '{0}' has not been scraped for this system")]
+    NotScraped(String),
+    #[error("semver ranges are not supported by the search index")]
+    UnsupportedQuery,
+    #[error("couldn't query package database {}", .0.display())]
+    Query(PathBuf, #[source] rusqlite::Error),
+    #[error("couldn't create search index directory")]
+    CreateIndexDir(#[source] std::io::Error),
+}
+
+/// The directory in which `pkgdb` stores package databases
+///
+/// Mirrors `getPkgDbCachedir()` in pkgdb.
+pub fn pkgdb_cache_dir() -> Result<PathBuf, SearchIndexError> {
+    if let Some(dir) = env::var_os("PKGDB_CACHEDIR") {
+        return Ok(dir.into());
+    }
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .ok_or(SearchIndexError::NoCacheDir)?;
+    Ok(cache_home
+        .join("flox")
+        .join(format!("pkgdb-v{PKGDB_TABLES_VERSION}")))
+}
+
+/// The package databases of the inputs of a lockfile
+pub struct SearchIndex {
+    inputs: Vec<IndexedInput>,
+    system: System,
+    allows: ManifestAllows,
+}
+
+/// A read-only connection to the package database of a single input
+/// and a connection to its search index
+struct IndexedInput {
+    name: String,
+    subtrees: Vec<Subtree>,
+    db_path: PathBuf,
+    connection: Connection,
+    index_path: PathBuf,
+    index: Connection,
+}
+
+impl SearchIndex {
+    /// Open the package databases of all inputs locked in `lockfile_path`
+    ///
+    /// Databases are looked up in `cache_dir` by the locked flake reference
+    /// they were scraped from.
+    /// Their search indexes are created in `cache_dir` as well.
+    pub fn open(
+        cache_dir: &Path,
+        lockfile_path: &CanonicalPath,
+        system: &System,
+    ) -> Result<Self, SearchIndexError> {
+        let lockfile = LockedManifest::read_from_file(lockfile_path)
+            .map_err(SearchIndexError::ReadLockfile)?;
+        // the global lockfile does not contain a manifest
+        let allows = lockfile
+            .manifest()
+            .ok()
+            .and_then(|manifest| manifest.options)
+            .and_then(|options| options.allow)
+            .unwrap_or_default();
+        let lockfile =
+            TypedLockedManifest::try_from(lockfile).map_err(SearchIndexError::ReadLockfile)?;
+
+        let mut databases = Self::databases(cache_dir)?;
+        let index_dir = cache_dir.join(format!("search-index-v{SEARCH_INDEX_VERSION}"));
+        let mut inputs = Vec::new();
+        for (name, input) in &lockfile.registry().inputs {
+            let Some(position) = databases
+                .iter()
+                .position(|database| database.from == input.from)
+            else {
+                debug!("no package database found for input '{name}'");
+                return Err(SearchIndexError::NotScraped(name.clone()));
+            };
+            let database = databases.swap_remove(position);
+            let connection = open_read_only(&database.path)?;
+            fs::create_dir_all(&index_dir).map_err(SearchIndexError::CreateIndexDir)?;
+            let index_path = index_dir.join(format!("{}.sqlite", database.fingerprint));
+            let index = open_index(&index_path)?;
+            inputs.push(IndexedInput {
+                name: name.clone(),
+                subtrees: input.subtrees().to_vec(),
+                db_path: database.path,
+                connection,
+                index_path,
+                index,
+            });
+        }
+
+        Ok(SearchIndex {
+            inputs,
+            system: system.clone(),
+            allows,
+        })
+    }
+
+    /// List the package databases in `cache_dir`
+    fn databases(cache_dir: &Path) -> Result<Vec<Database>, SearchIndexError> {
+        let entries = match std::fs::read_dir(cache_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(SearchIndexError::ReadCacheDir(err)),
+        };
+
+        let mut databases = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(SearchIndexError::ReadCacheDir)?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("sqlite") {
+                continue;
+            }
+            // Databases that are still being created may not have any of the tables yet
+            let locked_flake = open_read_only(&path).and_then(|connection| {
+                connection
+                    .query_row(
+                        "SELECT fingerprint, attrs FROM LockedFlake LIMIT 1",
+                        [],
+                        |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+                    )
+                    .optional()
+                    .map_err(|err| SearchIndexError::Query(path.clone(), err))
+            });
+            match locked_flake {
+                Ok(Some((fingerprint, attrs))) => match serde_json::from_str(&attrs) {
+                    Ok(from) => databases.push(Database {
+                        path,
+                        fingerprint,
+                        from,
+                    }),
+                    Err(err) => debug!("skipping {}: invalid locked flake: {err}", path.display()),
+                },
+                Ok(None) => debug!("skipping {}: no locked flake", path.display()),
+                Err(err) => debug!("skipping {}: {err}", path.display()),
+            }
+        }
+        Ok(databases)
+    }
+
+    /// Search all inputs for packages matching `query`
+    ///
+    /// Results are ordered by input and then by rank within each input,
+    /// following the order used by `pkgdb search`.
+    /// Which fields are matched and how they are ranked is determined by the
+    /// [SearchStrategy] field the search term was set on.
+    pub fn search(&self, query: &Query) -> Result<SearchResults, SearchIndexError> {
+        if query.semver.is_some() {
+            return Err(SearchIndexError::UnsupportedQuery);
+        }
+
+        let mut results = Vec::new();
+        for input in &self.inputs {
+            results.extend(self.search_input(input, query)?);
+        }
+
+        let count = results.len() as u64;
+        if let Some(limit) = query.limit {
+            results.truncate(limit as usize);
+        }
+        Ok(SearchResults {
+            results,
+            count: query.limit.map(|_| count),
+        })
+    }
+
+    fn search_input(
+        &self,
+        input: &IndexedInput,
+        query: &Query,
+    ) -> Result<Vec<SearchResult>, SearchIndexError> {
+        let query_error = |err| SearchIndexError::Query(input.index_path.clone(), err);
+
+        let systems = query
+            .systems
+            .as_deref()
+            .unwrap_or(std::slice::from_ref(&self.system));
+
+        // Map the indexed `<subtree>.<system>` attribute sets to the position
+        // of their subtree and system in the preferences
+        let mut roots = HashMap::new();
+        for (system_rank, system) in systems.iter().enumerate() {
+            let mut scraped = false;
+            for (subtree_rank, subtree) in input.subtrees.iter().enumerate() {
+                let Some(root_id) = input.root(&subtree.to_string(), system)? else {
+                    continue;
+                };
+                scraped = true;
+                roots.insert(root_id, Root {
+                    subtree_rank,
+                    system_rank,
+                    subtree,
+                    system,
+                });
+            }
+            if !scraped {
+                return Err(SearchIndexError::NotScraped(input.name.clone()));
+            }
+        }
+
+        let (strategy, tokens) = match query.search_term() {
+            Some((strategy, term)) => (
+                strategy,
+                term.split_whitespace().map(str::to_lowercase).collect(),
+            ),
+            None => (SearchStrategy::MatchName, vec![]),
+        };
+        let columns: &[&str] = match strategy {
+            SearchStrategy::Match => &["pname", "attrName", "description"],
+            SearchStrategy::MatchName => &["pname", "attrName"],
+            SearchStrategy::MatchNameOrRelPath => &["pname", "attrName", "relPath"],
+        };
+
+        // Trigrams can't match tokens shorter than three characters,
+        // which are matched with `LIKE` instead
+        let (full_text_tokens, short_tokens): (Vec<&String>, Vec<&String>) =
+            tokens.iter().partition(|token| token.chars().count() >= 3);
+
+        // Narrow down candidates in SQL, they are ranked precisely below.
+        // `bm25()` normalizes by the length of all columns, so it's only
+        // meaningful if descriptions are matched, too.
+        let score = if strategy == SearchStrategy::Match && !full_text_tokens.is_empty() {
+            format!("bm25 ( Packages, {INDEX_WEIGHTS} )")
+        } else {
+            "0.0".to_string()
+        };
+        let mut sql = format!(
+            "SELECT packageId, rootId, attrPath, attrName, pname, version, semver, license,
+                    broken, unfree, description, {score}
+             FROM Packages
+             WHERE rootId IN ({})",
+            join_ids(roots.keys().copied())
+        );
+
+        let mut binds: Vec<String> = Vec::new();
+        if !full_text_tokens.is_empty() {
+            let column_filter = columns.join(" ");
+            let expression = full_text_tokens
+                .iter()
+                .map(|token| format!("{{{column_filter}}} : \"{}\"", token.replace('"', "\"\"")))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+            binds.push(expression);
+            sql.push_str(&format!(" AND Packages MATCH ?{}", binds.len()));
+        }
+        for token in short_tokens {
+            binds.push(like_pattern(token));
+            let n = binds.len();
+            let matches = columns
+                .iter()
+                .map(|column| format!("{column} LIKE ?{n} ESCAPE '\\'"))
+                .collect::<Vec<_>>()
+                .join(" OR ");
+            sql.push_str(&format!(" AND ( {matches} )"));
+        }
+        for (column, value) in [
+            ("name", &query.name),
+            ("pname", &query.pname),
+            ("version", &query.version),
+        ] {
+            if let Some(value) = value {
+                binds.push(value.clone());
+                sql.push_str(&format!(" AND {column} = ?{}", binds.len()));
+            }
+        }
+        if let Some(attr_name) = query.rel_path.as_ref().and_then(|rel_path| rel_path.last()) {
+            binds.push(attr_name.clone());
+            sql.push_str(&format!(" AND attrName = ?{}", binds.len()));
+        }
+
+        let mut statement = input.index.prepare(&sql).map_err(query_error)?;
+        let rows = statement
+            .query_map(params_from_iter(binds.iter()), |row| {
+                let package = PackageRow {
+                    id: row.get(0)?,
+                    rel_path: json_column(row, 2)?,
+                    attr_name: row.get(3)?,
+                    pname: row.get(4)?,
+                    version: row.get(5)?,
+                    semver: row.get(6)?,
+                    license: row.get(7)?,
+                    broken: row.get(8)?,
+                    unfree: row.get(9)?,
+                    description: row.get(10)?,
+                };
+                Ok((package, row.get::<_, u64>(1)?, row.get::<_, f64>(11)?))
+            })
+            .map_err(query_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(query_error)?;
+
+        // Equal scores get the same relevance,
+        // e.g. for the same package on different systems
+        let mut scores = rows.iter().map(|(_, _, score)| *score).collect::<Vec<_>>();
+        scores.sort_by(f64::total_cmp);
+        scores.dedup();
+
+        let mut ranked = Vec::new();
+        for (row, root_id, score) in rows {
+            let root = &roots[&root_id];
+
+            if !self.allowed(&row, query) {
+                continue;
+            }
+            if query
+                .rel_path
+                .as_ref()
+                .is_some_and(|wanted| *wanted != row.rel_path)
+            {
+                continue;
+            }
+            let relevance = scores.partition_point(|other| other.total_cmp(&score).is_lt());
+            let Some(rank) = Rank::new(&row, &tokens, &strategy, relevance) else {
+                continue;
+            };
+
+            let key = (
+                rank,
+                root.subtree_rank,
+                root.system_rank,
+                row.pname.clone(),
+                VersionRank::new(row.semver.as_deref(), row.version.as_deref()),
+                row.broken.unwrap_or(false),
+                row.unfree.unwrap_or(false),
+                row.attr_name.clone(),
+            );
+            let mut abs_path = vec![root.subtree.to_string(), root.system.clone()];
+            abs_path.extend(row.rel_path.iter().cloned());
+            let result = SearchResult {
+                input: input.name.clone(),
+                abs_path,
+                subtree: root.subtree.clone(),
+                system: root.system.clone(),
+                rel_path: row.rel_path,
+                pname: row.pname,
+                version: row.version,
+                description: row.description,
+                broken: row.broken,
+                unfree: row.unfree,
+                license: row.license,
+                id: row.id,
+            };
+            ranked.push((key, result));
+        }
+        ranked.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut seen = HashSet::new();
+        Ok(ranked
+            .into_iter()
+            .map(|(_, result)| result)
+            .filter(|result| !query.deduplicate || seen.insert(result.rel_path.clone()))
+            .collect())
+    }
+
+    /// Whether the filters of `query` or the manifest's `options.allow`
+    /// permit a package
+    ///
+    /// Uses the same defaults as pkgdb:
+    /// unfree packages are allowed, broken packages are not.
+    fn allowed(&self, row: &PackageRow, query: &Query) -> bool {
+        let allow_broken = query.allow_broken.or(self.allows.broken).unwrap_or(false);
+        if row.broken == Some(true) && !allow_broken {
+            return false;
+        }
+        let allow_unfree = query.allow_unfree.or(self.allows.unfree).unwrap_or(true);
+        if row.unfree == Some(true) && !allow_unfree {
+            return false;
+        }
+        if let Some(licenses) = query.licenses.as_ref().or(self.allows.licenses.as_ref()) {
+            let allowed = licenses.is_empty()
+                || row
+                    .license
+                    .as_ref()
+                    .is_some_and(|license| licenses.contains(license));
+            if !allowed {
+                return false;
+            }
+        }
+        match &query.description {
+            Some(regex) => row
+                .description
+                .as_deref()
+                .is_some_and(|description| regex.is_match(description)),
+            None => true,
+        }
+    }
+}
+
+impl Query {
+    /// The search term and the strategy it should be matched with
+    fn search_term(&self) -> Option<(SearchStrategy, &str)> {
+        [
+            (SearchStrategy::Match, &self.r#match),
+            (SearchStrategy::MatchName, &self.match_name),
+            (
+                SearchStrategy::MatchNameOrRelPath,
+                &self.match_name_or_rel_path,
+            ),
+        ]
+        .into_iter()
+        .find_map(|(strategy, term)| Some((strategy, term.as_deref()?)))
+        .filter(|(_, term)| !term.trim().is_empty())
+    }
+}
+
+impl IndexedInput {
+    /// The id of the indexed `<subtree>.<system>` attribute set,
+    /// indexing its packages first if they haven't been indexed yet
+    ///
+    /// Returns `None` if the package database doesn't contain the attribute set,
+    /// and an error if it hasn't been scraped completely.
+    fn root(&self, subtree: &str, system: &str) -> Result<Option<u64>, SearchIndexError> {
+        let index_error = |err| SearchIndexError::Query(self.index_path.clone(), err);
+        let db_error = |err| SearchIndexError::Query(self.db_path.clone(), err);
+        let indexed = |index: &Connection| {
+            index
+                .query_row(
+                    "SELECT id FROM Roots WHERE subtree = ?1 AND system = ?2",
+                    [subtree, system],
+                    |row| row.get::<_, u64>(0),
+                )
+                .optional()
+        };
+
+        if let Some(root_id) = indexed(&self.index).map_err(index_error)? {
+            return Ok(Some(root_id));
+        }
+
+        let system_set = self
+            .connection
+            .query_row(
+                "SELECT Systems.id, Systems.done
+                 FROM AttrSets AS Systems
+                 JOIN AttrSets AS Subtrees ON ( Systems.parent = Subtrees.id )
+                 WHERE COALESCE ( Subtrees.parent, 0 ) = 0
+                   AND Subtrees.attrName = ?1 AND Systems.attrName = ?2",
+                [subtree, system],
+                |row| Ok((row.get::<_, u64>(0)?, row.get::<_, bool>(1)?)),
+            )
+            .optional()
+            .map_err(db_error)?;
+        let system_id = match system_set {
+            None => return Ok(None),
+            Some((_, false)) => return Err(SearchIndexError::NotScraped(self.name.clone())),
+            Some((system_id, true)) => system_id,
+        };
+        let packages = self.packages_below(system_id).map_err(db_error)?;
+
+        let transaction = Transaction::new_unchecked(&self.index, TransactionBehavior::Immediate)
+            .map_err(index_error)?;
+        // Another process may have indexed the packages in the meantime
+        if let Some(root_id) = indexed(&transaction).map_err(index_error)? {
+            return Ok(Some(root_id));
+        }
+        transaction
+            .execute("INSERT INTO Roots ( subtree, system ) VALUES ( ?1, ?2 )", [
+                subtree, system,
+            ])
+            .map_err(index_error)?;
+        let root_id = transaction.last_insert_rowid() as u64;
+        {
+            let mut insert = transaction
+                .prepare(
+                    "INSERT INTO Packages (
+                       pname, attrName, relPath, description, rootId, packageId, attrPath,
+                       name, version, semver, license, broken, unfree
+                     ) VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13 )",
+                )
+                .map_err(index_error)?;
+            for (package, name) in &packages {
+                insert
+                    .execute(params![
+                        package.pname,
+                        package.attr_name,
+                        package.rel_path.join("."),
+                        package.description,
+                        root_id,
+                        package.id,
+                        serde_json::to_string(&package.rel_path)
+                            .expect("attribute paths are serializable"),
+                        name,
+                        package.version,
+                        package.semver,
+                        package.license,
+                        package.broken,
+                        package.unfree,
+                    ])
+                    .map_err(index_error)?;
+            }
+        }
+        transaction.commit().map_err(index_error)?;
+
+        debug!(
+            "indexed {} packages of {subtree}.{system} for input '{}'",
+            packages.len(),
+            self.name
+        );
+        Ok(Some(root_id))
+    }
+
+    /// All packages below the attribute set `root` in the package database,
+    /// together with their `name`
+    fn packages_below(
+        &self,
+        root: u64,
+    ) -> Result<Vec<(PackageRow, Option<String>)>, rusqlite::Error> {
+        let mut statement = self.connection.prepare(
+            "WITH RECURSIVE Tree ( id, relPath ) AS (
+               SELECT ?1, json_array ( )
+               UNION ALL
+               SELECT AttrSets.id, json_insert ( Tree.relPath, '$[#]', AttrSets.attrName )
+               FROM AttrSets JOIN Tree ON ( AttrSets.parent = Tree.id )
+             )
+             SELECT Packages.id, Tree.relPath, Packages.attrName, pname, version, semver,
+                    license, broken, unfree, description, name
+             FROM Packages
+             JOIN Tree ON ( Packages.parentId = Tree.id )
+             LEFT OUTER JOIN Descriptions ON ( Packages.descriptionId = Descriptions.id )",
+        )?;
+        let rows = statement.query_map([root], |row| {
+            let attr_name: String = row.get(2)?;
+            let mut rel_path: Vec<String> = json_column(row, 1)?;
+            rel_path.push(attr_name.clone());
+            let package = PackageRow {
+                id: row.get(0)?,
+                rel_path,
+                attr_name,
+                pname: row.get(3)?,
+                version: row.get(4)?,
+                semver: row.get(5)?,
+                license: row.get(6)?,
+                broken: row.get(7)?,
+                unfree: row.get(8)?,
+                description: row.get(9)?,
+            };
+            Ok((package, row.get(10)?))
+        })?;
+        rows.collect()
+    }
+}
+
+/// Open the search index at `path`, creating it if it doesn't exist
+fn open_index(path: &Path) -> Result<Connection, SearchIndexError> {
+    let index_error = |err| SearchIndexError::Query(path.to_path_buf(), err);
+    let connection = Connection::open(path).map_err(index_error)?;
+    // Other processes may be indexing the same database
+    connection
+        .busy_timeout(Duration::from_secs(60))
+        .map_err(index_error)?;
+    connection
+        .execute_batch(INDEX_SCHEMA)
+        .map_err(index_error)?;
+    Ok(connection)
+}
+
+/// Read a JSON encoded column
+fn json_column<T: serde::de::DeserializeOwned>(
+    row: &rusqlite::Row,
+    index: usize,
+) -> Result<T, rusqlite::Error> {
+    let json: String = row.get(index)?;
+    serde_json::from_str(&json)
+        .map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err)))
+}
+
+fn open_read_only(path: &Path) -> Result<Connection, SearchIndexError> {
+    Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|err| SearchIndexError::Query(path.to_path_buf(), err))
+}
+
+/// Escape `_` and `%` and match `term` anywhere
+fn like_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('_', "\\_")
+        .replace('%', "\\%");
+    format!("%{escaped}%")
+}
+
+/// Database ids are integers and can be inlined into queries
+fn join_ids(ids: impl Iterator<Item = u64>) -> String {
+    ids.map(|id| id.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// A package database in the pkgdb cache directory
+struct Database {
+    path: PathBuf,
+    /// The fingerprint of the locked flake the database was scraped from
+    fingerprint: String,
+    /// The locked flake reference the database was scraped from
+    from: Value,
+}
+
+/// An indexed `<subtree>.<system>` attribute set
+struct Root<'a> {
+    subtree_rank: usize,
+    system_rank: usize,
+    subtree: &'a Subtree,
+    system: &'a System,
+}
+
+/// A package of a package database or its search index
+struct PackageRow {
+    id: u64,
+    /// The path of the package relative to `<subtree>.<system>`
+    rel_path: Vec<String>,
+    attr_name: String,
+    pname: Option<String>,
+    version: Option<String>,
+    semver: Option<String>,
+    license: Option<String>,
+    broken: Option<bool>,
+    unfree: Option<bool>,
+    description: Option<String>,
+}
+
+/// How well a package matches the search tokens
+///
+/// Ordered like the `ORDER BY` clause of `pkgdb search`:
+/// exact matches first, then shallower packages, then partial matches.
+/// Scores are summed over all tokens,
+/// so packages matching more tokens exactly rank higher.
+/// Packages with equal scores are ordered by the relevance
+/// the search index assigned to them when matching descriptions.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Rank {
+    exact: Reverse<u32>,
+    depth: usize,
+    partial: Reverse<u32>,
+    /// Position of the package's `bm25()` score among all matches
+    relevance: usize,
+}
+
+impl Rank {
+    /// Rank a package, or return `None` if any token doesn't match
+    ///
+    /// The fields considered depend on `strategy`:
+    /// - [SearchStrategy::MatchName]: `pname` and attribute name
+    /// - [SearchStrategy::MatchNameOrRelPath]: additionally the `.` joined relative path
+    /// - [SearchStrategy::Match]: additionally the description, ranked lowest
+    fn new(
+        row: &PackageRow,
+        tokens: &[String],
+        strategy: &SearchStrategy,
+        relevance: usize,
+    ) -> Option<Self> {
+        let rel_path = &row.rel_path;
+        let pname = row.pname.as_deref().unwrap_or_default().to_lowercase();
+        let attr_name = row.attr_name.to_lowercase();
+        let rel_path_string = (*strategy == SearchStrategy::MatchNameOrRelPath)
+            .then(|| rel_path.join(".").to_lowercase());
+        let description = (*strategy == SearchStrategy::Match).then(|| {
+            row.description
+                .as_deref()
+                .unwrap_or_default()
+                .to_lowercase()
+        });
+
+        let mut exact = 0;
+        let mut partial = 0;
+        for token in tokens {
+            let token = token.as_str();
+            let mut token_exact = 0;
+            let mut token_partial = 0;
+            if pname == token {
+                token_exact += 4;
+            }
+            if attr_name == token {
+                token_exact += 2;
+            }
+            if rel_path_string.as_deref() == Some(token) {
+                token_exact += 1;
+            }
+            if pname.contains(token) {
+                token_partial += 8;
+            }
+            if attr_name.contains(token) {
+                token_partial += 4;
+            }
+            if rel_path_string
+                .as_deref()
+                .is_some_and(|rel_path| rel_path.contains(token))
+            {
+                token_partial += 2;
+            }
+            if description
+                .as_deref()
+                .is_some_and(|description| description.contains(token))
+            {
+                token_partial += 1;
+            }
+            if token_exact + token_partial == 0 {
+                return None;
+            }
+            exact += token_exact;
+            partial += token_partial;
+        }
+
+        Some(Rank {
+            exact: Reverse(exact),
+            depth: rel_path.len(),
+            partial: Reverse(partial),
+            relevance,
+        })
+    }
+}
+
+/// Orders versions like pkgdb:
+/// semantic versions before other versions before no version,
+/// releases before pre-releases, and newer versions first.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum VersionRank {
+    Semver {
+        pre_release: bool,
+        version: Reverse<semver::Version>,
+    },
+    Other(String),
+    None,
+}
+
+impl VersionRank {
+    fn new(semver: Option<&str>, version: Option<&str>) -> Self {
+        match (semver.and_then(|s| semver::Version::parse(s).ok()), version) {
+            (Some(version), _) => VersionRank::Semver {
+                pre_release: !version.pre.is_empty(),
+                version: Reverse(version),
+            },
+            (None, Some(version)) => VersionRank::Other(version.to_string()),
+            (None, None) => VersionRank::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use regex::Regex;
+    use serde_json::json;
+
+    use super::*;
+
+    const SYSTEM: &str = "x86_64-linux";
+
+    /// A subset of the pkgdb schema used by the index
+    const SCHEMA: &str = indoc! {"
+        CREATE TABLE LockedFlake ( fingerprint TEXT PRIMARY KEY, string TEXT, attrs JSON );
+        CREATE TABLE AttrSets (
+          id INTEGER PRIMARY KEY, parent INTEGER, attrName VARCHAR(255),
+          done BOOL NOT NULL DEFAULT FALSE
+        );
+        CREATE TABLE Descriptions ( id INTEGER PRIMARY KEY, description TEXT );
+        CREATE TABLE Packages (
+          id INTEGER PRIMARY KEY, parentId INTEGER, attrName VARCHAR(255),
+          name VARCHAR(255), pname VARCHAR(255), version VARCHAR(127),
+          semver VARCHAR(127), license VARCHAR(255), outputs JSON,
+          outputsToInstall JSON, broken BOOL, unfree BOOL, descriptionId INTEGER
+        );
+    "};
+
+    fn from() -> Value {
+        json!({ "type": "github", "owner": "NixOS", "repo": "nixpkgs", "rev": "abc" })
+    }
+
+    /// Create a database with a few packages for [SYSTEM]
+    /// and a lockfile referring to it
+    fn index_fixture(done: bool) -> (tempfile::TempDir, CanonicalPath) {
+        let tempdir = tempfile::tempdir().unwrap();
+        let connection = Connection::open(tempdir.path().join("0123.sqlite")).unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        connection
+            .execute(
+                "INSERT INTO LockedFlake VALUES ( '0123', 'github:NixOS/nixpkgs/abc', ?1 )",
+                [from().to_string()],
+            )
+            .unwrap();
+        connection
+            .execute_batch(&format!(
+                "INSERT INTO AttrSets VALUES
+                   ( 1, 0, 'legacyPackages', {done} ),
+                   ( 2, 1, '{SYSTEM}', {done} ),
+                   ( 3, 2, 'python310Packages', {done} ),
+                   ( 4, 1, 'aarch64-darwin', {done} );
+                 INSERT INTO Descriptions VALUES
+                   ( 1, 'A program that produces a familiar, friendly greeting' ),
+                   ( 2, 'A microframework based on Werkzeug' );
+                 INSERT INTO Packages VALUES
+                   ( 1, 2, 'hello', 'hello-2.12.1', 'hello', '2.12.1', '2.12.1', 'GPL-3.0-or-later', '[]', NULL, 0, 0, 1 ),
+                   ( 2, 2, 'hello-wayland', 'hello-wayland-0.1', 'hello-wayland', '0.1', '0.1.0', NULL, '[]', NULL, 0, 1, NULL ),
+                   ( 3, 3, 'flask', 'flask-3.0.0', 'flask', '3.0.0', '3.0.0', 'BSD-3-Clause', '[]', NULL, 0, 0, 2 ),
+                   ( 4, 2, 'othello', 'othello-1.0', 'othello', '1.0', '1.0.0', NULL, '[]', NULL, 1, 0, NULL ),
+                   ( 5, 4, 'hello', 'hello-2.12.1', 'hello', '2.12.1', '2.12.1', NULL, '[]', NULL, 0, 0, 1 );"
+            ))
+            .unwrap();
+
+        let lockfile_path = tempdir.path().join("manifest.lock");
+        std::fs::write(
+            &lockfile_path,
+            json!({
+                "lockfile-version": 0,
+                "packages": {},
+                "registry": {
+                    "inputs": {
+                        "nixpkgs": { "from": from(), "subtrees": ["legacyPackages"] }
+                    }
+                }
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let lockfile_path = CanonicalPath::new(lockfile_path).unwrap();
+        (tempdir, lockfile_path)
+    }
+
+    fn search(term: &str, strategy: SearchStrategy) -> Vec<String> {
+        search_query(Query::new(term, strategy, None, true).unwrap())
+    }
+
+    fn search_query(query: Query) -> Vec<String> {
+        let (tempdir, lockfile) = index_fixture(true);
+        let index = SearchIndex::open(tempdir.path(), &lockfile, &SYSTEM.to_string()).unwrap();
+        index
+            .search(&query)
+            .unwrap()
+            .results
+            .into_iter()
+            .map(|result| format!("{}.{}", result.system, result.rel_path.join(".")))
+            .collect()
+    }
+
+    #[test]
+    fn ranks_exact_matches_first() {
+        assert_eq!(search("hello", SearchStrategy::MatchName), vec![
+            "x86_64-linux.hello",
+            "x86_64-linux.hello-wayland"
+        ]);
+    }
+
+    #[test]
+    fn excludes_broken_packages() {
+        assert_eq!(search("ell", SearchStrategy::MatchName), vec![
+            "x86_64-linux.hello",
+            "x86_64-linux.hello-wayland"
+        ]);
+    }
+
+    #[test]
+    fn matches_rel_path() {
+        assert_eq!(
+            search("python310Packages.fla", SearchStrategy::MatchNameOrRelPath),
+            vec!["x86_64-linux.python310Packages.flask"]
+        );
+        assert!(search("python310Packages.fla", SearchStrategy::MatchName).is_empty());
+    }
+
+    #[test]
+    fn matches_descriptions_only_with_match_strategy() {
+        assert_eq!(search("werkzeug", SearchStrategy::Match), vec![
+            "x86_64-linux.python310Packages.flask"
+        ]);
+        assert!(search("werkzeug", SearchStrategy::MatchName).is_empty());
+    }
+
+    #[test]
+    fn matches_tokens_shorter_than_trigrams() {
+        assert_eq!(search("he", SearchStrategy::MatchName), vec![
+            "x86_64-linux.hello",
+            "x86_64-linux.hello-wayland"
+        ]);
+        assert_eq!(search("wayland he", SearchStrategy::MatchName), vec![
+            "x86_64-linux.hello-wayland"
+        ]);
+    }
+
+    #[test]
+    fn reuses_index_of_database() {
+        let (tempdir, lockfile) = index_fixture(true);
+        let query = hello_query();
+        let index = SearchIndex::open(tempdir.path(), &lockfile, &SYSTEM.to_string()).unwrap();
+        assert_eq!(index.search(&query).unwrap().results.len(), 2);
+        drop(index);
+        assert!(tempdir
+            .path()
+            .join(format!("search-index-v{SEARCH_INDEX_VERSION}"))
+            .join("0123.sqlite")
+            .exists());
+
+        // Packages are only read from the package database once
+        Connection::open(tempdir.path().join("0123.sqlite"))
+            .unwrap()
+            .execute("DELETE FROM Packages", [])
+            .unwrap();
+        let index = SearchIndex::open(tempdir.path(), &lockfile, &SYSTEM.to_string()).unwrap();
+        assert_eq!(index.search(&query).unwrap().results.len(), 2);
+    }
+
+    #[test]
+    fn requires_all_tokens_to_match() {
+        assert_eq!(search("hello friendly", SearchStrategy::Match), vec![
+            "x86_64-linux.hello"
+        ]);
+    }
+
+    fn hello_query() -> Query {
+        Query::new("hello", SearchStrategy::MatchName, None, true).unwrap()
+    }
+
+    #[test]
+    fn filters_by_license() {
+        assert_eq!(
+            search_query(Query {
+                licenses: Some(vec!["GPL-3.0-or-later".to_string()]),
+                ..hello_query()
+            }),
+            vec!["x86_64-linux.hello"]
+        );
+    }
+
+    #[test]
+    fn excludes_unfree_packages_on_request() {
+        assert_eq!(
+            search_query(Query {
+                allow_unfree: Some(false),
+                ..hello_query()
+            }),
+            vec!["x86_64-linux.hello"]
+        );
+    }
+
+    #[test]
+    fn includes_broken_packages_on_request() {
+        assert_eq!(
+            search_query(Query {
+                allow_broken: Some(true),
+                ..Query::new("ell", SearchStrategy::MatchName, None, true).unwrap()
+            }),
+            vec![
+                "x86_64-linux.hello",
+                "x86_64-linux.hello-wayland",
+                "x86_64-linux.othello"
+            ]
+        );
+    }
+
+    #[test]
+    fn searches_requested_systems() {
+        assert_eq!(
+            search_query(Query {
+                systems: Some(vec!["aarch64-darwin".to_string()]),
+                ..hello_query()
+            }),
+            vec!["aarch64-darwin.hello"]
+        );
+    }
+
+    #[test]
+    fn filters_descriptions() {
+        assert_eq!(
+            search_query(Query {
+                description: Some(Regex::new("(?i)friendly greeting").unwrap()),
+                ..hello_query()
+            }),
+            vec!["x86_64-linux.hello"]
+        );
+    }
+
+    #[test]
+    fn limits_results_and_reports_count() {
+        let (tempdir, lockfile) = index_fixture(true);
+        let index = SearchIndex::open(tempdir.path(), &lockfile, &SYSTEM.to_string()).unwrap();
+        let query = Query::new("hello", SearchStrategy::MatchName, Some(1), true).unwrap();
+        let results = index.search(&query).unwrap();
+        assert_eq!(results.results.len(), 1);
+        assert_eq!(results.count, Some(2));
+        assert_eq!(results.results[0].abs_path, vec![
+            "legacyPackages",
+            SYSTEM,
+            "hello"
+        ]);
+    }
+
+    #[test]
+    fn rejects_unscraped_inputs() {
+        let (tempdir, lockfile) = index_fixture(false);
+        let index = SearchIndex::open(tempdir.path(), &lockfile, &SYSTEM.to_string()).unwrap();
+        let query = Query::new("hello", SearchStrategy::MatchName, None, true).unwrap();
+        assert!(matches!(
+            index.search(&query),
+            Err(SearchIndexError::NotScraped(_))
+        ));
+
+        let empty_cache = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            SearchIndex::open(empty_cache.path(), &lockfile, &SYSTEM.to_string()),
+            Err(SearchIndexError::NotScraped(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_semver_queries() {
+        let (tempdir, lockfile) = index_fixture(true);
+        let index = SearchIndex::open(tempdir.path(), &lockfile, &SYSTEM.to_string()).unwrap();
+        let query = Query::new("hello@>=2", SearchStrategy::MatchName, None, true).unwrap();
+        assert!(matches!(
+            index.search(&query),
+            Err(SearchIndexError::UnsupportedQuery)
+        ));
+    }
+}
@@ -1,10 +1,10 @@
-use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 #[cfg(target_os = "macos")]
 use std::ffi::OsStr;
 use std::ffi::OsString;
 use std::fs::{self, File};
 use std::io::{stdin, stdout, Write};
+use std::num::NonZeroUsize;
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -13,15 +13,21 @@ use std::{env, vec};
 
 use anyhow::{anyhow, bail, Context, Result};
 use bpaf::Bpaf;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
 use crossterm::tty::IsTty;
+use flox_rust_sdk::data::System;
 use flox_rust_sdk::flox::{EnvironmentName, EnvironmentOwner, EnvironmentRef, Flox};
+use flox_rust_sdk::models::daemon::{self, BuildState, EnvironmentStatus, PrepareResult};
+use flox_rust_sdk::models::environment::generations::{AllGenerationsMetadata, GenerationId};
 use flox_rust_sdk::models::environment::managed_environment::{
     ManagedEnvironment,
     ManagedEnvironmentError,
     PullResult,
+    GENERATION_LOCK_FILENAME,
 };
 use flox_rust_sdk::models::environment::path_environment::{self};
 use flox_rust_sdk::models::environment::{
+    lock_detached,
     CanonicalPath,
     CoreEnvironmentError,
     EditResult,
@@ -31,8 +37,11 @@ use flox_rust_sdk::models::environment::{
     ManagedPointer,
     PathPointer,
     UpdateResult,
+    DEFAULT_KEEP_GENERATIONS,
+    DEFAULT_MAX_AGE_DAYS,
     DOT_FLOX,
     ENVIRONMENT_POINTER_FILENAME,
+    ENV_DIR_NAME,
     FLOX_ACTIVE_ENVIRONMENTS_VAR,
     FLOX_ENV_CACHE_VAR,
     FLOX_ENV_DIRS_VAR,
@@ -41,21 +50,38 @@ use flox_rust_sdk::models::environment::{
     FLOX_ENV_VAR,
     FLOX_PATH_PATCHED_VAR,
     FLOX_PROMPT_ENVIRONMENTS_VAR,
+    LOCKFILE_FILENAME,
+    MANIFEST_FILENAME,
 };
 use flox_rust_sdk::models::lockfile::{
     Input,
     InstalledPackage,
     LockedManifest,
+    LockedManifestDiff,
     LockedManifestError,
     PackageInfo,
+    PackageVersion,
     TypedLockedManifest,
 };
-use flox_rust_sdk::models::manifest::{self, PackageToInstall};
-use flox_rust_sdk::models::pkgdb::{self, error_codes, CallPkgDbError, PkgDbError, ScrapeError};
+use flox_rust_sdk::models::manifest::{
+    self,
+    Manifest,
+    ManifestError,
+    ManifestPackageRepository,
+    PackageToInstall,
+};
+use flox_rust_sdk::models::pkgdb::{
+    self,
+    error_codes,
+    CallPkgDbError,
+    PkgDbError,
+    ScrapeInputsError,
+};
 use indexmap::IndexSet;
 use indoc::{formatdoc, indoc};
 use itertools::Itertools;
 use log::{debug, warn};
+use serde::{Deserialize, Serialize};
 use toml_edit::Document;
 use url::Url;
 
@@ -70,7 +96,7 @@ use crate::commands::{
     UninitializedEnvironment,
 };
 use crate::config::Config;
-use crate::utils::dialog::{Confirm, Dialog, Select, Spinner};
+use crate::utils::dialog::{Confirm, Dialog, Progress, Select, Spinner};
 use crate::utils::didyoumean::{DidYouMean, InstallSuggestion};
 use crate::utils::errors::{
     apply_doc_link_for_unsupported_packages,
@@ -79,6 +105,8 @@ use crate::utils::errors::{
     format_locked_manifest_error,
 };
 use crate::utils::openers::Shell;
+use crate::utils::picker::pick_packages;
+use crate::utils::shell_backend::{self, ShellBackend};
 use crate::utils::{default_nix_env_vars, message};
 use crate::{subcommand_metric, utils};
 
@@ -227,8 +255,11 @@ impl Edit {
             .map_err(apply_doc_link_for_unsupported_packages);
 
             match result {
-                Err(EnvironmentError2::Core(CoreEnvironmentError::LockedManifest(e))) => {
-                    message::error(format_locked_manifest_error(&e));
+                Err(EnvironmentError2::Core(
+                    e @ (CoreEnvironmentError::LockedManifest(_)
+                    | CoreEnvironmentError::DeserializeManifest(_)),
+                )) => {
+                    message::error(format_core_error(&e));
 
                     if !Dialog::can_prompt() {
                         bail!("Can't prompt to continue editing in non-interactive context");
@@ -377,17 +408,101 @@ pub struct Activate {
     #[bpaf(long("print-script"), short, hide)]
     print_script: bool,
 
+    /// Print the changes activation makes to the environment as dotenv
+    #[bpaf(long("print-env"))]
+    print_env: bool,
+
+    /// Print the changes activation makes to the environment as JSON
+    /// (implies '--print-env')
+    #[bpaf(long)]
+    json: bool,
+
+    /// Replace the environment activated last by 'eval "$(flox activate)"'
+    /// instead of activating on top of it
+    #[bpaf(long)]
+    replace: bool,
+
     /// Command to run interactively in the context of the environment
     #[bpaf(positional("cmd"), strict, many)]
     run_args: Vec<String>,
 }
 
+/// Formats of the changes activation makes to the environment
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PrintEnvFormat {
+    /// `flox activate --print-env`
+    Dotenv,
+    /// `flox activate --json`
+    Json,
+    /// `flox direnv`
+    Direnv,
+}
+
+/// What [Activate::activate] does with the activated environment
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ActivationMode {
+    /// Start an interactive shell, run a command in a shell,
+    /// or print a script for in-place activation
+    Shell,
+    /// Print the changes activation makes to the environment
+    PrintEnv(PrintEnvFormat),
+    /// `flox run`, execute a command directly
+    Exec {
+        /// Skip the environment's profile scripts and hooks
+        skip_hooks: bool,
+    },
+}
+
 impl Activate {
-    pub async fn handle(self, mut config: Config, flox: Flox) -> Result<()> {
+    pub async fn handle(self, config: Config, flox: Flox) -> Result<()> {
         subcommand_metric!("activate");
 
+        let mode = if self.json {
+            ActivationMode::PrintEnv(PrintEnvFormat::Json)
+        } else if self.print_env {
+            ActivationMode::PrintEnv(PrintEnvFormat::Dotenv)
+        } else {
+            ActivationMode::Shell
+        };
+
+        self.activate(config, flox, mode).await
+    }
+
+    async fn activate(self, mut config: Config, flox: Flox, mode: ActivationMode) -> Result<()> {
+        let print_env = match mode {
+            ActivationMode::PrintEnv(format) => Some(format),
+            _ => None,
+        };
+
+        // Undo the last activation before evaluating anything,
+        // so that the new environment takes its place on the stack.
+        let replaced = if self.replace {
+            if print_env.is_some() {
+                bail!("'--replace' can not be combined with '--print-env' or '--json'");
+            }
+            let (depth, snapshot) = ActivationSnapshot::last_activation()?;
+            snapshot.restore_in_process(depth);
+            Some((depth, snapshot))
+        } else {
+            None
+        };
+
         let mut concrete_environment = self.environment.to_concrete_environment(&flox)?;
 
+        // direnv reloads when any of these change
+        let direnv_watch_files = match (print_env, &concrete_environment) {
+            (Some(PrintEnvFormat::Direnv), ConcreteEnvironment::Remote(_)) => {
+                bail!("direnv can only load local environments, use '--dir' instead of '--remote'")
+            },
+            (Some(PrintEnvFormat::Direnv), ConcreteEnvironment::Path(env)) => {
+                Self::direnv_watch_files(&env.path)
+            },
+            (Some(PrintEnvFormat::Direnv), ConcreteEnvironment::Managed(env)) => {
+                Self::direnv_watch_files(&env.path)
+            },
+            _ => vec![],
+        };
+
         // TODO could move this to a pretty print method on the Environment trait?
         let prompt_name = match concrete_environment {
             // Note that the same environment could show up twice without any
@@ -410,11 +525,22 @@ impl Activate {
         let now_active =
             UninitializedEnvironment::from_concrete_environment(&concrete_environment)?;
 
+        let daemon_status = Self::notify_daemon(&flox, &concrete_environment);
+        // floxd keeps the environment built, so there is nothing to wait for
+        let prebuilt = matches!(
+            daemon_status,
+            Some(EnvironmentStatus {
+                build: BuildState::Ready { .. },
+                ..
+            })
+        );
+
         let environment = concrete_environment.dyn_environment_ref_mut();
 
-        let in_place = self.print_script || (!stdout().is_tty() && self.run_args.is_empty());
+        let in_place = mode == ActivationMode::Shell
+            && (self.print_script || (!stdout().is_tty() && self.run_args.is_empty()));
         // Don't spin in bashrcs and similar contexts
-        let activation_path_result = if in_place {
+        let activation_path_result = if in_place || mode != ActivationMode::Shell || prebuilt {
             environment.activation_path(&flox)
         } else {
             Dialog {
@@ -476,7 +602,7 @@ impl Activate {
             Self::fixup_path(&flox_env_install_prefixes).transpose()?;
 
         // Detect if the current environment is already active
-        if flox_active_environments.is_active(&now_active) {
+        if flox_active_environments.is_active(&now_active) && mode == ActivationMode::Shell {
             if !in_place {
                 // Error if interactive and already active
                 bail!("Environment '{now_active}' is already active.");
@@ -486,6 +612,19 @@ impl Activate {
             return Ok(());
         }
 
+        // Environments activated outside of direnv are left alone,
+        // as direnv would otherwise add them twice.
+        if flox_active_environments.is_active(&now_active)
+            && print_env == Some(PrintEnvFormat::Direnv)
+        {
+            debug!("Environment is already active: environment={now_active}. Only watching files");
+            println!(
+                "{}",
+                ActivationEnvDiff::default().to_direnv(&BTreeMap::new(), &direnv_watch_files)
+            );
+            return Ok(());
+        }
+
         // Add to _FLOX_ACTIVE_ENVIRONMENTS so we can detect what environments are active.
         flox_active_environments.set_last_active(now_active.clone());
 
@@ -562,11 +701,84 @@ impl Activate {
         //    eval "$(flox activate)"
         if in_place {
             let shell = Self::detect_shell_for_in_place()?;
-            Self::activate_in_place(&shell, &exports, &activation_path);
+
+            // Record what activation changes, so that `flox deactivate` can undo it
+            let depth = flox_active_environments.len();
+            let manifest_vars = Self::manifest_vars(environment, &flox);
+            let snapshot = ActivationSnapshot::capture(
+                exports
+                    .keys()
+                    .copied()
+                    .chain(ActivationSnapshot::PROFILE_VARS)
+                    .chain(manifest_vars.keys().map(String::as_str)),
+            );
+            let snapshot_var = ActivationSnapshot::var(depth);
+            let mut exports = exports;
+            exports.insert(&snapshot_var, serde_json::to_string(&snapshot)?);
+
+            if let Some((replaced_depth, replaced)) = replaced {
+                println!(
+                    "{}",
+                    replaced.render_restore(shell.backend(), replaced_depth)
+                );
+            }
+            Self::activate_in_place(
+                &shell,
+                &exports,
+                &activation_path,
+                &ActivationSnapshot::prompt_var(depth),
+            );
+
+            return Ok(());
+        }
+
+        if let Some(format) = print_env {
+            let activated = Self::evaluate_activation(&exports, &activation_path, false)?;
+            let current = env::vars_os()
+                .map(|(key, value)| {
+                    (
+                        key.to_string_lossy().into_owned(),
+                        value.to_string_lossy().into_owned(),
+                    )
+                })
+                .collect();
+            let diff = ActivationEnvDiff::new(&current, &activated);
+
+            match format {
+                PrintEnvFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+                PrintEnvFormat::Dotenv => println!("{}", diff.to_dotenv(&activated)),
+                PrintEnvFormat::Direnv => {
+                    println!("{}", diff.to_direnv(&activated, &direnv_watch_files))
+                },
+            }
 
             return Ok(());
         }
 
+        if let ActivationMode::Exec { skip_hooks } = mode {
+            // Without the profile scripts, vars are only set by the manifest
+            let manifest_vars = if skip_hooks {
+                Self::manifest_vars(environment, &flox)
+            } else {
+                BTreeMap::new()
+            };
+            let mut exports = exports;
+            exports.extend(
+                manifest_vars
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.clone())),
+            );
+
+            return Self::activate_exec(self.run_args, &exports, &activation_path, skip_hooks);
+        }
+
+        if daemon_status.is_some_and(|status| status.upstream_changed) {
+            message::plain(formatdoc! {"
+                Updates to environment {now_active} are available on FloxHub.
+                Run 'flox pull' to apply them.
+            "});
+        }
+
         let shell = Self::detect_shell_for_subshell()?;
         let activate_error = if !self.run_args.is_empty() {
             Self::activate_command(self.run_args, shell, exports, activation_path)
@@ -588,19 +800,22 @@ impl Activate {
 
         command.envs(exports);
 
+        let backend = shell.backend();
         let script = formatdoc! {r#"
                 # to avoid infinite recursion sourcing bashrc
-                export FLOX_SOURCED_FROM_SHELL_RC=1
+                {set_sourced_from_rc}
 
                 # TODO: this script sets prompt, which isn't necessary
-                source {activation_path}/activate/{shell}
+                {source_activation_script}
 
-                unset FLOX_SOURCED_FROM_SHELL_RC
+                {unset_sourced_from_rc}
 
-                {quoted_args}
+                {run_args}
         "#,
-            activation_path=shell_escape::escape(activation_path.to_string_lossy()),
-            quoted_args = Self::quote_run_args(&run_args)
+            set_sourced_from_rc = backend.export("FLOX_SOURCED_FROM_SHELL_RC", "1"),
+            source_activation_script = backend.source(&Self::activation_script(&shell, &activation_path)),
+            unset_sourced_from_rc = backend.unset("FLOX_SOURCED_FROM_SHELL_RC"),
+            run_args = backend.run(&run_args),
         };
 
         command.arg("-c");
@@ -612,6 +827,34 @@ impl Activate {
         command.exec().into()
     }
 
+    /// Used for `flox run`
+    ///
+    /// Unlike [Self::activate_command], the command is executed directly
+    /// with the environment produced by the activation scripts,
+    /// so its exit status and signals reach the caller unaltered.
+    /// This function only returns if the command could not be executed.
+    fn activate_exec(
+        run_args: Vec<String>,
+        exports: &HashMap<&str, String>,
+        activation_path: &Path,
+        skip_hooks: bool,
+    ) -> Result<()> {
+        let Some((program, args)) = run_args.split_first() else {
+            bail!("No command to run");
+        };
+
+        let activated = Self::evaluate_activation(exports, activation_path, skip_hooks)?;
+
+        let mut command = Command::new(program);
+        command.args(args).env_clear().envs(activated);
+
+        debug!("running command: {:?}", command);
+
+        // exec only returns on error
+        let err = command.exec();
+        bail!("Could not run '{program}': {err}")
+    }
+
     /// Activate the environment interactively by spawning a new shell
     /// and running the respective activation scripts.
     ///
@@ -625,45 +868,10 @@ impl Activate {
         let mut command = Command::new(shell.exe_path());
         command.envs(exports);
 
-        match shell {
-            Shell::Bash(_) => {
-                command
-                    .arg("--rcfile")
-                    .arg(activation_path.join("activate").join("bash"));
-            },
-            Shell::Zsh(_) => {
-                // From man zsh:
-                // Commands are then read from $ZDOTDIR/.zshenv.  If the shell is a
-                // login shell, commands are read from /etc/zprofile and then
-                // $ZDOTDIR/.zprofile.  Then, if the shell is interactive, commands
-                // are read from /etc/zshrc and then $ZDOTDIR/.zshrc.  Finally, if
-                // the shell is a login shell, /etc/zlogin and $ZDOTDIR/.zlogin are
-                // read.
-                //
-                // We want to add our customizations as late as possible in the
-                // initialization process - if, e.g. the user has prompt
-                // customizations, we want ours to go last. So we put our
-                // customizations at the end of .zshrc, passing our customizations
-                // using FLOX_ZSH_INIT_SCRIPT.
-                // Otherwise, we want initialization to proceed as normal, so the
-                // files in our ZDOTDIR source global rcs and user rcs.
-                // We disable global rc files and instead source them manually so we
-                // can control the ZDOTDIR they are run with - this is important
-                // since macOS sets
-                // HISTFILE=${ZDOTDIR:-$HOME}/.zsh_history
-                // in /etc/zshrc.
-                if let Ok(zdotdir) = env::var("ZDOTDIR") {
-                    command.env("FLOX_ORIG_ZDOTDIR", zdotdir);
-                }
-                command
-                    .env("ZDOTDIR", env!("FLOX_ZDOTDIR"))
-                    .env(
-                        "FLOX_ZSH_INIT_SCRIPT",
-                        activation_path.join("activate").join("zsh"),
-                    )
-                    .arg("--no-globalrcs");
-            },
-        };
+        shell.backend().configure_interactive(
+            &mut command,
+            &Self::activation_script(&shell, &activation_path),
+        );
 
         debug!("running activation command: {:?}", command);
 
@@ -776,9 +984,12 @@ impl Activate {
                 "Patching PATH to {}",
                 fixed_up_path_joined.to_string_lossy()
             );
+            let shell = Self::detect_shell_for_in_place()?;
             println!(
-                "export PATH={}",
-                shell_escape::escape(fixed_up_path_joined.to_string_lossy())
+                "{}",
+                shell
+                    .backend()
+                    .export("PATH", &fixed_up_path_joined.to_string_lossy())
             );
         } else {
             debug!("No path patching needed");
@@ -787,41 +998,147 @@ impl Activate {
     }
 
     /// Used for `eval "$(flox activate)"`
-    fn activate_in_place(shell: &Shell, exports: &HashMap<&str, String>, activation_path: &Path) {
-        let exports_rendered = exports
+    fn activate_in_place(
+        shell: &Shell,
+        exports: &HashMap<&str, String>,
+        activation_path: &Path,
+        prompt_var: &str,
+    ) {
+        let script = Self::render_in_place(shell, exports, activation_path, prompt_var);
+
+        println!("{script}");
+    }
+
+    /// Render the script printed by [Self::activate_in_place]
+    ///
+    /// The script doesn't contain comments,
+    /// as e.g. fish users may run `eval (flox activate)` which joins all lines.
+    /// The prompt is saved to `prompt_var` before the activation script changes it.
+    fn render_in_place(
+        shell: &Shell,
+        exports: &HashMap<&str, String>,
+        activation_path: &Path,
+        prompt_var: &str,
+    ) -> String {
+        let backend = shell.backend();
+
+        exports
             .iter()
-            .map(|(key, value)| (key, shell_escape::escape(Cow::Borrowed(value))))
-            .map(|(key, value)| format!("export {key}={value}",))
-            .join("\n");
+            .map(|(key, value)| backend.export(key, value))
+            .chain(backend.save_prompt(prompt_var))
+            .chain([
+                // to avoid infinite recursion sourcing bashrc
+                backend.export("FLOX_SOURCED_FROM_SHELL_RC", "1"),
+                backend.source(&Self::activation_script(shell, activation_path)),
+                backend.unset("FLOX_SOURCED_FROM_SHELL_RC"),
+            ])
+            .join("\n")
+    }
+
+    /// Evaluate vars, profile scripts and hooks of an environment
+    /// in a clean POSIX subshell and return the resulting environment
+    ///
+    /// Used for `flox activate --print-env` and `flox run`.
+    /// The subshell doesn't read any rc files.
+    /// Output of the activation scripts is redirected to stderr,
+    /// so that only the environment, printed as JSON by `flox print-env`,
+    /// is written to stdout.
+    ///
+    /// With `skip_hooks`, only the `etc/profile.d` scripts setting up
+    /// e.g. `PATH` are sourced, but not the environment's vars,
+    /// profile scripts and hooks.
+    fn evaluate_activation(
+        exports: &HashMap<&str, String>,
+        activation_path: &Path,
+        skip_hooks: bool,
+    ) -> Result<BTreeMap<String, String>> {
+        let script = if skip_hooks {
+            r#"for p in "$FLOX_ENV/etc/profile.d"/*.sh; do if [ -f "$p" ]; then . "$p" >&2; fi; done; exec "$2" print-env"#
+        } else {
+            r#". "$1" >&2; exec "$2" print-env"#
+        };
 
-        let script = formatdoc! {"
-                # Common flox environment variables
-                {exports_rendered}
+        let shell = Shell::Posix("sh".into());
+        let mut command = Command::new(shell.exe_path());
+        command
+            .envs(exports)
+            .arg("-c")
+            .arg(script)
+            .arg("sh")
+            .arg(Self::activation_script(&shell, activation_path))
+            .arg(env::current_exe().context("could not find the flox executable")?)
+            .stderr(std::process::Stdio::inherit());
+
+        debug!("evaluating activation: {:?}", command);
+
+        let output = command
+            .output()
+            .context("could not run activation scripts")?;
+        if !output.status.success() {
+            bail!("Activation scripts failed with {}", output.status);
+        }
 
-                # to avoid infinite recursion sourcing bashrc
-                export FLOX_SOURCED_FROM_SHELL_RC=1
+        let activated = serde_json::from_slice(&output.stdout)
+            .context("could not read the environment after activation")?;
 
-                source {activation_path}/activate/{shell}
+        Ok(activated)
+    }
 
-                unset FLOX_SOURCED_FROM_SHELL_RC
-            ",
-        activation_path=shell_escape::escape(activation_path.to_string_lossy()),
+    /// Register a local environment with floxd and return its status
+    ///
+    /// Activation doesn't depend on the daemon running,
+    /// so failing to reach it is not an error.
+    fn notify_daemon(flox: &Flox, environment: &ConcreteEnvironment) -> Option<EnvironmentStatus> {
+        let dot_flox_path = match environment {
+            ConcreteEnvironment::Path(env) => env.path.to_path_buf(),
+            ConcreteEnvironment::Managed(env) => env.path.to_path_buf(),
+            ConcreteEnvironment::Remote(_) => return None,
         };
 
-        println!("{script}");
+        let request = daemon::Request::Used { dot_flox_path };
+        match daemon::request(&daemon::socket_path(&flox.cache_dir), &request) {
+            Ok(daemon::Response::Environment { status }) => Some(status),
+            Ok(response) => {
+                debug!("unexpected response from floxd: {response:?}");
+                None
+            },
+            Err(err) => {
+                debug!("could not query floxd: {err}");
+                None
+            },
+        }
+    }
+
+    /// Variables set by the manifest of `environment`,
+    /// empty if the manifest can't be read
+    fn manifest_vars(environment: &dyn Environment, flox: &Flox) -> BTreeMap<String, String> {
+        environment
+            .manifest_content(flox)
+            .ok()
+            .and_then(|contents| Manifest::from_str(&contents).ok())
+            .and_then(|manifest| manifest.vars)
+            .unwrap_or_default()
     }
 
-    /// Quote run args so that words don't get split,
-    /// but don't escape all characters.
+    /// Files defining the environment in `dot_flox_path`
     ///
-    /// To do this we escape `"`,
-    /// but we don't escape anything else.
-    /// We want `$` for example to be expanded by the shell.
-    fn quote_run_args(run_args: &[String]) -> String {
-        run_args
-            .iter()
-            .map(|arg| format!(r#""{}""#, arg.replace('"', r#"\""#)))
-            .join(" ")
+    /// Managed environments are defined by the generation
+    /// locked in [GENERATION_LOCK_FILENAME], e.g. after `flox pull`.
+    fn direnv_watch_files(dot_flox_path: &Path) -> Vec<PathBuf> {
+        vec![
+            dot_flox_path.join(ENV_DIR_NAME).join(MANIFEST_FILENAME),
+            dot_flox_path.join(ENV_DIR_NAME).join(LOCKFILE_FILENAME),
+            dot_flox_path.join(ENVIRONMENT_POINTER_FILENAME),
+            dot_flox_path.join(GENERATION_LOCK_FILENAME),
+        ]
+    }
+
+    /// Path of the activation script for `shell`
+    /// generated in the environment at `activation_path`
+    fn activation_script(shell: &Shell, activation_path: &Path) -> PathBuf {
+        activation_path
+            .join("activate")
+            .join(shell.backend().name())
     }
 
     /// Detect the shell to use for activation
@@ -853,6 +1170,361 @@ impl Activate {
     }
 }
 
+/// Changes made to the environment by activation
+///
+/// Printed by `flox activate --print-env`
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct ActivationEnvDiff {
+    /// Variables set to a new value
+    set: BTreeMap<String, String>,
+    /// Variables removed from the environment
+    unset: Vec<String>,
+    /// Entries prepended to colon separated lists such as `PATH`
+    prepend: BTreeMap<String, Vec<String>>,
+}
+
+impl ActivationEnvDiff {
+    /// Variables maintained by the shell rather than by activation
+    const IGNORED: [&'static str; 4] = ["_", "OLDPWD", "PWD", "SHLVL"];
+
+    fn new(current: &BTreeMap<String, String>, activated: &BTreeMap<String, String>) -> Self {
+        let mut diff = Self::default();
+
+        for (key, value) in activated {
+            if Self::IGNORED.contains(&key.as_str()) {
+                continue;
+            }
+            match current.get(key) {
+                Some(current_value) if current_value == value => {},
+                Some(current_value)
+                    if !current_value.is_empty()
+                        && value.ends_with(&format!(":{current_value}")) =>
+                {
+                    let prepended = &value[..value.len() - current_value.len() - 1];
+                    diff.prepend.insert(
+                        key.clone(),
+                        prepended.split(':').map(String::from).collect(),
+                    );
+                },
+                _ => {
+                    diff.set.insert(key.clone(), value.clone());
+                },
+            }
+        }
+
+        diff.unset = current
+            .keys()
+            .filter(|key| !activated.contains_key(*key) && !Self::IGNORED.contains(&key.as_str()))
+            .cloned()
+            .collect();
+
+        diff
+    }
+
+    /// Render the changes as commands for direnv's `.envrc`
+    ///
+    /// direnv computes and caches the resulting changes itself,
+    /// and reverts them when leaving the directory.
+    fn to_direnv(&self, activated: &BTreeMap<String, String>, watch_files: &[PathBuf]) -> String {
+        let backend = shell_backend::Bash;
+        let watch = watch_files
+            .iter()
+            .map(|path| backend.quote(&path.to_string_lossy()).into_owned())
+            .join(" ");
+
+        [format!("watch_file {watch}")]
+            .into_iter()
+            .chain(
+                self.set
+                    .keys()
+                    .chain(self.prepend.keys())
+                    .sorted()
+                    .map(|key| backend.export(key, &activated[key])),
+            )
+            .chain(self.unset.iter().map(|key| backend.unset(key)))
+            .join("\n")
+    }
+
+    /// Render the final values of all set or prepended variables as dotenv
+    ///
+    /// dotenv can't express unsetting a variable,
+    /// so unset variables are omitted.
+    fn to_dotenv(&self, activated: &BTreeMap<String, String>) -> String {
+        self.set
+            .keys()
+            .chain(self.prepend.keys())
+            .sorted()
+            .map(|key| {
+                let value = activated[key]
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                format!("{key}=\"{value}\"")
+            })
+            .join("\n")
+    }
+}
+
+/// Run a command in an environment without starting a shell
+///
+/// The command is executed directly rather than through '$SHELL -c',
+/// so its exit status and signals are passed on unaltered.
+#[derive(Bpaf, Clone)]
+pub struct Run {
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+
+    /// Trust a remote environment temporarily for this command
+    #[bpaf(long, short)]
+    trust: bool,
+
+    /// Don't run the environment's profile scripts and hooks
+    #[bpaf(long("no-hooks"))]
+    no_hooks: bool,
+
+    /// Command to run and its arguments
+    #[bpaf(positional("cmd"), strict, some("Must specify a command to run"))]
+    cmd: Vec<String>,
+}
+
+impl Run {
+    pub async fn handle(self, config: Config, flox: Flox) -> Result<()> {
+        subcommand_metric!("run");
+
+        let activate = Activate {
+            environment: self.environment,
+            trust: self.trust,
+            print_script: false,
+            print_env: false,
+            json: false,
+            replace: false,
+            run_args: self.cmd,
+        };
+        activate
+            .activate(config, flox, ActivationMode::Exec {
+                skip_hooks: self.no_hooks,
+            })
+            .await
+    }
+}
+
+/// Build an environment ahead of activation
+///
+/// Run by floxd to keep environments ready,
+/// prints a [PrepareResult] as JSON.
+#[derive(Bpaf, Clone)]
+pub struct Prepare {
+    /// Path containing a .flox/ directory
+    #[bpaf(long, short, argument("path"))]
+    dir: PathBuf,
+
+    /// Also fetch updates of a managed environment from FloxHub
+    #[bpaf(long)]
+    fetch: bool,
+}
+
+impl Prepare {
+    pub async fn handle(self, flox: Flox) -> Result<()> {
+        // Not counted as a subcommand, floxd runs this in the background
+
+        let mut environment = EnvironmentSelect::Dir(self.dir).to_concrete_environment(&flox)?;
+
+        let upstream_changed = match environment {
+            ConcreteEnvironment::Managed(ref env) if self.fetch => Some(env.fetch_upstream()?),
+            _ => None,
+        };
+        let activation_path = environment
+            .dyn_environment_ref_mut()
+            .activation_path(&flox)?;
+
+        let result = PrepareResult {
+            activation_path,
+            upstream_changed,
+        };
+        println!("{}", serde_json::to_string(&result)?);
+
+        Ok(())
+    }
+}
+
+/// Values of variables before an in-place activation
+///
+/// Every `eval "$(flox activate)"` exports a snapshot as
+/// `_FLOX_ACTIVATION_SNAPSHOT_<n>`, where `<n>` is the number of active
+/// environments including the one activated, and saves the prompt of
+/// shells that don't render the flox indicator dynamically as
+/// `_FLOX_ACTIVATION_PS1_<n>`.
+/// `flox deactivate` and `flox activate --replace` restore the snapshot
+/// of the environment activated last.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct ActivationSnapshot {
+    /// Variables changed by activation and their previous value,
+    /// [None] if they were unset
+    vars: BTreeMap<String, Option<String>>,
+    /// The prompt before activation
+    #[serde(skip)]
+    prompt: Option<String>,
+}
+
+impl ActivationSnapshot {
+    /// Variables modified by the profile scripts of every environment
+    const PROFILE_VARS: [&'static str; 14] = [
+        "PATH",
+        "MANPATH",
+        "INFOPATH",
+        "CPATH",
+        "LIBRARY_PATH",
+        "PKG_CONFIG_PATH",
+        "ACLOCAL_PATH",
+        "XDG_DATA_DIRS",
+        "FPATH",
+        "LD_AUDIT",
+        "DYLD_FALLBACK_LIBRARY_PATH",
+        "PYTHONPATH",
+        "PIP_CONFIG_FILE",
+        "FLOX_SAVE_PS1",
+    ];
+
+    fn var(depth: usize) -> String {
+        format!("_FLOX_ACTIVATION_SNAPSHOT_{depth}")
+    }
+
+    fn prompt_var(depth: usize) -> String {
+        format!("_FLOX_ACTIVATION_PS1_{depth}")
+    }
+
+    /// Record the current values of `keys`
+    fn capture<'a>(keys: impl IntoIterator<Item = &'a str>) -> Self {
+        let vars = keys
+            .into_iter()
+            .map(|key| (key.to_string(), env::var(key).ok()))
+            .collect();
+
+        Self { vars, prompt: None }
+    }
+
+    /// Read the snapshot of the environment activated last
+    /// and the number of active environments
+    fn last_activation() -> Result<(usize, Self)> {
+        let active_environments = activated_environments();
+        if active_environments.is_empty() {
+            bail!("No environment is active");
+        }
+        let depth = active_environments.len();
+
+        let Ok(serialized) = env::var(Self::var(depth)) else {
+            bail!(indoc! {r#"
+                The environment activated last was not activated with 'eval "$(flox activate)"'.
+                Type 'exit' to leave an environment activated in a subshell."#});
+        };
+        let mut snapshot: Self = serde_json::from_str(&serialized)
+            .context("Could not read the state before activating the environment")?;
+        snapshot.prompt = env::var(Self::prompt_var(depth)).ok();
+
+        Ok((depth, snapshot))
+    }
+
+    /// Render commands restoring the snapshot taken at `depth`
+    fn render_restore(&self, backend: &dyn ShellBackend, depth: usize) -> String {
+        self.prompt
+            .as_deref()
+            .and_then(|prompt| backend.restore_prompt(prompt))
+            .into_iter()
+            .chain(self.vars.iter().map(|(key, value)| match value {
+                Some(value) => backend.export(key, value),
+                None => backend.unset(key),
+            }))
+            .chain([
+                backend.unset(&Self::var(depth)),
+                backend.unset(&Self::prompt_var(depth)),
+            ])
+            .join("\n")
+    }
+
+    /// Restore the snapshot taken at `depth` in the environment of this process
+    fn restore_in_process(&self, depth: usize) {
+        for (key, value) in &self.vars {
+            match value {
+                Some(value) => env::set_var(key, value),
+                None => env::remove_var(key),
+            }
+        }
+        env::remove_var(Self::var(depth));
+        env::remove_var(Self::prompt_var(depth));
+    }
+}
+
+/// Leave the environment activated last by 'eval "$(flox activate)"'
+///
+/// Prints a script restoring the variables changed by the activation,
+/// to be evaluated with 'eval "$(flox deactivate)"'.
+#[derive(Bpaf, Clone)]
+pub struct Deactivate {}
+
+impl Deactivate {
+    pub fn handle(self) -> Result<()> {
+        subcommand_metric!("deactivate");
+
+        let (depth, snapshot) = ActivationSnapshot::last_activation()?;
+
+        if stdout().is_tty() {
+            bail!(indoc! {r#"
+                'flox deactivate' prints a script that has to be evaluated by your shell.
+                Run 'eval "$(flox deactivate)"' instead."#});
+        }
+
+        let shell = Activate::detect_shell_for_in_place()?;
+        println!("{}", snapshot.render_restore(shell.backend(), depth));
+
+        Ok(())
+    }
+}
+
+/// Load an environment with direnv
+#[derive(Bpaf, Clone)]
+pub struct Direnv {
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+
+    /// Print a 'use_flox' function to add to direnv's stdlib
+    /// (e.g. ~/.config/direnv/direnvrc) instead
+    #[bpaf(long)]
+    stdlib: bool,
+}
+
+impl Direnv {
+    pub async fn handle(self, config: Config, flox: Flox) -> Result<()> {
+        subcommand_metric!("direnv");
+
+        if self.stdlib {
+            print!("{}", indoc! {r#"
+                # Load a flox environment, e.g. `use flox` or `use flox --dir ./project`
+                use_flox() {
+                  eval "$(flox direnv "$@")"
+                }
+            "#});
+            return Ok(());
+        }
+
+        let activate = Activate {
+            environment: self.environment,
+            trust: false,
+            print_script: false,
+            print_env: false,
+            json: false,
+            replace: false,
+            run_args: vec![],
+        };
+        activate
+            .activate(
+                config,
+                flox,
+                ActivationMode::PrintEnv(PrintEnvFormat::Direnv),
+            )
+            .await
+    }
+}
+
 #[cfg(test)]
 mod activate_tests {
     use super::*;
@@ -931,45 +1603,244 @@ mod activate_tests {
             assert!(shell.is_err());
         });
     }
-}
 
-// List packages installed in an environment
-#[derive(Bpaf, Clone)]
-pub struct List {
-    #[bpaf(external(environment_select), fallback(Default::default()))]
-    environment: EnvironmentSelect,
+    #[test]
+    fn test_detect_fish() {
+        temp_env::with_vars(
+            [("FLOX_SHELL", Some("/flox_shell/fish")), SHELL_UNSET],
+            || {
+                let shell = Activate::detect_shell_for_subshell().unwrap();
+                assert_eq!(shell, Shell::Fish("/flox_shell/fish".into()));
+                assert_eq!(shell.to_string(), "fish");
+            },
+        );
+    }
 
-    #[bpaf(external(list_mode), fallback(ListMode::Extended))]
-    list_mode: ListMode,
-}
+    #[test]
+    fn test_detect_posix_and_nu() {
+        for (path, name) in [
+            ("/bin/sh", "sh"),
+            ("/bin/dash", "sh"),
+            ("/bin/busybox", "sh"),
+            ("/usr/bin/nu", "nu"),
+        ] {
+            let shell = Shell::try_from(Path::new(path)).unwrap();
+            assert_eq!(shell.to_string(), name);
+        }
+    }
 
-#[derive(Bpaf, Clone, PartialEq, Debug)]
-pub enum ListMode {
-    /// Show the raw contents of the manifest
-    #[bpaf(long, short)]
-    Config,
+    fn env_vars(vars: &[(&str, &str)]) -> BTreeMap<String, String> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
 
-    /// Show only the name of each package
-    #[bpaf(long("name"), short)]
-    NameOnly,
+    #[test]
+    fn test_activation_env_diff() {
+        let current = env_vars(&[
+            ("PATH", "/usr/bin:/bin"),
+            ("HOME", "/home/flox"),
+            ("REMOVED", "1"),
+            ("SHLVL", "1"),
+        ]);
+        let activated = env_vars(&[
+            ("PATH", "/flox/env/bin:/flox/env/sbin:/usr/bin:/bin"),
+            ("HOME", "/home/flox"),
+            ("FOO", "bar"),
+            ("SHLVL", "2"),
+        ]);
 
-    /// Show the name, pkg-path, and version of each package (default)
-    #[bpaf(long, short)]
-    Extended,
+        let diff = ActivationEnvDiff::new(&current, &activated);
+        assert_eq!(diff, ActivationEnvDiff {
+            set: env_vars(&[("FOO", "bar")]),
+            unset: vec!["REMOVED".to_string()],
+            prepend: BTreeMap::from([("PATH".to_string(), vec![
+                "/flox/env/bin".to_string(),
+                "/flox/env/sbin".to_string()
+            ])]),
+        });
+    }
 
-    /// Show all available package information including priority and license
-    #[bpaf(long, short)]
-    All,
-}
+    #[test]
+    fn test_activation_env_diff_dotenv() {
+        let current = env_vars(&[("PATH", "/bin")]);
+        let activated = env_vars(&[("PATH", "/flox/bin:/bin"), ("FOO", "say \"hi\"\n")]);
+
+        let diff = ActivationEnvDiff::new(&current, &activated);
+        assert_eq!(diff.to_dotenv(&activated), indoc! {r#"
+                FOO="say \"hi\"\n"
+                PATH="/flox/bin:/bin""#});
+    }
 
-impl List {
-    pub async fn handle(self, flox: Flox) -> Result<()> {
-        subcommand_metric!("list");
+    #[test]
+    fn test_activation_env_diff_direnv() {
+        let current = env_vars(&[("PATH", "/bin"), ("REMOVED", "1")]);
+        let activated = env_vars(&[("PATH", "/flox/bin:/bin"), ("FOO", "it's")]);
+
+        let diff = ActivationEnvDiff::new(&current, &activated);
+        let watch_files = Activate::direnv_watch_files(Path::new("/project/.flox"));
+        assert_eq!(diff.to_direnv(&activated, &watch_files), indoc! {r#"
+            watch_file /project/.flox/env/manifest.toml /project/.flox/env/manifest.lock /project/.flox/env.json /project/.flox/env.lock
+            export FOO='it'\''s'
+            export PATH='/flox/bin:/bin'
+            unset REMOVED"#});
+    }
 
-        let mut env = self
-            .environment
-            .detect_concrete_environment(&flox, "list using")?
-            .into_dyn_environment();
+    #[test]
+    fn test_render_in_place_fish() {
+        let shell = Shell::Fish("/bin/fish".into());
+        let exports = HashMap::from([("FOO", r"it's a \ test".to_string())]);
+        let script = Activate::render_in_place(
+            &shell,
+            &exports,
+            Path::new("/flox env"),
+            "_FLOX_ACTIVATION_PS1_1",
+        );
+
+        assert_eq!(script, indoc! {r"
+            set -gx FOO 'it\'s a \\ test';
+            set -gx FLOX_SOURCED_FROM_SHELL_RC '1';
+            source '/flox env/activate/fish';
+            set -e FLOX_SOURCED_FROM_SHELL_RC;"});
+    }
+
+    #[test]
+    fn test_render_in_place_bash() {
+        let shell = Shell::Bash("/bin/bash".into());
+        let exports = HashMap::from([("FOO", "it's".to_string())]);
+        let script = Activate::render_in_place(
+            &shell,
+            &exports,
+            Path::new("/flox/env"),
+            "_FLOX_ACTIVATION_PS1_1",
+        );
+
+        assert_eq!(script, indoc! {r#"
+            export FOO='it'\''s'
+            export _FLOX_ACTIVATION_PS1_1="${PS1-}"
+            export FLOX_SOURCED_FROM_SHELL_RC=1
+            source /flox/env/activate/bash
+            unset FLOX_SOURCED_FROM_SHELL_RC"#});
+    }
+
+    #[test]
+    fn test_render_in_place_posix() {
+        let shell = Shell::Posix("/bin/dash".into());
+        let exports = HashMap::from([("FOO", "bar".to_string())]);
+        let script = Activate::render_in_place(
+            &shell,
+            &exports,
+            Path::new("/flox/env"),
+            "_FLOX_ACTIVATION_PS1_1",
+        );
+
+        assert_eq!(script, indoc! {r#"
+            export FOO=bar
+            export _FLOX_ACTIVATION_PS1_1="${PS1-}"
+            export FLOX_SOURCED_FROM_SHELL_RC=1
+            . /flox/env/activate/sh
+            unset FLOX_SOURCED_FROM_SHELL_RC"#});
+    }
+
+    #[test]
+    fn test_render_in_place_nu() {
+        let shell = Shell::Nu("/bin/nu".into());
+        let exports = HashMap::from([("FOO", "bar".to_string())]);
+        let script = Activate::render_in_place(
+            &shell,
+            &exports,
+            Path::new("/flox/env"),
+            "_FLOX_ACTIVATION_PS1_1",
+        );
+
+        assert_eq!(script, indoc! {r#"
+            $env.FOO = "bar"
+            $env.FLOX_SOURCED_FROM_SHELL_RC = "1"
+            source "/flox/env/activate/nu"
+            hide-env FLOX_SOURCED_FROM_SHELL_RC"#});
+    }
+
+    #[test]
+    fn test_activation_snapshot_capture() {
+        temp_env::with_vars([("SET", Some("1")), ("UNSET", None)], || {
+            let snapshot = ActivationSnapshot::capture(["SET", "UNSET"]);
+            assert_eq!(
+                snapshot.vars,
+                BTreeMap::from([
+                    ("SET".to_string(), Some("1".to_string())),
+                    ("UNSET".to_string(), None),
+                ])
+            );
+        });
+    }
+
+    #[test]
+    fn test_activation_snapshot_render_restore() {
+        let snapshot = ActivationSnapshot {
+            vars: BTreeMap::from([
+                ("FLOX_ENV".to_string(), None),
+                ("PATH".to_string(), Some("/bin".to_string())),
+            ]),
+            prompt: Some("$ ".to_string()),
+        };
+
+        assert_eq!(
+            snapshot.render_restore(&shell_backend::Bash, 2),
+            indoc! {r#"
+            PS1='$ '
+            unset FLOX_ENV
+            export PATH=/bin
+            unset _FLOX_ACTIVATION_SNAPSHOT_2
+            unset _FLOX_ACTIVATION_PS1_2"#}
+        );
+        assert_eq!(
+            snapshot.render_restore(&shell_backend::Fish, 1),
+            indoc! {r#"
+            set -e FLOX_ENV;
+            set -gx PATH '/bin';
+            set -e _FLOX_ACTIVATION_SNAPSHOT_1;
+            set -e _FLOX_ACTIVATION_PS1_1;"#}
+        );
+    }
+}
+
+// List packages installed in an environment
+#[derive(Bpaf, Clone)]
+pub struct List {
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+
+    #[bpaf(external(list_mode), fallback(ListMode::Extended))]
+    list_mode: ListMode,
+}
+
+#[derive(Bpaf, Clone, PartialEq, Debug)]
+pub enum ListMode {
+    /// Show the raw contents of the manifest
+    #[bpaf(long, short)]
+    Config,
+
+    /// Show only the name of each package
+    #[bpaf(long("name"), short)]
+    NameOnly,
+
+    /// Show the name, pkg-path, and version of each package (default)
+    #[bpaf(long, short)]
+    Extended,
+
+    /// Show all available package information including priority and license
+    #[bpaf(long, short)]
+    All,
+}
+
+impl List {
+    pub async fn handle(self, flox: Flox) -> Result<()> {
+        subcommand_metric!("list");
+
+        let mut env = self
+            .environment
+            .detect_concrete_environment(&flox, "list using")?
+            .into_dyn_environment();
 
         let manifest_contents = env.manifest_content(&flox)?;
         if self.list_mode == ListMode::Config {
@@ -1097,6 +1968,13 @@ pub struct Install {
     #[bpaf(external(pkg_with_id_option), many)]
     id: Vec<PkgWithIdOption>,
 
+    /// Search for packages and pick the ones to install,
+    /// the default if no packages are given in a terminal
+    #[bpaf(long)]
+    interactive: bool,
+
+    /// Packages as shown by 'flox search',
+    /// or flake installables such as 'github:our-org/tools#linter'
     #[bpaf(positional("packages"))]
     packages: Vec<String>,
 }
@@ -1169,8 +2047,27 @@ impl Install {
             version: None,
             input: None,
         }));
-        if packages.is_empty() {
-            bail!("Must specify at least one package");
+        for package in packages.iter_mut() {
+            let Some(input) = package.input.as_mut() else {
+                continue;
+            };
+            if let Some(absolute) = Self::absolute_flake_ref(input)? {
+                message::warning(formatdoc! {"
+                    '{input}' refers to a flake on this machine, which is stored as '{absolute}'.
+                    The environment can only be used on other machines
+                    if the flake exists at the same path there."
+                });
+                *input = absolute;
+            }
+        }
+        if packages.is_empty() || self.interactive {
+            if !Dialog::can_prompt() {
+                if self.interactive {
+                    bail!("Can't pick packages interactively without a terminal");
+                }
+                bail!("Must specify at least one package");
+            }
+            packages.extend(pick_packages(&flox, &*environment).await?);
         }
 
         let installation = Dialog {
@@ -1216,6 +2113,40 @@ impl Install {
         Ok(())
     }
 
+    /// Resolve flake references to local paths relative to the current directory,
+    /// such that they keep referring to the same flake when locking the environment
+    ///
+    /// Handles plain paths as well as `path:` and `git+file:` references.
+    /// Returns `None` for references that aren't local paths.
+    fn absolute_flake_ref(input: &str) -> Result<Option<String>> {
+        let (scheme, rest) = match ["path:", "git+file:"]
+            .into_iter()
+            .find_map(|scheme| Some((scheme, input.strip_prefix(scheme)?)))
+        {
+            Some((scheme, rest)) => (scheme, rest.strip_prefix("//").unwrap_or(rest)),
+            // Without a scheme, only paths starting with `.` or `/` are local,
+            // e.g. `nixpkgs` is an indirect reference
+            None if input.starts_with(['.', '/']) => ("", input),
+            None => return Ok(None),
+        };
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+
+        let path = std::fs::canonicalize(path)
+            .with_context(|| format!("Couldn't find a flake at '{input}'"))?;
+        let mut absolute = match scheme {
+            "git+file:" => format!("git+file://{}", path.display()),
+            _ => format!("{scheme}{}", path.display()),
+        };
+        if let Some(query) = query {
+            absolute.push('?');
+            absolute.push_str(query);
+        }
+        Ok(Some(absolute))
+    }
+
     fn handle_error(
         err: EnvironmentError2,
         flox: &Flox,
@@ -1247,6 +2178,11 @@ impl Install {
                 }
                 let path = packages[0].pkg_path.clone();
 
+                // Suggestions are only searched for in the default inputs
+                if let Some(ref input) = packages[0].input {
+                    break 'error anyhow!("Could not find package {path} in {input}.");
+                }
+
                 let head = format!("Could not find package {path}.");
 
                 let suggestion = DidYouMean::<InstallSuggestion>::new(flox, environment, &path);
@@ -1279,111 +2215,997 @@ impl Uninstall {
     pub async fn handle(self, mut flox: Flox) -> Result<()> {
         subcommand_metric!("uninstall");
 
-        debug!(
-            "uninstalling packages [{}] from {:?}",
-            self.packages.as_slice().join(", "),
-            self.environment
-        );
-        let concrete_environment = match self
+        debug!(
+            "uninstalling packages [{}] from {:?}",
+            self.packages.as_slice().join(", "),
+            self.environment
+        );
+        let concrete_environment = match self
+            .environment
+            .detect_concrete_environment(&flox, "uninstall from")
+        {
+            Ok(concrete_environment) => concrete_environment,
+            Err(EnvironmentSelectError::Environment(
+                ref e @ EnvironmentError2::DotFloxNotFound(ref dir),
+            )) => {
+                bail!(formatdoc! {"
+                {e}
+
+                Create an environment with 'flox init --dir {}'", dir.to_string_lossy()
+                })
+            },
+            Err(e @ EnvironmentSelectError::EnvNotFoundInCurrentDirectory) => {
+                bail!(formatdoc! {"
+                {e}
+
+                Create an environment with 'flox init' or uninstall packages from an environment found elsewhere with 'flox uninstall {} --dir <path>'",
+                self.packages.join(" ")})
+            },
+            Err(e) => Err(e)?,
+        };
+
+        // Ensure the user is logged in for the following remote operations
+        if let ConcreteEnvironment::Remote(_) = concrete_environment {
+            ensure_floxhub_token(&mut flox).await?;
+        };
+
+        let description = environment_description(&concrete_environment)?;
+        let mut environment = concrete_environment.into_dyn_environment();
+
+        let _ = Dialog {
+            message: &format!("Uninstalling packages from environment {description}..."),
+            help_message: None,
+            typed: Spinner::new(|| environment.uninstall(self.packages.clone(), &flox)),
+        }
+        .spin()?;
+
+        // Note, you need two spaces between this emoji and the package name
+        // otherwise they appear right next to each other.
+        self.packages.iter().for_each(|p| {
+            message::deleted(format!("'{p}' uninstalled from environment {description}"))
+        });
+        Ok(())
+    }
+}
+
+// Delete builds of non-current versions of an environment
+#[derive(Bpaf, Clone)]
+pub struct WipeHistory {
+    /// Keep the given number of most recent generations
+    /// (default: 10, unless '--newer-than' is given)
+    #[bpaf(long, argument("N"))]
+    keep: Option<usize>,
+
+    /// Keep generations created after the given date (YYYY-MM-DD)
+    /// (default: 90 days ago, unless '--keep' is given)
+    #[bpaf(long, argument("DATE"))]
+    newer_than: Option<NaiveDate>,
+
+    /// Only show what would be deleted
+    #[bpaf(long)]
+    dry_run: bool,
+
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+}
+
+impl WipeHistory {
+    pub async fn handle(self, mut flox: Flox) -> Result<()> {
+        subcommand_metric!("wipe-history");
+
+        let mut environment = self
+            .environment
+            .detect_concrete_environment(&flox, "wipe the history of")?;
+        let description = environment_description(&environment)?;
+
+        let (keep, newer_than) = self.retention();
+        let metadata = generations_metadata(&environment)?;
+        let generations = metadata.wipe_candidates(keep, newer_than);
+
+        if self.dry_run {
+            for generation in &generations {
+                message::plain(format!(
+                    "Would delete generation {generation}: {description}",
+                    description = metadata.generations[generation]
+                        .description
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                ));
+            }
+            let links = match &environment {
+                ConcreteEnvironment::Path(_) => vec![],
+                ConcreteEnvironment::Managed(environment) => {
+                    environment.generation_links(&generations)
+                },
+                ConcreteEnvironment::Remote(environment) => {
+                    environment.generation_links(&generations)
+                },
+            };
+            for link in &links {
+                message::plain(format!("Would remove link {}", link.display()));
+            }
+            if generations.is_empty() {
+                message::plain("Nothing to delete.");
+            }
+            return Ok(());
+        }
+
+        if generations.is_empty() {
+            message::plain("Nothing to delete.");
+            return Ok(());
+        }
+
+        // Ensure the user is logged in for the following remote operations
+        if let ConcreteEnvironment::Remote(_) = environment {
+            ensure_floxhub_token(&mut flox).await?;
+        };
+
+        let removed_links = Dialog {
+            message: &format!("Wiping history of environment {description}..."),
+            help_message: None,
+            typed: Spinner::new(|| match &mut environment {
+                ConcreteEnvironment::Path(environment) => {
+                    environment.wipe_history(&generations).map(|_| vec![])
+                },
+                ConcreteEnvironment::Managed(environment) => environment
+                    .wipe_history(&flox, &generations)
+                    .map_err(EnvironmentError2::from),
+                ConcreteEnvironment::Remote(environment) => {
+                    environment.wipe_history(&flox, &generations)
+                },
+            }),
+        }
+        .spin()?;
+
+        message::deleted(format!(
+            "Deleted {count} generation(s) of environment {description}.",
+            count = generations.len()
+        ));
+        if !removed_links.is_empty() {
+            message::deleted(format!(
+                "Removed {count} link(s) to builds of deleted generations.",
+                count = removed_links.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The number of generations and the creation date after which generations are kept
+    ///
+    /// Without any options, fall back to the defaults for both.
+    fn retention(&self) -> (Option<usize>, Option<DateTime<Utc>>) {
+        let newer_than = self
+            .newer_than
+            .map(|date| date.and_time(NaiveTime::MIN).and_utc());
+
+        match (self.keep, newer_than) {
+            (None, None) => (
+                Some(DEFAULT_KEEP_GENERATIONS),
+                Some(Utc::now() - Duration::days(DEFAULT_MAX_AGE_DAYS.into())),
+            ),
+            retention => retention,
+        }
+    }
+}
+
+// List environment generations with contents
+#[derive(Bpaf, Clone)]
+pub struct Generations {
+    /// Print the generations as JSON
+    #[bpaf(long)]
+    json: bool,
+
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+}
+
+/// A generation and the changes it made to its predecessor
+/// as shown by `flox generations`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct GenerationsEntry {
+    #[serde(flatten)]
+    generation: HistoryEntry,
+    changes: LockedManifestDiff,
+}
+
+impl Generations {
+    pub async fn handle(self, flox: Flox) -> Result<()> {
+        subcommand_metric!("generations");
+
+        let environment = self
+            .environment
+            .detect_concrete_environment(&flox, "list the generations of")?;
+
+        let metadata = generations_metadata(&environment)?;
+        let lockfiles = match environment {
+            ConcreteEnvironment::Path(ref environment) => environment.generations_lockfiles()?,
+            ConcreteEnvironment::Managed(ref environment) => {
+                environment.generations_lockfiles(&flox)?
+            },
+            ConcreteEnvironment::Remote(ref environment) => {
+                environment.generations_lockfiles(&flox)?
+            },
+        }
+        .into_iter()
+        .map(|(id, lockfile)| Ok((id, lockfile.map(TypedLockedManifest::try_from).transpose()?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+        let entries = Self::entries(&metadata, &lockfiles, &flox.system);
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(());
+        }
+
+        if entries.is_empty() {
+            message::warning("Environment has no generations yet.");
+            return Ok(());
+        }
+
+        println!("{}", Self::render(&entries));
+
+        Ok(())
+    }
+
+    /// Collect the generations of an environment, most recent generation first,
+    /// together with the changes against the preceding generation
+    fn entries(
+        metadata: &AllGenerationsMetadata,
+        lockfiles: &BTreeMap<GenerationId, Option<TypedLockedManifest>>,
+        system: &System,
+    ) -> Vec<GenerationsEntry> {
+        History::entries(metadata)
+            .into_iter()
+            .map(|generation| {
+                let id = GenerationId::from(generation.generation);
+                let previous = lockfiles
+                    .range(..&id)
+                    .next_back()
+                    .and_then(|(_, lockfile)| lockfile.as_ref());
+                let current = lockfiles.get(&id).and_then(Option::as_ref);
+
+                GenerationsEntry {
+                    generation,
+                    changes: LockedManifestDiff::new(previous, current, system),
+                }
+            })
+            .collect()
+    }
+
+    /// Render each generation with a summary of its changes
+    fn render(entries: &[GenerationsEntry]) -> String {
+        entries
+            .iter()
+            .map(|entry| {
+                let HistoryEntry {
+                    generation,
+                    current,
+                    created,
+                    description,
+                    ..
+                } = &entry.generation;
+                let mut lines = vec![
+                    format!(
+                        "Generation {generation}{current}",
+                        current = if *current { " (current)" } else { "" }
+                    ),
+                    format!("  Created:     {}", History::format_timestamp(created)),
+                    format!("  Description: {description}"),
+                ];
+
+                lines.extend(render_changes(&entry.changes));
+
+                lines.join("\n")
+            })
+            .join("\n\n")
+    }
+}
+
+/// Render the changes between two lockfiles, one line per change
+fn render_changes(changes: &LockedManifestDiff) -> Vec<String> {
+    let version =
+        |package: &PackageVersion| package.version.as_deref().unwrap_or("N/A").to_string();
+
+    let mut lines = Vec::new();
+    for package in &changes.added {
+        lines.push(format!(
+            "  + {id} ({version})",
+            id = package.install_id,
+            version = version(package)
+        ));
+    }
+    for package in &changes.removed {
+        lines.push(format!(
+            "  - {id} ({version})",
+            id = package.install_id,
+            version = version(package)
+        ));
+    }
+    for package in &changes.upgraded {
+        lines.push(format!(
+            "  ~ {id} ({old} -> {new})",
+            id = package.install_id,
+            old = version(&package.old),
+            new = version(&package.new)
+        ));
+    }
+    for package in &changes.downgraded {
+        lines.push(format!(
+            "  ~ {id} ({old} -> {new}, downgraded)",
+            id = package.install_id,
+            old = version(&package.old),
+            new = version(&package.new)
+        ));
+    }
+    if !changes.inputs.is_empty() {
+        lines.push(format!("  Changed inputs: {}", changes.inputs.join(", ")));
+    }
+    if !changes.vars.is_empty() {
+        lines.push(format!("  Changed variables: {}", changes.vars.join(", ")));
+    }
+    if !changes.sections.is_empty() {
+        lines.push(format!(
+            "  Changed sections: {}",
+            changes.sections.join(", ")
+        ));
+    }
+    lines
+}
+
+/// Compare the packages, variables and settings of two versions of an environment
+#[derive(Bpaf, Clone)]
+pub struct Diff {
+    /// Print the differences as JSON
+    #[bpaf(long)]
+    json: bool,
+
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+
+    #[bpaf(external(diff_select), fallback(DiffSelect::WorkingCopy {}))]
+    select: DiffSelect,
+}
+
+#[derive(Debug, Bpaf, Clone)]
+enum DiffSelect {
+    /// Compare two generations of the environment
+    Generations {
+        /// Generation to compare from
+        #[bpaf(long, argument("GENERATION"))]
+        from: usize,
+        /// Generation to compare to (default: current generation)
+        #[bpaf(long, argument("GENERATION"))]
+        to: Option<usize>,
+    },
+    /// Compare the local copy of a FloxHub environment with its upstream
+    #[bpaf(long("upstream"))]
+    Upstream,
+    /// Compare the environments in two directories
+    Directories {
+        /// Directory containing the old environment
+        #[bpaf(positional("OLD"))]
+        old: PathBuf,
+        /// Directory containing the new environment
+        #[bpaf(positional("NEW"))]
+        new: PathBuf,
+    },
+    /// Compare the uncommitted changes of an environment to its current generation
+    WorkingCopy {},
+}
+
+impl Diff {
+    pub async fn handle(self, flox: Flox) -> Result<()> {
+        subcommand_metric!("diff");
+
+        let (header, old, new) = match self.select {
+            DiffSelect::Directories { old, new } => {
+                let old_dir = Self::resolve_env_dir(&old)?;
+                let new_dir = Self::resolve_env_dir(&new)?;
+                let (old_lockfile, new_lockfile) = Dialog {
+                    message: "Locking environments...",
+                    help_message: None,
+                    typed: Spinner::new(|| {
+                        Ok::<_, EnvironmentError2>((
+                            lock_detached(&flox, old_dir)?,
+                            lock_detached(&flox, new_dir)?,
+                        ))
+                    }),
+                }
+                .spin()?;
+                (
+                    format!("Changes from {} to {}:", old.display(), new.display()),
+                    Some(old_lockfile),
+                    Some(new_lockfile),
+                )
+            },
+            select => {
+                let environment = self
+                    .environment
+                    .detect_concrete_environment(&flox, "compare")?;
+                let description = environment_description(&environment)?;
+                Self::environment_lockfiles(&flox, environment, &description, select)?
+            },
+        };
+
+        let old = old.map(TypedLockedManifest::try_from).transpose()?;
+        let new = new.map(TypedLockedManifest::try_from).transpose()?;
+        let changes = LockedManifestDiff::new(old.as_ref(), new.as_ref(), &flox.system);
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&changes)?);
+            return Ok(());
+        }
+
+        if changes.is_empty() {
+            message::plain("No differences.");
+            return Ok(());
+        }
+
+        println!("{header}\n{}", render_changes(&changes).join("\n"));
+
+        Ok(())
+    }
+
+    /// Find the lockfiles of an environment that should be compared
+    fn environment_lockfiles(
+        flox: &Flox,
+        environment: ConcreteEnvironment,
+        description: &str,
+        select: DiffSelect,
+    ) -> Result<(String, Option<LockedManifest>, Option<LockedManifest>)> {
+        match select {
+            DiffSelect::Generations { from, to } => {
+                let metadata = generations_metadata(&environment)?;
+                let lockfiles = match environment {
+                    ConcreteEnvironment::Path(ref environment) => {
+                        environment.generations_lockfiles()?
+                    },
+                    ConcreteEnvironment::Managed(ref environment) => {
+                        environment.generations_lockfiles(flox)?
+                    },
+                    ConcreteEnvironment::Remote(ref environment) => {
+                        environment.generations_lockfiles(flox)?
+                    },
+                };
+
+                let to = match to {
+                    Some(to) => to,
+                    None => match metadata.current_gen {
+                        Some(current) => *current,
+                        None => bail!("Environment {description} has no generations yet."),
+                    },
+                };
+
+                let lockfile =
+                    |generation: usize| match lockfiles.get(&GenerationId::from(generation)) {
+                        Some(lockfile) => Ok(lockfile.clone()),
+                        None => Err(anyhow!(formatdoc! {"
+                        Generation {generation} does not exist.
+
+                        Use 'flox history' to list the generations of this environment.
+                    "})),
+                    };
+
+                Ok((
+                    format!("Changes from generation {from} to generation {to} of {description}:"),
+                    lockfile(from)?,
+                    lockfile(to)?,
+                ))
+            },
+            DiffSelect::Upstream => {
+                let local = Self::current_lockfile(flox, &environment)?;
+                let upstream = match environment {
+                    ConcreteEnvironment::Managed(ref environment) => Dialog {
+                        message: "Fetching upstream environment...",
+                        help_message: None,
+                        typed: Spinner::new(|| environment.upstream_lockfile(flox)),
+                    }
+                    .spin()?,
+                    ConcreteEnvironment::Remote(ref environment) => Dialog {
+                        message: "Fetching upstream environment...",
+                        help_message: None,
+                        typed: Spinner::new(|| environment.upstream_lockfile(flox)),
+                    }
+                    .spin()?,
+                    ConcreteEnvironment::Path(_) => {
+                        bail!("Environment {description} is not shared on FloxHub and has no upstream.")
+                    },
+                };
+                Ok((
+                    format!("Changes from {description} to its upstream:"),
+                    local,
+                    upstream,
+                ))
+            },
+            DiffSelect::WorkingCopy {} => {
+                let ConcreteEnvironment::Path(environment) = environment else {
+                    bail!(indoc! {"
+                        Environments shared on FloxHub have no local changes outside of generations.
+
+                        Use 'flox diff --upstream' to compare with the upstream environment
+                        or 'flox diff --from <GENERATION>' to compare generations.
+                    "});
+                };
+
+                let env_dir = environment.path.join(ENV_DIR_NAME);
+                // Without a history, compare with the lockfile of the last modification
+                let committed = if environment.has_history() {
+                    Self::current_lockfile(flox, &ConcreteEnvironment::Path(environment))?
+                } else {
+                    let lockfile_path = env_dir.join(LOCKFILE_FILENAME);
+                    match CanonicalPath::new(lockfile_path) {
+                        Ok(path) => Some(LockedManifest::read_from_file(&path)?),
+                        Err(_) => None,
+                    }
+                };
+                let working_copy = Dialog {
+                    message: "Locking environment...",
+                    help_message: None,
+                    typed: Spinner::new(|| lock_detached(flox, env_dir)),
+                }
+                .spin()?;
+
+                Ok((
+                    format!("Uncommitted changes of {description}:"),
+                    committed,
+                    Some(working_copy),
+                ))
+            },
+            DiffSelect::Directories { .. } => unreachable!("handled without an environment"),
+        }
+    }
+
+    /// The lockfile of the current generation of an environment, if any
+    fn current_lockfile(
+        flox: &Flox,
+        environment: &ConcreteEnvironment,
+    ) -> Result<Option<LockedManifest>> {
+        let Some(current) = generations_metadata(environment)?.current_gen else {
+            return Ok(None);
+        };
+        let mut lockfiles = match environment {
+            ConcreteEnvironment::Path(environment) => environment.generations_lockfiles()?,
+            ConcreteEnvironment::Managed(environment) => environment.generations_lockfiles(flox)?,
+            ConcreteEnvironment::Remote(environment) => environment.generations_lockfiles(flox)?,
+        };
+        Ok(lockfiles.remove(&current).flatten())
+    }
+
+    /// Find the environment definition in or below `dir`
+    ///
+    /// Accepts the environment directory itself,
+    /// a `.flox` directory or the directory containing it.
+    fn resolve_env_dir(dir: &Path) -> Result<PathBuf> {
+        [
+            dir.to_path_buf(),
+            dir.join(ENV_DIR_NAME),
+            dir.join(DOT_FLOX).join(ENV_DIR_NAME),
+        ]
+        .into_iter()
+        .find(|candidate| candidate.join(MANIFEST_FILENAME).exists())
+        .ok_or_else(|| anyhow!("No environment found in {}", dir.display()))
+    }
+}
+
+/// Check an environment for problems
+#[derive(Bpaf, Clone)]
+pub struct Check {
+    /// Print the results as JSON
+    #[bpaf(long)]
+    json: bool,
+
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CheckLevel {
+    Error,
+    Warning,
+}
+
+/// A problem found by `flox check`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct CheckDiagnostic {
+    level: CheckLevel,
+    /// The check that found the problem
+    check: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<usize>,
+    message: String,
+}
+
+impl CheckDiagnostic {
+    fn new(level: CheckLevel, check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            check,
+            package: None,
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    fn package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
+}
+
+impl Check {
+    pub async fn handle(self, flox: Flox) -> Result<()> {
+        subcommand_metric!("check");
+
+        let environment = self
             .environment
-            .detect_concrete_environment(&flox, "uninstall from")
-        {
-            Ok(concrete_environment) => concrete_environment,
-            Err(EnvironmentSelectError::Environment(
-                ref e @ EnvironmentError2::DotFloxNotFound(ref dir),
-            )) => {
-                bail!(formatdoc! {"
-                {e}
-
-                Create an environment with 'flox init --dir {}'", dir.to_string_lossy()
-                })
-            },
-            Err(e @ EnvironmentSelectError::EnvNotFoundInCurrentDirectory) => {
-                bail!(formatdoc! {"
-                {e}
+            .detect_concrete_environment(&flox, "check")?;
+        let description = environment_description(&environment)?;
+        let environment = environment.into_dyn_environment();
 
-                Create an environment with 'flox init' or uninstall packages from an environment found elsewhere with 'flox uninstall {} --dir <path>'",
-                self.packages.join(" ")})
-            },
-            Err(e) => Err(e)?,
+        let manifest_contents = environment.manifest_content(&flox)?;
+        let lockfile_path = environment.lockfile_path(&flox)?;
+        let lockfile = match CanonicalPath::new(&lockfile_path) {
+            Ok(path) => Some((LockedManifest::read_from_file(&path)?, path)),
+            Err(_) => None,
         };
 
-        // Ensure the user is logged in for the following remote operations
-        if let ConcreteEnvironment::Remote(_) = concrete_environment {
-            ensure_floxhub_token(&mut flox).await?;
-        };
+        let mut diagnostics = Self::diagnostics(
+            &manifest_contents,
+            lockfile.as_ref().map(|(lockfile, _)| lockfile),
+            &flox.system,
+        );
 
-        let description = environment_description(&concrete_environment)?;
-        let mut environment = concrete_environment.into_dyn_environment();
+        if let Some((_, ref path)) = lockfile {
+            diagnostics.extend(
+                LockedManifest::check_lockfile(path)?
+                    .into_iter()
+                    .map(|warning| {
+                        CheckDiagnostic::new(CheckLevel::Warning, "packages", warning.message)
+                            .package(warning.package)
+                    }),
+            );
+        }
 
-        let _ = Dialog {
-            message: &format!("Uninstalling packages from environment {description}..."),
-            help_message: None,
-            typed: Spinner::new(|| environment.uninstall(self.packages.clone(), &flox)),
+        let errors = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.level == CheckLevel::Error)
+            .count();
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+        } else if diagnostics.is_empty() {
+            message::plain(format!("No problems found in environment {description}."));
+        } else {
+            for diagnostic in &diagnostics {
+                let location = match (diagnostic.line, diagnostic.column) {
+                    (Some(line), Some(column)) => format!("line {line}, column {column}: "),
+                    _ => String::new(),
+                };
+                let message = format!("{location}{}", diagnostic.message);
+                match diagnostic.level {
+                    CheckLevel::Error => message::error(message),
+                    CheckLevel::Warning => message::warning(message),
+                }
+            }
+        }
+
+        if errors > 0 {
+            bail!("Found {errors} error(s) in environment {description}.");
         }
-        .spin()?;
 
-        // Note, you need two spaces between this emoji and the package name
-        // otherwise they appear right next to each other.
-        self.packages.iter().for_each(|p| {
-            message::deleted(format!("'{p}' uninstalled from environment {description}"))
-        });
         Ok(())
     }
-}
 
-// Delete builds of non-current versions of an environment
-#[derive(Bpaf, Clone)]
-pub struct WipeHistory {
-    #[bpaf(external(environment_select), fallback(Default::default()))]
-    _environment: EnvironmentSelect,
-}
+    /// Run all checks that don't require pkgdb
+    ///
+    /// If the manifest can't be parsed, only that error is reported.
+    fn diagnostics(
+        manifest_contents: &str,
+        lockfile: Option<&LockedManifest>,
+        system: &System,
+    ) -> Vec<CheckDiagnostic> {
+        let manifest = match Manifest::from_str(manifest_contents) {
+            Ok(manifest) => manifest,
+            Err(ManifestError::Invalid { message, location }) => {
+                let mut diagnostic = CheckDiagnostic::new(CheckLevel::Error, "manifest", message);
+                diagnostic.line = location.map(|location| location.line);
+                diagnostic.column = location.map(|location| location.column);
+                return vec![diagnostic];
+            },
+            Err(err) => {
+                return vec![CheckDiagnostic::new(
+                    CheckLevel::Error,
+                    "manifest",
+                    err.to_string(),
+                )]
+            },
+        };
 
-impl WipeHistory {
-    pub async fn handle(self, _flox: Flox) -> Result<()> {
-        subcommand_metric!("wipe-history");
+        let mut diagnostics = Vec::new();
+        let install = manifest.install.clone().unwrap_or_default();
+
+        // The same package installed under several install IDs
+        let mut install_ids_by_path = BTreeMap::<_, Vec<&str>>::new();
+        for (install_id, descriptor) in &install {
+            if let Some(ref pkg_path) = descriptor.pkg_path {
+                let repository =
+                    descriptor
+                        .package_repository
+                        .as_ref()
+                        .map(|repository| match repository {
+                            ManifestPackageRepository::Name(name) => name.clone(),
+                            ManifestPackageRepository::Attrs(attrs) => attrs.to_string(),
+                        });
+                install_ids_by_path
+                    .entry((repository, pkg_path.attributes()))
+                    .or_default()
+                    .push(install_id);
+            }
+        }
+        for ((_, pkg_path), install_ids) in install_ids_by_path {
+            if let [first, rest @ ..] = install_ids.as_slice() {
+                for duplicate in rest {
+                    diagnostics.push(
+                        CheckDiagnostic::new(
+                            CheckLevel::Warning,
+                            "install-ids",
+                            format!(
+                                "'{duplicate}' installs the same package '{}' as '{first}'",
+                                pkg_path.join(".")
+                            ),
+                        )
+                        .package(*duplicate),
+                    );
+                }
+            }
+        }
 
-        todo!("this command is planned for a future release");
-    }
-}
+        let systems = manifest
+            .options
+            .as_ref()
+            .and_then(|options| options.systems.clone());
+        if let Some(ref systems) = systems {
+            if !systems.contains(system) {
+                diagnostics.push(CheckDiagnostic::new(
+                    CheckLevel::Error,
+                    "systems",
+                    format!(
+                        "The current system '{system}' is not listed in 'options.systems' ({})",
+                        systems.join(", ")
+                    ),
+                ));
+            }
+        }
 
-// List environment generations with contents
-#[derive(Bpaf, Clone)]
-pub struct Generations {
-    #[allow(dead_code)] // not yet handled in impl
-    #[bpaf(long)]
-    json: bool,
+        let Some(lockfile) = lockfile else {
+            diagnostics.push(CheckDiagnostic::new(
+                CheckLevel::Error,
+                "lockfile",
+                "Environment has not been locked yet",
+            ));
+            return diagnostics;
+        };
 
-    #[allow(unused)] // Command currently forwarded
-    #[bpaf(external(environment_select), fallback(Default::default()))]
-    environment: EnvironmentSelect,
-}
+        match lockfile.manifest() {
+            Ok(locked_manifest) if locked_manifest == manifest => {},
+            _ => diagnostics.push(CheckDiagnostic::new(
+                CheckLevel::Error,
+                "lockfile",
+                "Lockfile is out of date with the manifest",
+            )),
+        }
 
-impl Generations {
-    pub async fn handle(self, _flox: Flox) -> Result<()> {
-        subcommand_metric!("generations");
+        let Ok(lockfile) = TypedLockedManifest::try_from(lockfile.clone()) else {
+            diagnostics.push(CheckDiagnostic::new(
+                CheckLevel::Error,
+                "lockfile",
+                "Lockfile could not be parsed",
+            ));
+            return diagnostics;
+        };
+
+        for locked_system in lockfile.systems() {
+            for install_id in lockfile.unavailable_packages(locked_system) {
+                let Some(descriptor) = install.get(install_id) else {
+                    continue;
+                };
+                let optional = descriptor.optional.unwrap_or(false);
+                let excluded = match descriptor.systems {
+                    Some(ref systems) => !systems.contains(locked_system),
+                    None => false,
+                };
+                if optional || excluded {
+                    continue;
+                }
+                diagnostics.push(
+                    CheckDiagnostic::new(
+                        CheckLevel::Warning,
+                        "systems",
+                        format!("'{install_id}' is not available for system '{locked_system}'"),
+                    )
+                    .package(install_id),
+                );
+            }
+        }
 
-        todo!("this command is planned for a future release")
+        diagnostics
     }
 }
 
 // Show all versions of an environment
 #[derive(Bpaf, Clone)]
 pub struct History {
-    #[allow(dead_code)] // not yet handled in impl
+    /// Print one generation per line
     #[bpaf(long, short)]
     oneline: bool,
 
-    #[allow(unused)] // Command currently forwarded
+    /// Print the history as JSON
+    #[bpaf(long)]
+    json: bool,
+
+    /// Start keeping a history of a local environment
+    ///
+    /// Environments on FloxHub always keep a history.
+    #[bpaf(long)]
+    enable: bool,
+
     #[bpaf(external(environment_select), fallback(Default::default()))]
     environment: EnvironmentSelect,
 }
 
+/// A single generation of an environment as shown by `flox history`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryEntry {
+    generation: usize,
+    current: bool,
+    created: DateTime<Utc>,
+    last_active: Option<DateTime<Utc>>,
+    description: String,
+}
+
 impl History {
-    pub async fn handle(self, _flox: Flox) -> Result<()> {
+    pub async fn handle(self, flox: Flox) -> Result<()> {
         subcommand_metric!("history");
 
-        todo!("this command is planned for a future release")
+        let environment = self
+            .environment
+            .detect_concrete_environment(&flox, "show the history of")?;
+
+        if self.enable {
+            return Self::enable(environment);
+        }
+
+        let metadata = generations_metadata(&environment)?;
+        let entries = Self::entries(&metadata);
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(());
+        }
+
+        if entries.is_empty() {
+            if let ConcreteEnvironment::Path(_) = environment {
+                message::warning(indoc! {"
+                    Environment does not keep a history.
+                    Use 'flox history --enable' to start recording changes."});
+            } else {
+                message::warning("Environment has no generations yet.");
+            }
+            return Ok(());
+        }
+
+        let rendered = if self.oneline {
+            Self::render_oneline(&entries)
+        } else {
+            Self::render_extended(&entries)
+        };
+        println!("{rendered}");
+
+        Ok(())
+    }
+
+    /// Record the current state of a path environment as its first generation
+    fn enable(environment: ConcreteEnvironment) -> Result<()> {
+        let ConcreteEnvironment::Path(environment) = environment else {
+            message::plain("Environments on FloxHub always keep a history.");
+            return Ok(());
+        };
+
+        if environment.has_history() {
+            message::plain(format!(
+                "Environment {} already keeps a history.",
+                environment.name()
+            ));
+            return Ok(());
+        }
+
+        environment.enable_history("Import existing environment")?;
+        message::updated(format!(
+            "Enabled history for environment {}",
+            environment.name()
+        ));
+
+        Ok(())
+    }
+
+    /// Collect the generations of an environment, most recent generation first
+    fn entries(metadata: &AllGenerationsMetadata) -> Vec<HistoryEntry> {
+        metadata
+            .generations
+            .iter()
+            .rev()
+            .map(|(id, generation)| HistoryEntry {
+                generation: **id,
+                current: metadata.current_gen.as_ref() == Some(id),
+                created: generation.created,
+                last_active: generation.last_active,
+                description: generation.description.clone(),
+            })
+            .collect()
+    }
+
+    /// Format a timestamp as shown in the history
+    fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+        timestamp.format("%Y-%m-%d %H:%M:%S %Z").to_string()
+    }
+
+    /// Render one generation per line
+    ///
+    /// e.g. `* 3  2024-03-01 12:00:00 UTC  manually edited`
+    fn render_oneline(entries: &[HistoryEntry]) -> String {
+        let width = entries
+            .iter()
+            .map(|entry| entry.generation.to_string().len())
+            .max()
+            .unwrap_or_default();
+
+        entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{marker} {generation:>width$}  {created}  {description}",
+                    marker = if entry.current { "*" } else { " " },
+                    generation = entry.generation,
+                    created = Self::format_timestamp(&entry.created),
+                    description = entry.description.lines().next().unwrap_or_default(),
+                )
+            })
+            .join("\n")
+    }
+
+    /// Render all metadata of each generation
+    ///
+    /// This is the default mode
+    fn render_extended(entries: &[HistoryEntry]) -> String {
+        entries
+            .iter()
+            .map(|entry| {
+                formatdoc! {"
+                    Generation {generation}{current}
+                      Created:     {created}
+                      Last active: {last_active}
+                      Description: {description}",
+                    generation = entry.generation,
+                    current = if entry.current { " (current)" } else { "" },
+                    created = Self::format_timestamp(&entry.created),
+                    last_active = entry
+                        .last_active
+                        .as_ref()
+                        .map(Self::format_timestamp)
+                        .unwrap_or_else(|| "never".to_string()),
+                    description = entry.description,
+                }
+            })
+            .join("\n\n")
     }
 }
 
@@ -1965,45 +3787,128 @@ impl Pull {
     }
 }
 
+/// Read the generations metadata of an environment
+///
+/// Path environments keep their generations locally,
+/// managed and remote environments in their floxmeta repository.
+fn generations_metadata(environment: &ConcreteEnvironment) -> Result<AllGenerationsMetadata> {
+    let metadata = match environment {
+        ConcreteEnvironment::Path(environment) => environment.generations_metadata()?,
+        ConcreteEnvironment::Managed(environment) => environment.generations_metadata()?,
+        ConcreteEnvironment::Remote(environment) => environment.generations_metadata()?,
+    };
+    Ok(metadata)
+}
+
+/// Switch an environment to an existing generation
+///
+/// The target generation is built before it becomes the current generation.
+fn switch_to_generation(
+    flox: &Flox,
+    mut environment: ConcreteEnvironment,
+    generation: usize,
+) -> Result<()> {
+    let description = environment_description(&environment)?;
+    let metadata = generations_metadata(&environment)?;
+
+    if metadata.current_gen.as_deref() == Some(&generation) {
+        message::warning(format!(
+            "Environment {description} is already at generation {generation}."
+        ));
+        return Ok(());
+    }
+
+    Dialog {
+        message: &format!("Switching environment {description} to generation {generation}..."),
+        help_message: None,
+        typed: Spinner::new(|| match &mut environment {
+            ConcreteEnvironment::Path(environment) => {
+                environment.switch_generation(flox, generation)
+            },
+            ConcreteEnvironment::Managed(environment) => {
+                environment.switch_generation(flox, generation)
+            },
+            ConcreteEnvironment::Remote(environment) => {
+                environment.switch_generation(flox, generation)
+            },
+        }),
+    }
+    .spin()?;
+
+    message::updated(format!(
+        "Switched environment {description} to generation {generation}."
+    ));
+    Ok(())
+}
+
 // Rollback to the previous generation of an environment
 #[derive(Bpaf, Clone)]
 pub struct Rollback {
-    #[bpaf(long, short, argument("ENV"))]
-    #[allow(dead_code)] // not yet handled in impl
-    environment: Option<EnvironmentRef>,
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
 
     /// Generation to roll back to.
     ///
     /// If omitted, defaults to the previous generation.
-    #[allow(dead_code)] // not yet handled in impl
     #[bpaf(argument("GENERATION"))]
     to: Option<u32>,
 }
 impl Rollback {
-    pub async fn handle(self, _flox: Flox) -> Result<()> {
+    pub async fn handle(self, mut flox: Flox) -> Result<()> {
         subcommand_metric!("rollback");
 
-        todo!("this command is planned for a future release")
+        let environment = self
+            .environment
+            .detect_concrete_environment(&flox, "roll back")?;
+
+        let generation = match self.to {
+            Some(generation) => generation as usize,
+            None => {
+                let metadata = generations_metadata(&environment)?;
+                let Some(previous) = metadata.previous_generation() else {
+                    bail!(formatdoc! {"
+                        Environment {description} has no generation before the current one.
+
+                        Use 'flox history' to list the generations of this environment.
+                    ", description = environment_description(&environment)?});
+                };
+                **previous
+            },
+        };
+
+        // Ensure the user is logged in for the following remote operations
+        if let ConcreteEnvironment::Remote(_) = environment {
+            ensure_floxhub_token(&mut flox).await?;
+        };
+
+        switch_to_generation(&flox, environment, generation)
     }
 }
 
 // Switch to a specific generation of an environment
 #[derive(Bpaf, Clone)]
 pub struct SwitchGeneration {
-    #[allow(unused)] // Command currently forwarded
     #[bpaf(external(environment_select), fallback(Default::default()))]
     environment: EnvironmentSelect,
 
-    #[allow(dead_code)] // not yet handled in impl
     #[bpaf(positional("GENERATION"))]
     generation: u32,
 }
 
 impl SwitchGeneration {
-    pub async fn handle(self, _flox: Flox) -> Result<()> {
+    pub async fn handle(self, mut flox: Flox) -> Result<()> {
         subcommand_metric!("switch-generation");
 
-        todo!("this command is planned for a future release")
+        let environment = self
+            .environment
+            .detect_concrete_environment(&flox, "switch the generation of")?;
+
+        // Ensure the user is logged in for the following remote operations
+        if let ConcreteEnvironment::Remote(_) = environment {
+            ensure_floxhub_token(&mut flox).await?;
+        };
+
+        switch_to_generation(&flox, environment, self.generation as usize)
     }
 }
 
@@ -2021,6 +3926,12 @@ impl Default for EnvironmentOrGlobalSelect {
     }
 }
 
+/// Upper bound for the number of inputs scraped at the same time by `flox update`
+const MAX_CONCURRENT_SCRAPES: NonZeroUsize = match NonZeroUsize::new(4) {
+    Some(n) => n,
+    None => unreachable!(),
+};
+
 // Update the global base catalog or an environment's base catalog
 #[derive(Bpaf, Clone)]
 pub struct Update {
@@ -2100,7 +4011,7 @@ impl Update {
             }
         }
 
-        let mut inputs_to_scrape: Vec<&Input> = vec![];
+        let mut inputs_to_scrape: Vec<(&String, &Input)> = vec![];
 
         for (input_name, new_input) in &new_lockfile.registry().inputs {
             let old_input = old_lockfile
@@ -2128,7 +4039,7 @@ impl Update {
                     description.as_ref().unwrap(),
                 )),
             }
-            inputs_to_scrape.push(new_input);
+            inputs_to_scrape.push((input_name, new_input));
         }
 
         if let Some(old_lockfile) = old_lockfile {
@@ -2154,22 +4065,40 @@ impl Update {
             return Ok(());
         }
 
-        // TODO: make this async when scraping multiple inputs
-        let results: Vec<Result<(), ScrapeError>> = Dialog {
+        let parallelism = std::thread::available_parallelism()
+            .map_or(NonZeroUsize::MIN, |n| n.min(MAX_CONCURRENT_SCRAPES));
+        let result = Dialog {
             message: "Generating databases for updated inputs...",
             help_message: (inputs_to_scrape.len() > 1).then_some("This may take a while."),
-            typed: Spinner::new(|| {
-                // TODO: rayon::par_iter
-                inputs_to_scrape
-                    .iter()
-                    .map(|input| pkgdb::scrape_input(&input.from))
-                    .collect()
+            typed: Progress::new(inputs_to_scrape.len(), |progress| {
+                pkgdb::scrape_inputs(
+                    inputs_to_scrape
+                        .iter()
+                        .map(|(name, input)| (name.as_str(), *input)),
+                    parallelism,
+                    |name, result| match result {
+                        Ok(()) => {
+                            progress.finished(format!("✅ Generated database for input '{name}'."))
+                        },
+                        Err(_) => progress.finished(format!(
+                            "❌ Failed to generate database for input '{name}'."
+                        )),
+                    },
+                )
             }),
         }
         .spin();
 
-        for result in results {
-            result?;
+        if let Err(ScrapeInputsError(errors)) = result {
+            let details = errors
+                .iter()
+                .map(|(name, err)| format!("{name}: {}", display_chain(err)))
+                .join("\n");
+            bail!(formatdoc! {"
+                Failed to generate databases for {} of {} updated inputs:
+
+                {details}
+            ", errors.len(), inputs_to_scrape.len()});
         }
 
         Ok(())
@@ -2308,11 +4237,198 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_absolute_flake_ref() {
+        let cwd = std::env::current_dir().unwrap().canonicalize().unwrap();
+        let cwd = cwd.display();
+        assert_eq!(
+            Install::absolute_flake_ref(".").unwrap(),
+            Some(cwd.to_string())
+        );
+        assert_eq!(
+            Install::absolute_flake_ref("path:.?dir=sub").unwrap(),
+            Some(format!("path:{cwd}?dir=sub"))
+        );
+        assert_eq!(
+            Install::absolute_flake_ref("git+file:.").unwrap(),
+            Some(format!("git+file://{cwd}"))
+        );
+        assert_eq!(
+            Install::absolute_flake_ref(&format!("git+file://{cwd}")).unwrap(),
+            Some(format!("git+file://{cwd}"))
+        );
+        assert_eq!(
+            Install::absolute_flake_ref("github:our-org/tools").unwrap(),
+            None
+        );
+        assert_eq!(Install::absolute_flake_ref("nixpkgs").unwrap(), None);
+        assert!(Install::absolute_flake_ref("path:./does-not-exist").is_err());
+    }
+
     #[test]
     fn test_quote_run_args() {
         assert_eq!(
-            Activate::quote_run_args(&["a b".to_string(), '"'.to_string()]),
+            shell_backend::Bash.run(&["a b".to_string(), '"'.to_string()]),
             r#""a b" "\"""#
         )
     }
+
+    #[test]
+    fn test_history_entries_newest_first() {
+        use chrono::TimeZone;
+        use flox_rust_sdk::models::environment::generations::SingleGenerationMetadata;
+
+        let mut metadata = AllGenerationsMetadata::default();
+        for (generation, description) in [(1, "first"), (2, "second")] {
+            metadata
+                .generations
+                .insert(generation.into(), SingleGenerationMetadata {
+                    created: Utc.timestamp_opt(generation as i64 * 60, 0).unwrap(),
+                    last_active: None,
+                    description: description.to_string(),
+                });
+        }
+        metadata.current_gen = Some(1.into());
+
+        let entries = History::entries(&metadata);
+        assert_eq!(
+            entries.iter().map(|e| e.generation).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert!(!entries[0].current);
+        assert!(entries[1].current);
+
+        assert_eq!(History::render_oneline(&entries), indoc! {"
+                  2  1970-01-01 00:02:00 UTC  second
+                * 1  1970-01-01 00:01:00 UTC  first"});
+    }
+
+    /// A lockfile locking `manifest` with `packages` per system,
+    /// `None` marks a package that could not be locked
+    fn check_lockfile(
+        manifest: serde_json::Value,
+        packages: &[(&str, &str, Option<&str>)],
+    ) -> LockedManifest {
+        let mut locked = serde_json::Map::new();
+        for (system, install_id, version) in packages {
+            let package = version.map(|version| {
+                serde_json::json!({
+                    "info": {
+                        "description": null,
+                        "broken": false,
+                        "license": null,
+                        "pname": install_id,
+                        "unfree": false,
+                        "version": version
+                    },
+                    "attr-path": ["legacyPackages", system, install_id],
+                    "priority": 5
+                })
+            });
+            locked
+                .entry(system.to_string())
+                .or_insert_with(|| serde_json::json!({}))
+                .as_object_mut()
+                .unwrap()
+                .insert(install_id.to_string(), package.into());
+        }
+        serde_json::from_value(serde_json::json!({
+            "lockfile-version": 0,
+            "manifest": manifest,
+            "packages": locked,
+            "registry": { "inputs": {} }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_reports_invalid_manifest_location() {
+        let diagnostics = Check::diagnostics(
+            "[install]\nhello.pkg-pth = \"hello\"\n",
+            None,
+            &"x86_64-linux".to_string(),
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].level, CheckLevel::Error);
+        assert_eq!(diagnostics[0].check, "manifest");
+        assert_eq!(
+            (diagnostics[0].line, diagnostics[0].column),
+            (Some(2), Some(7))
+        );
+    }
+
+    #[test]
+    fn test_check_reports_manifest_problems() {
+        let manifest = indoc! {r#"
+            [install]
+            hello.pkg-path = "hello"
+            greeting.pkg-path = "hello"
+
+            [options]
+            systems = ["aarch64-darwin"]
+        "#};
+        let diagnostics = Check::diagnostics(manifest, None, &"x86_64-linux".to_string());
+        let summary = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.level,
+                    diagnostic.check,
+                    diagnostic.package.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(summary, vec![
+            (CheckLevel::Warning, "install-ids", Some("hello")),
+            (CheckLevel::Error, "systems", None),
+            (CheckLevel::Error, "lockfile", None),
+        ]);
+    }
+
+    #[test]
+    fn test_check_reports_stale_lockfile() {
+        let system = "x86_64-linux".to_string();
+        let manifest = "[install]\nhello.pkg-path = \"hello\"\n";
+        let locked_manifest =
+            serde_json::json!({ "install": { "hello": { "pkg-path": "hello" } } });
+
+        let fresh = check_lockfile(locked_manifest, &[("x86_64-linux", "hello", Some("2.12"))]);
+        assert_eq!(Check::diagnostics(manifest, Some(&fresh), &system), vec![]);
+
+        let stale = check_lockfile(serde_json::json!({}), &[]);
+        let diagnostics = Check::diagnostics(manifest, Some(&stale), &system);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].check, "lockfile");
+        assert_eq!(diagnostics[0].level, CheckLevel::Error);
+    }
+
+    #[test]
+    fn test_check_reports_unavailable_packages() {
+        let system = "x86_64-linux".to_string();
+        let manifest = indoc! {r#"
+            [install]
+            hello.pkg-path = "hello"
+            gdb = { pkg-path = "gdb", optional = true }
+        "#};
+        let locked_manifest = serde_json::json!({
+            "install": {
+                "hello": { "pkg-path": "hello" },
+                "gdb": { "pkg-path": "gdb", "optional": true }
+            }
+        });
+        let lockfile = check_lockfile(locked_manifest, &[
+            ("x86_64-linux", "hello", Some("2.12")),
+            ("x86_64-linux", "gdb", Some("13.2")),
+            ("aarch64-darwin", "hello", None),
+            ("aarch64-darwin", "gdb", None),
+        ]);
+
+        let diagnostics = Check::diagnostics(manifest, Some(&lockfile), &system);
+        assert_eq!(diagnostics, vec![CheckDiagnostic::new(
+            CheckLevel::Warning,
+            "systems",
+            "'hello' is not available for system 'aarch64-darwin'"
+        )
+        .package("hello")]);
+    }
 }
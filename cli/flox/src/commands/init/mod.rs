@@ -52,6 +52,11 @@ pub struct Init {
     /// are being used in the containing directory
     #[bpaf(long)]
     auto_setup: bool,
+
+    /// Keep a history of changes to the environment
+    /// (see 'flox history')
+    #[bpaf(long)]
+    history: bool,
 }
 
 impl Init {
@@ -89,10 +94,9 @@ impl Init {
                     let lockfile: TypedLockedManifest =
                         LockedManifest::read_from_file(&CanonicalPath::new(global_lockfile)?)?
                             .try_into()?;
-                    // --ga-registry forces a single input
-                    if let Some((_, input)) = lockfile.registry().inputs.iter().next() {
-                        scrape_input(&input.from)?;
-                    };
+                    for input in lockfile.registry().inputs.values() {
+                        scrape_input(input)?;
+                    }
                     Ok::<(), Error>(())
                 }),
             }
@@ -134,6 +138,10 @@ impl Init {
             )?
         };
 
+        if self.history {
+            env.enable_history("Initialize environment")?;
+        }
+
         message::created(format!(
             "Created environment {name} ({system})",
             name = env.name(),
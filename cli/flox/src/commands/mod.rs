@@ -3,8 +3,9 @@ mod environment;
 mod general;
 mod init;
 mod search;
+mod services;
 
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Display;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -372,6 +373,15 @@ enum LocalDevelopmentCommands {
         footer("Run 'man flox-activate' for more details.")
     )]
     Activate(#[bpaf(external(environment::activate))] environment::Activate),
+    /// Leave the environment activated last by 'eval "$(flox activate)"'
+    #[bpaf(command, footer("Run 'man flox-deactivate' for more details."))]
+    Deactivate(#[bpaf(external(environment::deactivate))] environment::Deactivate),
+    /// Run a command in an environment without starting a shell
+    #[bpaf(command, footer("Run 'man flox-run' for more details."))]
+    Run(#[bpaf(external(environment::run))] environment::Run),
+    /// Start, stop and inspect the services of an environment
+    #[bpaf(command, footer("Run 'man flox-services' for more details."))]
+    Services(#[bpaf(external(services::services))] services::Services),
     /// Search for system or library packages to install
     #[bpaf(command, footer("Run 'man flox-search' for more details."))]
     Search(#[bpaf(external(search::search))] search::Search),
@@ -413,6 +423,9 @@ impl LocalDevelopmentCommands {
         match self {
             LocalDevelopmentCommands::Init(args) => args.handle(flox).await?,
             LocalDevelopmentCommands::Activate(args) => args.handle(config, flox).await?,
+            LocalDevelopmentCommands::Deactivate(args) => args.handle()?,
+            LocalDevelopmentCommands::Run(args) => args.handle(config, flox).await?,
+            LocalDevelopmentCommands::Services(args) => args.handle(flox).await?,
             LocalDevelopmentCommands::Edit(args) => args.handle(flox).await?,
             LocalDevelopmentCommands::Install(args) => args.handle(flox).await?,
             LocalDevelopmentCommands::Uninstall(args) => args.handle(flox).await?,
@@ -487,6 +500,15 @@ enum AdditionalCommands {
     /// Show all versions of an environment
     #[bpaf(command, hide)]
     History(#[bpaf(external(environment::history))] environment::History),
+    /// Compare the packages of two versions of an environment
+    #[bpaf(command, hide)]
+    Diff(#[bpaf(external(environment::diff))] environment::Diff),
+    /// Check an environment for problems
+    #[bpaf(command, hide)]
+    Check(#[bpaf(external(environment::check))] environment::Check),
+    /// Load an environment with direnv
+    #[bpaf(command, hide)]
+    Direnv(#[bpaf(external(environment::direnv))] environment::Direnv),
 }
 
 impl AdditionalCommands {
@@ -504,6 +526,9 @@ impl AdditionalCommands {
             AdditionalCommands::Config(args) => args.handle(config, flox).await?,
             AdditionalCommands::WipeHistory(args) => args.handle(flox).await?,
             AdditionalCommands::History(args) => args.handle(flox).await?,
+            AdditionalCommands::Diff(args) => args.handle(flox).await?,
+            AdditionalCommands::Check(args) => args.handle(flox).await?,
+            AdditionalCommands::Direnv(args) => args.handle(config, flox).await?,
         }
         Ok(())
     }
@@ -538,6 +563,9 @@ enum InternalCommands {
     /// FloxHub authentication commands
     #[bpaf(command, footer("Run 'man flox-auth' for more details."))]
     Auth(#[bpaf(external(auth::auth))] auth::Auth),
+    /// Build an environment ahead of activation, used by floxd
+    #[bpaf(command)]
+    Prepare(#[bpaf(external(environment::prepare))] environment::Prepare),
 }
 
 impl InternalCommands {
@@ -548,6 +576,7 @@ impl InternalCommands {
             InternalCommands::SwitchGeneration(args) => args.handle(flox).await?,
             InternalCommands::Rollback(args) => args.handle(flox).await?,
             InternalCommands::Auth(args) => args.handle(config, flox).await?,
+            InternalCommands::Prepare(args) => args.handle(flox).await?,
         }
         Ok(())
     }
@@ -597,6 +626,38 @@ impl Version {
     }
 }
 
+/// Fake command used to print the environment of the process
+///
+/// Used to read the environment after sourcing activation scripts,
+/// since not every `env` supports printing it unambiguously (`env -0`).
+/// Handled before flox sets any variables itself.
+#[derive(Bpaf)]
+#[bpaf(command("print-env"), hide)]
+pub struct PrintEnv;
+
+impl PrintEnv {
+    /// Parses to [Self] and checks whether `print-env` is the command
+    pub fn check() -> bool {
+        print_env()
+            .to_options()
+            .run_inner(Args::current_args())
+            .is_ok()
+    }
+
+    /// Print the environment of the process as a JSON object
+    pub fn handle() {
+        let vars = env::vars_os()
+            .map(|(key, value)| {
+                (
+                    key.to_string_lossy().into_owned(),
+                    value.to_string_lossy().into_owned(),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        println!("{}", serde_json::Value::from_iter(vars));
+    }
+}
+
 #[derive(Debug, Default, Bpaf, Clone)]
 pub enum EnvironmentSelect {
     Dir(
@@ -955,6 +1016,15 @@ impl ActiveEnvironments {
     pub fn is_active(&self, env: &UninitializedEnvironment) -> bool {
         self.0.contains(env)
     }
+
+    /// Number of environments on the stack of active environments
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
 impl Display for ActiveEnvironments {
@@ -1,12 +1,14 @@
-use std::fmt::Write;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
 use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
 use bpaf::Bpaf;
+use flox_rust_sdk::data::System;
 use flox_rust_sdk::flox::Flox;
-use flox_rust_sdk::models::environment::global_manifest_path;
+use flox_rust_sdk::models::environment::{global_manifest_path, CanonicalPath};
+use flox_rust_sdk::models::lockfile::{LockedManifest, TypedLockedManifest};
 use flox_rust_sdk::models::search::{
-    do_search,
     PathOrJson,
     Query,
     SearchParams,
@@ -17,6 +19,8 @@ use flox_rust_sdk::models::search::{
 };
 use indoc::formatdoc;
 use log::debug;
+use regex::Regex;
+use serde::Serialize;
 
 use crate::config::features::Features;
 use crate::config::Config;
@@ -27,6 +31,7 @@ use crate::utils::message;
 use crate::utils::search::{
     construct_search_params,
     manifest_and_lockfile,
+    search_packages,
     DisplaySearchResults,
     DEFAULT_DESCRIPTION,
     SEARCH_INPUT_SEPARATOR,
@@ -49,6 +54,9 @@ pub struct Search {
     #[bpaf(short, long)]
     pub all: bool,
 
+    #[bpaf(external(search_filters))]
+    pub filters: SearchFilters,
+
     /// The package to search for in the format '<pkg-path>[@<semver-range>]' using 'node-semver' syntax.
     ///
     /// ex.) python310Packages.pip
@@ -57,6 +65,56 @@ pub struct Search {
     #[bpaf(positional("search-term"))]
     pub search_term: String,
 }
+/// Filters narrowing down search results
+///
+/// Filters for licenses, unfree and broken packages
+/// override the manifest's `options.allow`.
+#[derive(Bpaf, Clone, Debug, Default)]
+pub struct SearchFilters {
+    /// Only show packages with this license, may be repeated
+    #[bpaf(long("license"), argument("license"), many)]
+    pub licenses: Vec<String>,
+
+    /// Hide packages with an unfree license
+    #[bpaf(long("no-unfree"))]
+    pub no_unfree: bool,
+
+    /// Show packages marked as broken
+    #[bpaf(long)]
+    pub broken: bool,
+
+    /// Only show packages available for this system, may be repeated
+    #[bpaf(long("system"), argument("system"), many)]
+    pub systems: Vec<System>,
+
+    /// Only show packages with a description matching this regular expression
+    #[bpaf(long, argument("regex"))]
+    pub description: Option<String>,
+}
+
+impl SearchFilters {
+    /// Add the filters to a [Query]
+    pub fn apply(&self, query: &mut Query) -> Result<()> {
+        if !self.licenses.is_empty() {
+            query.licenses = Some(self.licenses.clone());
+        }
+        if self.no_unfree {
+            query.allow_unfree = Some(false);
+        }
+        if self.broken {
+            query.allow_broken = Some(true);
+        }
+        if !self.systems.is_empty() {
+            query.systems = Some(self.systems.clone());
+        }
+        if let Some(description) = &self.description {
+            let regex = Regex::new(description)
+                .with_context(|| format!("Invalid description pattern '{description}'"))?;
+            query.description = Some(regex);
+        }
+        Ok(())
+    }
+}
 
 // Your first run will be slow, it's creating databases, but after that -
 //   it's fast!
@@ -85,18 +143,19 @@ impl Search {
             config.flox.search_limit.or(DEFAULT_SEARCH_LIMIT)
         };
 
-        let search_params = construct_search_params(
+        let mut search_params = construct_search_params(
             &self.search_term,
             limit,
             manifest.clone(),
             global_manifest.clone(),
             lockfile.clone(),
         )?;
+        self.filters.apply(&mut search_params.query)?;
 
         let (results, exit_status) = Dialog {
             message: "Searching for packages...",
             help_message: Some("This may take a while the first time you run it."),
-            typed: Spinner::new(|| do_search(&search_params)),
+            typed: Spinner::new(|| search_packages(&flox, &search_params)),
         }
         .spin_with_delay(Duration::from_secs(1))?;
 
@@ -113,6 +172,7 @@ impl Search {
             debug!("printing search results as user facing");
 
             let suggestion = DidYouMean::<SearchSuggestion>::new(
+                &flox,
                 &self.search_term,
                 manifest,
                 global_manifest,
@@ -171,6 +231,15 @@ pub struct Show {
     #[bpaf(long)]
     pub all: bool,
 
+    /// Display package details as JSON
+    #[bpaf(long)]
+    pub json: bool,
+
+    /// Show availability on this system, may be repeated
+    /// [default: the systems of the manifest]
+    #[bpaf(long("system"), argument("system"), many)]
+    pub systems: Vec<System>,
+
     /// The package to show detailed information about. Must be an exact match
     /// for a pkg-path e.g. something copy-pasted from the output of `flox search`.
     #[bpaf(positional("search-term"))]
@@ -183,14 +252,38 @@ impl Show {
 
         let (manifest, lockfile) = manifest_and_lockfile(&flox, "show packages using")
             .context("failed while looking for manifest and lockfile")?;
-        let search_params = construct_show_params(
+
+        let locked = LockedManifest::read_from_file(&CanonicalPath::new(&lockfile)?)?;
+        // The global lockfile doesn't contain a manifest
+        let systems = if !self.systems.is_empty() {
+            self.systems.clone()
+        } else {
+            locked
+                .manifest()
+                .ok()
+                .and_then(|manifest| manifest.options)
+                .and_then(|options| options.systems)
+                .unwrap_or_else(|| vec![flox.system.clone()])
+        };
+        let revisions = TypedLockedManifest::try_from(locked)?
+            .registry()
+            .inputs
+            .iter()
+            .map(|(name, input)| {
+                let rev = input.from.get("rev").and_then(|rev| rev.as_str());
+                (name.clone(), rev.map(String::from))
+            })
+            .collect();
+
+        let mut search_params = construct_show_params(
             &self.search_term,
             manifest.map(|p| p.try_into()).transpose()?,
             global_manifest_path(&flox).try_into()?,
             PathOrJson::Path(lockfile),
         )?;
+        search_params.query.systems = Some(systems.clone());
 
-        let (search_results, exit_status) = do_search(&search_params)?;
+        let (search_results, exit_status) = search_packages(&flox, &search_params)?;
 
         if search_results.results.is_empty() {
             bail!("no packages matched this search term: {}", self.search_term);
@@ -199,7 +292,12 @@ impl Show {
         // FIXME: We may have warnings on `stderr` even with a successful call to `pkgdb`.
         //        We aren't checking that at all at the moment because better overall error handling
         //        is coming in a later PR.
-        render_show(search_results.results.as_slice(), self.all)?;
+        let package = ShowPackage::new(&search_results.results, systems, &revisions, self.all)?;
+        if self.json {
+            println!("{}", serde_json::to_string(&package)?);
+        } else {
+            println!("{package}");
+        }
         if exit_status.success() {
             Ok(())
         } else {
@@ -243,63 +341,231 @@ fn construct_show_params(
     Ok(search_params)
 }
 
-fn render_show(search_results: &[SearchResult], all: bool) -> Result<()> {
-    let mut pkg_name = None;
-    let mut results = Vec::new();
-    // Collect all versions of the top search result
-    for package in search_results.iter() {
-        let this_pkg_name = package.rel_path.join(".");
-        if pkg_name.is_none() {
-            pkg_name = Some(this_pkg_name.clone());
-        }
-        if pkg_name == Some(this_pkg_name) {
-            results.push(package);
-        }
-    }
-    if results.is_empty() {
-        // This should never happen since we've already checked that the
-        // set of results is non-empty.
-        bail!("no packages found");
-    }
-    let pkg_name = pkg_name.unwrap();
-    let description = results[0]
-        .description
-        .as_ref()
-        .map(|d| d.replace('\n', " "))
-        .unwrap_or(DEFAULT_DESCRIPTION.into());
-    let versions = if all {
-        let multiple_versions = results
+/// A package and the systems each of its versions is available on
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ShowPackage {
+    pkg_path: String,
+    description: Option<String>,
+    /// The systems availability was checked for
+    systems: Vec<System>,
+    versions: Vec<ShowVersion>,
+}
+
+/// A version of a package provided by an input
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ShowVersion {
+    version: Option<String>,
+    input: String,
+    /// The locked revision of the input, if it has one
+    rev: Option<String>,
+    /// The systems this version is available on
+    systems: Vec<System>,
+}
+
+impl ShowPackage {
+    /// Collect the versions of the top search result
+    /// and the systems they are available on.
+    ///
+    /// Unless `all` is set only the version of the top search result is collected.
+    fn new(
+        search_results: &[SearchResult],
+        systems: Vec<System>,
+        revisions: &BTreeMap<String, Option<String>>,
+        all: bool,
+    ) -> Result<Self> {
+        let Some(top) = search_results.first() else {
+            // This should never happen since we've already checked that the
+            // set of results is non-empty.
+            bail!("no packages found");
+        };
+        let pkg_path = top.rel_path.join(".");
+
+        let mut versions: Vec<ShowVersion> = Vec::new();
+        for package in search_results
             .iter()
-            .filter_map(|sr| {
+            .filter(|package| package.rel_path == top.rel_path)
+        {
+            if all {
                 // Don't show a "latest" search result, it's just
                 // a duplicate
-                if sr.subtree == Subtree::Catalog
-                    && sr
+                if package.subtree == Subtree::Catalog
+                    && package
                         .abs_path
                         .last()
                         .map(|version| version == "latest")
                         .unwrap_or(false)
                 {
-                    return None;
+                    continue;
                 }
-                let name = sr.rel_path.join(".");
-                // We don't print packages that don't have a version since
+                // We don't show packages that don't have a version since
                 // the resolver will always rank versioned packages higher.
-                sr.version.clone().map(|version| [name, version].join("@"))
+                if package.version.is_none() {
+                    continue;
+                }
+            } else if package.version != top.version || package.input != top.input {
+                continue;
+            }
+
+            let existing = versions
+                .iter_mut()
+                .find(|v| v.version == package.version && v.input == package.input);
+            let version = match existing {
+                Some(version) => version,
+                None => {
+                    versions.push(ShowVersion {
+                        version: package.version.clone(),
+                        input: package.input.clone(),
+                        rev: revisions.get(&package.input).cloned().flatten(),
+                        systems: Vec::new(),
+                    });
+                    versions.last_mut().unwrap()
+                },
+            };
+            if !version.systems.contains(&package.system) {
+                version.systems.push(package.system.clone());
+            }
+        }
+        // List systems in the order they were requested in
+        for version in versions.iter_mut() {
+            version
+                .systems
+                .sort_by_key(|system| systems.iter().position(|s| s == system));
+        }
+
+        Ok(ShowPackage {
+            pkg_path,
+            description: top.description.as_ref().map(|d| d.replace('\n', " ")),
+            systems,
+            versions,
+        })
+    }
+}
+
+impl Display for ShowPackage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pkg_path = &self.pkg_path;
+        let description = self.description.as_deref().unwrap_or(DEFAULT_DESCRIPTION);
+        let versions = self
+            .versions
+            .iter()
+            .map(|v| match &v.version {
+                Some(version) => format!("{pkg_path}@{version}"),
+                None => pkg_path.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(f, "{pkg_path} - {description}")?;
+        writeln!(f, "    {pkg_path} - {versions}")?;
+        writeln!(f)?;
+
+        let mut rows = vec![["Version", "Input"]
+            .into_iter()
+            .map(String::from)
+            .chain(self.systems.iter().cloned())
+            .collect::<Vec<_>>()];
+        for version in &self.versions {
+            let input = match &version.rev {
+                Some(rev) => format!("{} ({})", version.input, &rev[..rev.len().min(7)]),
+                None => version.input.clone(),
+            };
+            let mut row = vec![version.version.clone().unwrap_or("-".to_string()), input];
+            row.extend(self.systems.iter().map(|system| {
+                if version.systems.contains(system) {
+                    "✓".to_string()
+                } else {
+                    "-".to_string()
+                }
+            }));
+            rows.push(row);
+        }
+
+        let widths = (0..rows[0].len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or_default()
             })
             .collect::<Vec<_>>();
-        multiple_versions.join(", ")
-    } else {
-        let sr = results[0];
-        let name = sr.rel_path.join(".");
-        let version = sr.version.clone();
-        if let Some(version) = version {
-            [name, version].join("@")
-        } else {
-            name
+        let lines = rows.iter().map(|row| {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            format!("    {}", line.trim_end())
+        });
+        write!(f, "{}", lines.collect::<Vec<_>>().join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    fn result(version: &str, system: &str) -> SearchResult {
+        SearchResult {
+            input: "nixpkgs".to_string(),
+            abs_path: vec!["legacyPackages".to_string(), system.to_string()],
+            subtree: Subtree::LegacyPackages,
+            system: system.to_string(),
+            rel_path: vec!["hello".to_string()],
+            pname: Some("hello".to_string()),
+            version: Some(version.to_string()),
+            description: Some("A friendly greeting".to_string()),
+            ..Default::default()
         }
-    };
-    println!("{pkg_name} - {description}");
-    println!("    {pkg_name} - {versions}");
-    Ok(())
+    }
+
+    fn show_package(all: bool) -> ShowPackage {
+        let results = [
+            result("2.12.1", "x86_64-linux"),
+            result("2.12", "x86_64-linux"),
+            result("2.12.1", "aarch64-darwin"),
+        ];
+        let revisions = BTreeMap::from([("nixpkgs".to_string(), Some("abcdef123456".to_string()))]);
+        let systems = vec!["aarch64-darwin".to_string(), "x86_64-linux".to_string()];
+        ShowPackage::new(&results, systems, &revisions, all).unwrap()
+    }
+
+    #[test]
+    fn show_collects_systems_per_version() {
+        let package = show_package(true);
+        assert_eq!(package.versions, vec![
+            ShowVersion {
+                version: Some("2.12.1".to_string()),
+                input: "nixpkgs".to_string(),
+                rev: Some("abcdef123456".to_string()),
+                systems: vec!["aarch64-darwin".to_string(), "x86_64-linux".to_string()],
+            },
+            ShowVersion {
+                version: Some("2.12".to_string()),
+                input: "nixpkgs".to_string(),
+                rev: Some("abcdef123456".to_string()),
+                systems: vec!["x86_64-linux".to_string()],
+            },
+        ]);
+    }
+
+    #[test]
+    fn show_renders_availability_matrix() {
+        assert_eq!(show_package(false).to_string(), indoc! {"
+            hello - A friendly greeting
+                hello - hello@2.12.1
+
+                Version  Input              aarch64-darwin  x86_64-linux
+                2.12.1   nixpkgs (abcdef1)  ✓               ✓"});
+        assert_eq!(show_package(true).to_string(), indoc! {"
+            hello - A friendly greeting
+                hello - hello@2.12.1, hello@2.12
+
+                Version  Input              aarch64-darwin  x86_64-linux
+                2.12.1   nixpkgs (abcdef1)  ✓               ✓
+                2.12     nixpkgs (abcdef1)  -               ✓"});
+    }
 }
@@ -0,0 +1,795 @@
+//! Long running processes declared in the `[services]` table of the manifest
+//!
+//! Every service is run by its own supervisor,
+//! a detached `flox services supervise` process,
+//! that restarts the service according to its restart policy
+//! and records the state of the service.
+//!
+//! State and output of the services of an environment are kept in
+//! `$FLOX_ENV_CACHE/services/<name>/`.
+//! A supervisor locks the lock file in that directory while it runs,
+//! which is how other commands tell whether a service is supervised.
+//! Services run in `$FLOX_ENV_PROJECT`, activated with `flox run`.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitStatus, Stdio};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::{env, thread};
+
+use anyhow::{bail, Context, Result};
+use bpaf::Bpaf;
+use flox_rust_sdk::flox::Flox;
+use flox_rust_sdk::models::manifest::{Manifest, ManifestRestartPolicy, ManifestService};
+use fslock::LockFile;
+use indoc::formatdoc;
+use log::debug;
+use nix::sys::signal::{kill, killpg, Signal};
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+use tokio::signal::unix::{signal, SignalKind};
+
+use super::{environment_select, ConcreteEnvironment, EnvironmentSelect};
+use crate::subcommand_metric;
+use crate::utils::dialog::{Dialog, Spinner};
+use crate::utils::message;
+
+const SERVICES_DIR_NAME: &str = "services";
+const STATE_FILENAME: &str = "state.json";
+const LOG_FILENAME: &str = "output.log";
+/// Locked by the supervisor of a service for as long as it runs
+const SUPERVISOR_LOCK_FILENAME: &str = "supervisor.lock";
+
+/// How long `flox services start` waits for services to become ready
+const READY_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often the readiness check of a starting service is run
+const READY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long a service may take to exit after SIGTERM before it is killed
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Delay before the first restart of a service,
+/// doubled for every consecutive restart up to [MAX_RESTART_DELAY]
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+/// Services that ran at least this long are restarted without delay
+const RESTART_DELAY_RESET: Duration = Duration::from_secs(60);
+
+/// Manage the services of an environment
+#[derive(Bpaf, Clone)]
+pub enum Services {
+    /// Start services of an environment
+    #[bpaf(command)]
+    Start(#[bpaf(external(start))] Start),
+    /// Stop services of an environment
+    #[bpaf(command)]
+    Stop(#[bpaf(external(stop))] Stop),
+    /// Show the status of the services of an environment
+    #[bpaf(command)]
+    Status(#[bpaf(external(status))] Status),
+    /// Show the output of a service
+    #[bpaf(command)]
+    Logs(#[bpaf(external(logs))] Logs),
+    /// Run a single service, started by 'flox services start'
+    #[bpaf(command, hide)]
+    Supervise(#[bpaf(external(supervise))] Supervise),
+}
+
+impl Services {
+    pub async fn handle(self, flox: Flox) -> Result<()> {
+        match self {
+            Services::Start(args) => args.handle(flox),
+            Services::Stop(args) => args.handle(flox),
+            Services::Status(args) => args.handle(flox),
+            Services::Logs(args) => args.handle(flox),
+            Services::Supervise(args) => args.handle(flox).await,
+        }
+    }
+}
+
+#[derive(Bpaf, Clone)]
+pub struct Start {
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+
+    /// Services to start, all services of the environment if omitted
+    #[bpaf(positional("name"))]
+    names: Vec<String>,
+}
+
+impl Start {
+    fn handle(self, flox: Flox) -> Result<()> {
+        subcommand_metric!("services", action = "start");
+
+        let environment =
+            ServicesEnvironment::detect(&flox, &self.environment, "start services of")?;
+
+        let mut supervisors = Vec::new();
+        for name in environment.select(&self.names)? {
+            let service_dir = environment.service_dir(name);
+            if service_dir.is_supervised()? {
+                message::warning(format!("Service '{name}' is already running"));
+                continue;
+            }
+            let supervisor = service_dir.spawn_supervisor(&environment.parent_path, name)?;
+            supervisors.push((name, service_dir, supervisor));
+        }
+
+        if supervisors.is_empty() {
+            return Ok(());
+        }
+
+        let results = Dialog {
+            message: "Starting services...",
+            help_message: None,
+            typed: Spinner::new(|| {
+                supervisors
+                    .into_iter()
+                    .map(|(name, service_dir, mut supervisor)| {
+                        (name, service_dir.wait_ready(&mut supervisor))
+                    })
+                    .collect::<Vec<_>>()
+            }),
+        }
+        .spin();
+
+        let mut failures = Vec::new();
+        for (name, result) in results {
+            match result {
+                Ok(()) => message::updated(format!("Service '{name}' started")),
+                Err(err) => failures.push(format!("  {name}: {err}")),
+            }
+        }
+
+        if !failures.is_empty() {
+            bail!(formatdoc! {"
+                Failed to start services:
+                {failures}
+
+                Run 'flox services logs <name>' to see their output.",
+                failures = failures.join("\n")
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Bpaf, Clone)]
+pub struct Stop {
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+
+    /// Services to stop, all services of the environment if omitted
+    #[bpaf(positional("name"))]
+    names: Vec<String>,
+}
+
+impl Stop {
+    fn handle(self, flox: Flox) -> Result<()> {
+        subcommand_metric!("services", action = "stop");
+
+        let environment =
+            ServicesEnvironment::detect(&flox, &self.environment, "stop services of")?;
+
+        for name in environment.select(&self.names)? {
+            let service_dir = environment.service_dir(name);
+            match service_dir.read_state()? {
+                Some(state) if service_dir.is_supervised()? => {
+                    service_dir.stop(&state)?;
+                    message::updated(format!("Service '{name}' stopped"));
+                },
+                _ if self.names.is_empty() => {},
+                _ => message::warning(format!("Service '{name}' is not running")),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Bpaf, Clone)]
+pub struct Status {
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+}
+
+impl Status {
+    fn handle(self, flox: Flox) -> Result<()> {
+        subcommand_metric!("services", action = "status");
+
+        let environment =
+            ServicesEnvironment::detect(&flox, &self.environment, "show services of")?;
+
+        let mut rows = Vec::new();
+        for name in environment.select(&[])? {
+            let service_dir = environment.service_dir(name);
+            let state = service_dir.read_state()?;
+            let status = match state {
+                None => "not started".to_string(),
+                Some(ref state) if state.status.is_active() && !service_dir.is_supervised()? => {
+                    "stopped unexpectedly".to_string()
+                },
+                Some(ref state) => state.status.to_string(),
+            };
+            let pid = state
+                .and_then(|state| state.pid)
+                .map(|pid| pid.to_string())
+                .unwrap_or_default();
+            rows.push((name, status, pid));
+        }
+
+        let name_width = rows.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+        let status_width = rows
+            .iter()
+            .map(|(_, status, _)| status.len())
+            .max()
+            .unwrap_or(0);
+        println!("{:name_width$}  {:status_width$}  PID", "NAME", "STATUS");
+        for (name, status, pid) in rows {
+            println!("{name:name_width$}  {status:status_width$}  {pid}");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Bpaf, Clone)]
+pub struct Logs {
+    #[bpaf(external(environment_select), fallback(Default::default()))]
+    environment: EnvironmentSelect,
+
+    /// Keep printing output as it is written
+    #[bpaf(long, short)]
+    follow: bool,
+
+    /// Service to show the output of
+    #[bpaf(positional("name"))]
+    name: String,
+}
+
+impl Logs {
+    fn handle(self, flox: Flox) -> Result<()> {
+        subcommand_metric!("services", action = "logs");
+
+        let environment = ServicesEnvironment::detect(&flox, &self.environment, "show logs of")?;
+        let name = self.name;
+        environment.select(std::slice::from_ref(&name))?;
+
+        let log_path = environment.service_dir(&name).log_path();
+        let mut log = match fs::File::open(&log_path) {
+            Ok(log) => log,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                bail!("Service '{name}' has not been started yet")
+            },
+            Err(err) => Err(err).context("Could not read service output")?,
+        };
+
+        let mut stdout = io::stdout().lock();
+        io::copy(&mut log, &mut stdout)?;
+        stdout.flush()?;
+
+        if !self.follow {
+            return Ok(());
+        }
+
+        // Like `tail -f`, poll for output until interrupted
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let position = log.stream_position()?;
+            if fs::metadata(&log_path)?.len() < position {
+                // the log was truncated, start over
+                log.seek(SeekFrom::Start(0))?;
+            }
+            let mut output = Vec::new();
+            log.read_to_end(&mut output)?;
+            stdout.write_all(&output)?;
+            stdout.flush()?;
+        }
+    }
+}
+
+#[derive(Bpaf, Clone)]
+pub struct Supervise {
+    /// Path containing a .flox/ directory
+    #[bpaf(long, short, argument("path"))]
+    dir: PathBuf,
+
+    /// Service to run
+    #[bpaf(positional("name"))]
+    name: String,
+}
+
+/// What ended waiting for a service
+enum SupervisorEvent {
+    Exited(ExitStatus),
+    Ready,
+    Stop,
+}
+
+impl Supervise {
+    /// Run the service until it exits without being restarted,
+    /// or until the supervisor is stopped by SIGTERM or SIGINT.
+    ///
+    /// Output of the supervisor and the service is written to the service log
+    /// by `flox services start`.
+    async fn handle(self, flox: Flox) -> Result<()> {
+        // Not counted as a subcommand, started by `flox services start`
+
+        let environment = ServicesEnvironment::open(
+            &flox,
+            EnvironmentSelect::Dir(self.dir).to_concrete_environment(&flox)?,
+        )?;
+        let name = self.name;
+        let Some(service) = environment.services.get(&name) else {
+            bail!("Service '{name}' is not defined in the manifest");
+        };
+        let service_dir = environment.service_dir(&name);
+        // Held until the supervisor exits
+        let _lock = service_dir.lock_supervisor()?;
+        let restart = service.restart.unwrap_or_default();
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+
+        let mut state = ServiceState {
+            supervisor: process::id() as i32,
+            pid: None,
+            status: ServiceStatus::Starting,
+            restarts: 0,
+        };
+        let mut restart_delay = MIN_RESTART_DELAY;
+
+        loop {
+            let started = Instant::now();
+            let mut child = tokio::process::Command::from(environment.command(
+                service,
+                &service.command,
+                false,
+            )?)
+            .spawn()
+            .with_context(|| format!("Could not start service '{name}'"))?;
+
+            state.pid = child.id().map(|pid| pid as i32);
+            state.status = if service.ready_check.is_some() {
+                ServiceStatus::Starting
+            } else {
+                ServiceStatus::Running
+            };
+            service_dir.write_state(&state)?;
+            message::updated(format!("Started service '{name}'"));
+
+            let exit_status = loop {
+                let starting = state.status == ServiceStatus::Starting;
+                let event = tokio::select! {
+                    status = child.wait() => SupervisorEvent::Exited(status?),
+                    () = environment.wait_ready(service), if starting => SupervisorEvent::Ready,
+                    _ = terminate.recv() => SupervisorEvent::Stop,
+                    _ = interrupt.recv() => SupervisorEvent::Stop,
+                };
+
+                match event {
+                    SupervisorEvent::Exited(status) => break status,
+                    SupervisorEvent::Ready => {
+                        message::updated(format!("Service '{name}' is ready"));
+                        state.status = ServiceStatus::Running;
+                        service_dir.write_state(&state)?;
+                    },
+                    SupervisorEvent::Stop => {
+                        message::plain(format!("Stopping service '{name}'"));
+                        Self::stop_child(&mut child).await?;
+                        state.pid = None;
+                        state.status = ServiceStatus::Stopped;
+                        return service_dir.write_state(&state);
+                    },
+                }
+            };
+
+            if exit_status.success() {
+                message::plain(format!("Service '{name}' exited: {exit_status}"));
+            } else {
+                message::warning(format!("Service '{name}' exited: {exit_status}"));
+            }
+            state.pid = None;
+
+            let should_restart = match restart {
+                ManifestRestartPolicy::Never => false,
+                ManifestRestartPolicy::OnFailure => !exit_status.success(),
+                ManifestRestartPolicy::Always => true,
+            };
+            if !should_restart {
+                state.status = ServiceStatus::Exited {
+                    code: exit_status.code(),
+                };
+                return service_dir.write_state(&state);
+            }
+
+            if started.elapsed() >= RESTART_DELAY_RESET {
+                restart_delay = MIN_RESTART_DELAY;
+            }
+            state.status = ServiceStatus::Restarting;
+            state.restarts += 1;
+            service_dir.write_state(&state)?;
+            message::plain(format!(
+                "Restarting service '{name}' in {}s",
+                restart_delay.as_secs()
+            ));
+
+            tokio::select! {
+                () = tokio::time::sleep(restart_delay) => {},
+                _ = terminate.recv() => {
+                    state.status = ServiceStatus::Stopped;
+                    return service_dir.write_state(&state);
+                },
+                _ = interrupt.recv() => {
+                    state.status = ServiceStatus::Stopped;
+                    return service_dir.write_state(&state);
+                },
+            }
+            restart_delay = (restart_delay * 2).min(MAX_RESTART_DELAY);
+        }
+    }
+
+    /// Stop the process group of a service,
+    /// killing it if it doesn't exit within [STOP_TIMEOUT]
+    async fn stop_child(child: &mut tokio::process::Child) -> Result<()> {
+        let Some(pid) = child.id() else {
+            // already exited
+            return Ok(());
+        };
+        let group = Pid::from_raw(pid as i32);
+
+        let _ = killpg(group, Signal::SIGTERM);
+        if tokio::time::timeout(STOP_TIMEOUT, child.wait())
+            .await
+            .is_err()
+        {
+            let _ = killpg(group, Signal::SIGKILL);
+            child.wait().await?;
+        }
+        Ok(())
+    }
+}
+
+/// An environment with its services
+struct ServicesEnvironment {
+    /// Directory containing .flox, the environment is run from
+    parent_path: PathBuf,
+    /// `$FLOX_ENV_PROJECT`, services are run in this directory
+    project_path: PathBuf,
+    /// `$FLOX_ENV_CACHE/services`
+    services_path: PathBuf,
+    services: BTreeMap<String, ManifestService>,
+}
+
+impl ServicesEnvironment {
+    /// Open the environment selected by the user,
+    /// see [EnvironmentSelect::detect_concrete_environment]
+    fn detect(flox: &Flox, environment: &EnvironmentSelect, message: &str) -> Result<Self> {
+        Self::open(
+            flox,
+            environment.detect_concrete_environment(flox, message)?,
+        )
+    }
+
+    fn open(flox: &Flox, environment: ConcreteEnvironment) -> Result<Self> {
+        if let ConcreteEnvironment::Remote(_) = environment {
+            bail!("Services are not supported for remote environments");
+        }
+        let environment = environment.into_dyn_environment();
+        let manifest = Manifest::from_str(&environment.manifest_content(flox)?)?;
+
+        Ok(Self {
+            parent_path: environment.parent_path()?,
+            project_path: environment.project_path()?,
+            services_path: environment.cache_path()?.join(SERVICES_DIR_NAME),
+            services: manifest.services.unwrap_or_default(),
+        })
+    }
+
+    /// The services named in `names`, or all services if `names` is empty
+    fn select<'a>(&'a self, names: &'a [String]) -> Result<Vec<&'a str>> {
+        if self.services.is_empty() {
+            bail!("The environment does not define any services");
+        }
+        if names.is_empty() {
+            return Ok(self.services.keys().map(String::as_str).collect());
+        }
+        names
+            .iter()
+            .map(|name| {
+                if !self.services.contains_key(name) {
+                    bail!("Service '{name}' is not defined in the manifest");
+                }
+                Ok(name.as_str())
+            })
+            .collect()
+    }
+
+    fn service_dir(&self, name: &str) -> ServiceDir {
+        ServiceDir(self.services_path.join(name))
+    }
+
+    /// Command running `script` of `service` in the activated environment
+    ///
+    /// The command is started in its own process group,
+    /// so that stopping it also stops processes started by `script`.
+    fn command(
+        &self,
+        service: &ManifestService,
+        script: &str,
+        skip_hooks: bool,
+    ) -> Result<process::Command> {
+        let mut command = process::Command::new(env::current_exe()?);
+        command.arg("run").arg("--dir").arg(&self.parent_path);
+        if skip_hooks {
+            command.arg("--no-hooks");
+        }
+        // `env` applies the service's variables after activation,
+        // so they take precedence over `[vars]`
+        command.args(["--", "env"]);
+        for (key, value) in service.vars.iter().flatten() {
+            command.arg(format!("{key}={value}"));
+        }
+        command
+            .args(["sh", "-c", script])
+            .current_dir(&self.project_path)
+            .stdin(Stdio::null())
+            .process_group(0);
+        Ok(command)
+    }
+
+    /// Run the readiness check of `service` until it succeeds
+    ///
+    /// Checks run without hooks, so hooks with side effects only run once,
+    /// when the service is started.
+    async fn wait_ready(&self, service: &ManifestService) {
+        let Some(ref ready_check) = service.ready_check else {
+            return;
+        };
+        loop {
+            match self.command(service, ready_check, true) {
+                Ok(mut command) => {
+                    command.stdout(Stdio::null()).stderr(Stdio::null());
+                    let status = tokio::process::Command::from(command).status().await;
+                    match status {
+                        Ok(status) if status.success() => return,
+                        Ok(_) => {},
+                        Err(err) => debug!("could not run readiness check: {err}"),
+                    }
+                },
+                Err(err) => debug!("could not run readiness check: {err}"),
+            }
+            tokio::time::sleep(READY_CHECK_INTERVAL).await;
+        }
+    }
+}
+
+/// State of a service as recorded by its supervisor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ServiceState {
+    /// Process id of the supervisor
+    supervisor: i32,
+    /// Process id of the service, if it is running
+    pid: Option<i32>,
+    status: ServiceStatus,
+    restarts: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+enum ServiceStatus {
+    /// The service was started and its readiness check did not succeed yet
+    Starting,
+    Running,
+    /// The service exited and will be restarted
+    Restarting,
+    /// The service exited and will not be restarted,
+    /// `code` is [None] if it was killed by a signal
+    Exited {
+        code: Option<i32>,
+    },
+    /// The service was stopped by `flox services stop`
+    Stopped,
+}
+
+impl ServiceStatus {
+    fn is_active(&self) -> bool {
+        matches!(
+            self,
+            ServiceStatus::Starting | ServiceStatus::Running | ServiceStatus::Restarting
+        )
+    }
+}
+
+impl Display for ServiceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceStatus::Starting => write!(f, "starting"),
+            ServiceStatus::Running => write!(f, "running"),
+            ServiceStatus::Restarting => write!(f, "restarting"),
+            ServiceStatus::Exited { code: Some(code) } => write!(f, "exited with status {code}"),
+            ServiceStatus::Exited { code: None } => write!(f, "killed"),
+            ServiceStatus::Stopped => write!(f, "stopped"),
+        }
+    }
+}
+
+/// `$FLOX_ENV_CACHE/services/<name>`
+struct ServiceDir(PathBuf);
+
+impl ServiceDir {
+    fn state_path(&self) -> PathBuf {
+        self.0.join(STATE_FILENAME)
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.0.join(LOG_FILENAME)
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        self.0.join(SUPERVISOR_LOCK_FILENAME)
+    }
+
+    /// Lock the service for its supervisor
+    ///
+    /// Fails if another supervisor of the service is running.
+    fn lock_supervisor(&self) -> Result<LockFile> {
+        let mut lock =
+            LockFile::open(&self.lock_path()).context("Could not open supervisor lock")?;
+        if !lock.try_lock().context("Could not lock supervisor lock")? {
+            bail!("Service is already supervised");
+        }
+        Ok(lock)
+    }
+
+    /// Whether the service is managed by a running supervisor
+    ///
+    /// Unlike the process id of the supervisor,
+    /// the lock can't be mistaken for an unrelated process
+    /// after a reboot or when process ids are reused.
+    fn is_supervised(&self) -> Result<bool> {
+        if !self.lock_path().exists() {
+            return Ok(false);
+        }
+        let mut lock =
+            LockFile::open(&self.lock_path()).context("Could not open supervisor lock")?;
+        let unlocked = lock.try_lock().context("Could not check supervisor lock")?;
+        Ok(!unlocked)
+    }
+
+    /// Stop the supervisor and wait for it to stop the service
+    ///
+    /// The supervisor recorded in `state` is only signalled while it holds the lock.
+    fn stop(&self, state: &ServiceState) -> Result<()> {
+        if !self.is_supervised()? {
+            return Ok(());
+        }
+        kill(Pid::from_raw(state.supervisor), Signal::SIGTERM).context("Could not stop service")?;
+
+        let started = Instant::now();
+        while self.is_supervised()? {
+            if started.elapsed() > STOP_TIMEOUT + Duration::from_secs(5) {
+                bail!("Service did not stop in time");
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    fn read_state(&self) -> Result<Option<ServiceState>> {
+        match fs::read(self.state_path()) {
+            Ok(contents) => Ok(Some(
+                serde_json::from_slice(&contents).context("Invalid service state")?,
+            )),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).context("Could not read service state"),
+        }
+    }
+
+    /// Replace the state atomically, so readers never see a partial write
+    fn write_state(&self, state: &ServiceState) -> Result<()> {
+        let mut file = tempfile::NamedTempFile::new_in(&self.0)?;
+        serde_json::to_writer(&mut file, state)?;
+        file.persist(self.state_path())
+            .context("Could not write service state")?;
+        Ok(())
+    }
+
+    /// Start a detached supervisor for the service `name`
+    ///
+    /// The supervisor writes the output of the service to the service log.
+    fn spawn_supervisor(&self, parent_path: &Path, name: &str) -> Result<process::Child> {
+        fs::create_dir_all(&self.0)?;
+        // Don't mistake the state of a previous run for the state of this one
+        match fs::remove_file(self.state_path()) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err)?,
+            _ => {},
+        }
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())
+            .context("Could not open service log")?;
+
+        let mut command = process::Command::new(env::current_exe()?);
+        command
+            .args(["services", "supervise", "--dir"])
+            .arg(parent_path)
+            .arg(name)
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            // Keep the supervisor running when the terminal is interrupted
+            .process_group(0);
+
+        debug!("starting supervisor: {command:?}");
+        command
+            .spawn()
+            .with_context(|| format!("Could not start service '{name}'"))
+    }
+
+    /// Wait until the service started by `supervisor` is ready
+    fn wait_ready(&self, supervisor: &mut process::Child) -> Result<()> {
+        let started = Instant::now();
+        loop {
+            if let Some(state) = self.read_state()? {
+                match state.status {
+                    ServiceStatus::Running => return Ok(()),
+                    ServiceStatus::Exited { .. } | ServiceStatus::Stopped => {
+                        bail!("{}", state.status)
+                    },
+                    ServiceStatus::Starting | ServiceStatus::Restarting => {},
+                }
+            }
+            if let Some(status) = supervisor.try_wait()? {
+                bail!("supervisor exited: {status}");
+            }
+            if started.elapsed() > READY_TIMEOUT {
+                bail!("not ready after {}s", READY_TIMEOUT.as_secs());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_state_roundtrip() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let service_dir = ServiceDir(tempdir.path().to_path_buf());
+        assert_eq!(service_dir.read_state().unwrap(), None);
+
+        let state = ServiceState {
+            supervisor: 1,
+            pid: Some(2),
+            status: ServiceStatus::Exited { code: Some(1) },
+            restarts: 3,
+        };
+        service_dir.write_state(&state).unwrap();
+        assert_eq!(service_dir.read_state().unwrap(), Some(state));
+    }
+
+    #[test]
+    fn test_is_supervised() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let service_dir = ServiceDir(tempdir.path().to_path_buf());
+        assert!(!service_dir.is_supervised().unwrap());
+
+        let lock = service_dir.lock_supervisor().unwrap();
+        assert!(service_dir.is_supervised().unwrap());
+        assert!(service_dir.lock_supervisor().is_err());
+
+        drop(lock);
+        assert!(!service_dir.is_supervised().unwrap());
+    }
+}
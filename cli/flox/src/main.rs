@@ -4,7 +4,7 @@ use std::process::ExitCode;
 
 use anyhow::{Context, Result};
 use bpaf::{Args, Parser};
-use commands::{FloxArgs, FloxCli, Prefix, Version};
+use commands::{FloxArgs, FloxCli, Prefix, PrintEnv, Version};
 use flox_rust_sdk::flox::FLOX_VERSION;
 use flox_rust_sdk::models::environment::managed_environment::ManagedEnvironmentError;
 use flox_rust_sdk::models::environment::remote_environment::RemoteEnvironmentError;
@@ -48,6 +48,13 @@ fn main() -> ExitCode {
         return ExitCode::from(0);
     }
 
+    // Quit early if the command is `print-env`,
+    // before any variables are set
+    if PrintEnv::check() {
+        PrintEnv::handle();
+        return ExitCode::from(0);
+    }
+
     // Parse verbosity flags to affect help message/parse errors
     let verbosity = {
         let verbosity_parser = commands::verbosity();
@@ -17,6 +17,17 @@ pub struct Select<T> {
     pub options: Vec<T>,
 }
 
+/// Select any number of options, filtered by fuzzy matching the typed input
+#[derive(Clone)]
+pub struct MultiSelect<T> {
+    pub options: Vec<T>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Text {
+    pub placeholder: Option<String>,
+}
+
 pub struct Spinner<F>(F);
 impl<F: FnOnce() -> T + Send, T: Send> Spinner<F> {
     pub fn new(f: F) -> Self {
@@ -24,6 +35,29 @@ impl<F: FnOnce() -> T + Send, T: Send> Spinner<F> {
     }
 }
 
+/// A spinner for a task made of `len` steps
+///
+/// The task is passed a [ProgressReporter] to report finished steps.
+pub struct Progress<F> {
+    len: u64,
+    f: F,
+}
+impl<F: FnOnce(&ProgressReporter) -> T + Send, T: Send> Progress<F> {
+    pub fn new(len: usize, f: F) -> Self {
+        Self { len: len as u64, f }
+    }
+}
+
+/// Handle to report finished steps of a [Progress] dialog
+pub struct ProgressReporter(ProgressBar);
+impl ProgressReporter {
+    /// Print `line` above the spinner and advance the progress by one step
+    pub fn finished(&self, line: impl Display) {
+        self.0.suspend(|| eprintln!("{line}"));
+        self.0.inc(1);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Checkpoint;
 
@@ -94,7 +128,6 @@ impl Display for Choice {
 }
 
 impl<'a, T: Display> Dialog<'a, Select<T>> {
-    #[allow(dead_code)]
     pub async fn prompt(self) -> inquire::error::InquireResult<T> {
         let message = self.message.to_owned();
         let help_message = self.help_message.map(ToOwned::to_owned);
@@ -157,6 +190,82 @@ impl<'a, T: Display> Dialog<'a, Select<T>> {
     }
 }
 
+impl<'a, T: Display> Dialog<'a, MultiSelect<T>> {
+    pub async fn prompt(self) -> inquire::error::InquireResult<Vec<T>> {
+        let message = self.message.to_owned();
+        let help_message = self.help_message.map(ToOwned::to_owned);
+        let mut options = self.typed.options.into_iter().map(Some).collect::<Vec<_>>();
+
+        let choices = options
+            .iter()
+            .flatten()
+            .map(ToString::to_string)
+            .enumerate()
+            .map(|(id, value)| Choice(id, value))
+            .collect();
+
+        let selected = tokio::task::spawn_blocking(move || {
+            let _stderr_lock = TERMINAL_STDERR.lock();
+
+            let filter =
+                |filter: &str, _: &Choice, value: &str, _: usize| fuzzy_match(filter, value);
+            let mut dialog = inquire::MultiSelect::new(&message, choices)
+                .with_filter(&filter)
+                .with_render_config(flox_theme());
+
+            if let Some(ref help_message) = help_message {
+                dialog = dialog.with_help_message(help_message);
+            }
+
+            dialog.prompt()
+        })
+        .await
+        .expect("Failed to join blocking dialog")?;
+
+        Ok(selected
+            .into_iter()
+            .filter_map(|Choice(id, _)| options[id].take())
+            .collect())
+    }
+}
+
+impl Dialog<'_, Text> {
+    pub async fn prompt(self) -> inquire::error::InquireResult<String> {
+        let message = self.message.to_owned();
+        let help_message = self.help_message.map(ToOwned::to_owned);
+        let placeholder = self.typed.placeholder;
+
+        tokio::task::spawn_blocking(move || {
+            let _stderr_lock = TERMINAL_STDERR.lock();
+
+            let mut dialog = inquire::Text::new(&message).with_render_config(flox_theme());
+
+            if let Some(ref placeholder) = placeholder {
+                dialog = dialog.with_placeholder(placeholder);
+            }
+
+            if let Some(ref help_message) = help_message {
+                dialog = dialog.with_help_message(help_message);
+            }
+
+            dialog.prompt()
+        })
+        .await
+        .expect("Failed to join blocking dialog")
+    }
+}
+
+/// Whether all characters of `filter` appear in `value` in order,
+/// ignoring case
+fn fuzzy_match(filter: &str, value: &str) -> bool {
+    let mut value = value.chars().flat_map(char::to_lowercase);
+    filter
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|c| value.any(|v| v == c))
+}
+
 impl<'a, F: FnOnce() -> T + Send, T: Send> Dialog<'a, Spinner<F>> {
     pub fn spin_with_delay(self, start_spinning_after: Duration) -> T {
         std::thread::scope(|s| {
@@ -202,6 +311,30 @@ impl<'a, F: FnOnce() -> T + Send, T: Send> Dialog<'a, Spinner<F>> {
     }
 }
 
+impl<'a, F: FnOnce(&ProgressReporter) -> T + Send, T: Send> Dialog<'a, Progress<F>> {
+    pub fn spin(self) -> T {
+        let bar = if std::io::stderr().is_tty() {
+            ProgressBar::new(self.typed.len)
+        } else {
+            ProgressBar::hidden()
+        };
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} {wide_msg} [{pos}/{len}] {prefix:>}").unwrap(),
+        );
+        bar.set_message(self.message.to_string());
+        if let Some(help_message) = self.help_message {
+            bar.set_prefix(help_message.to_string())
+        }
+        bar.enable_steady_tick(Duration::from_millis(100));
+
+        let reporter = ProgressReporter(bar);
+        let res = (self.typed.f)(&reporter);
+        reporter.0.finish_and_clear();
+
+        res
+    }
+}
+
 impl Dialog<'_, ()> {
     /// True if stderr and stdin are ttys
     pub fn can_prompt() -> bool {
@@ -233,3 +366,17 @@ pub fn flox_theme() -> RenderConfig {
 
     render_config
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_match_matches_subsequences() {
+        assert!(fuzzy_match("", "hello"));
+        assert!(fuzzy_match("hlo", "hello"));
+        assert!(fuzzy_match("P3 flask", "python310Packages.flask"));
+        assert!(!fuzzy_match("olleh", "hello"));
+        assert!(!fuzzy_match("helloo", "hello"));
+    }
+}
@@ -4,12 +4,12 @@ use anyhow::Result;
 use flox_rust_sdk::flox::Flox;
 use flox_rust_sdk::models::environment::{global_manifest_path, Environment};
 use flox_rust_sdk::models::lockfile::LockedManifest;
-use flox_rust_sdk::models::search::{do_search, PathOrJson, SearchResults};
+use flox_rust_sdk::models::search::{PathOrJson, SearchResults};
 use log::debug;
 
 use super::search::{DisplayItems, DisplaySearchResults};
 use crate::utils::dialog::{Dialog, Spinner};
-use crate::utils::search::construct_search_params;
+use crate::utils::search::{construct_search_params, search_packages};
 
 pub const SUGGESTION_SEARCH_LIMIT: u8 = 3;
 
@@ -78,7 +78,7 @@ impl<'a> DidYouMean<'a, InstallSuggestion> {
         let (results, _) = Dialog {
             message: &format!("Could not find package for {term}. Looking for suggestions..."),
             help_message: None,
-            typed: Spinner::new(|| do_search(&search_params)),
+            typed: Spinner::new(|| search_packages(flox, &search_params)),
         }
         .spin()?;
 
@@ -170,6 +170,7 @@ impl<'a> DidYouMean<'a, SearchSuggestion> {
     }
 
     fn suggest_searched_packages(
+        flox: &Flox,
         term: &str,
         manifest: Option<PathOrJson>,
         global_manifest: PathOrJson,
@@ -186,7 +187,7 @@ impl<'a> DidYouMean<'a, SearchSuggestion> {
         let (results, _) = Dialog {
             message: "Looking for alternative suggestions...",
             help_message: None,
-            typed: Spinner::new(|| do_search(&search_params)),
+            typed: Spinner::new(|| search_packages(flox, &search_params)),
         }
         .spin()?;
 
@@ -204,6 +205,7 @@ impl<'a> DidYouMean<'a, SearchSuggestion> {
     /// but still needs to be able to suggest search results
     /// based on an existing (global) manifest/lockfile.
     pub fn new(
+        flox: &Flox,
         term: &'a str,
         manifest: Option<PathOrJson>,
         global_manifest: PathOrJson,
@@ -217,7 +219,13 @@ impl<'a> DidYouMean<'a, SearchSuggestion> {
         };
 
         let search_results = if let Some(curated) = curated {
-            match Self::suggest_searched_packages(curated, manifest, global_manifest, lockfile) {
+            match Self::suggest_searched_packages(
+                flox,
+                curated,
+                manifest,
+                global_manifest,
+                lockfile,
+            ) {
                 Ok(results) => results,
                 Err(err) => {
                     debug!("failed to search for suggestions: {}", err);
@@ -1,3 +1,4 @@
+use flox_rust_sdk::models::environment::generations::GenerationsError;
 use flox_rust_sdk::models::environment::managed_environment::{
     ManagedEnvironmentError,
     GENERATION_LOCK_FILENAME,
@@ -214,6 +215,13 @@ pub fn format_error(err: &EnvironmentError2) -> String {
 
             Please make sure that you have write permissions to '.flox'.
         "},
+        EnvironmentError2::Generations(GenerationsError::GenerationNotFound(generation)) => {
+            formatdoc! {"
+                Generation {generation} does not exist.
+
+                Use 'flox history' to list the generations of this environment.
+            "}
+        },
         EnvironmentError2::Core(core_error) => format_core_error(core_error),
         EnvironmentError2::ManagedEnvironment(managed_error) => format_managed_error(managed_error),
         EnvironmentError2::RemoteEnvironment(remote_error) => format_remote_error(remote_error),
@@ -241,6 +249,15 @@ pub fn format_core_error(err: &CoreEnvironmentError) -> String {
         // witin transaction, user should not see this and likely can't do anything about it
         CoreEnvironmentError::WriteLockfile(_) => display_chain(err),
         CoreEnvironmentError::MakeTemporaryEnv(_) => display_chain(err),
+        CoreEnvironmentError::ReadLockfile(_) => display_chain(err),
+        CoreEnvironmentError::OpenTransactionLock(_) => display_chain(err),
+        CoreEnvironmentError::AcquireTransactionLock(_) => display_chain(err),
+        CoreEnvironmentError::ModifiedDuringTransaction => formatdoc! {"
+            The environment was modified while this change was being applied.
+
+            No changes were made.
+            Please retry the command to apply it to the latest version of the environment.
+        "},
         CoreEnvironmentError::PriorTransaction(backup) => {
             let mut env_path = backup.clone();
             env_path.set_file_name("env");
@@ -457,14 +474,48 @@ pub fn format_managed_error(err: &ManagedEnvironmentError) -> String {
 
             {err}
         ",err = display_chain(e) },
+        ManagedEnvironmentError::ReadGenerationsMetadata(e) => formatdoc! {"
+            Could not read the history of the managed environment.
+
+            {err}
+        ",err = display_chain(e) },
+        ManagedEnvironmentError::ReadGenerationLockfiles(e) => formatdoc! {"
+            Could not read the lockfiles of the generations of the managed environment.
+
+            {err}
+        ",err = display_chain(e) },
+        ManagedEnvironmentError::WipeHistory(e) => formatdoc! {"
+            Could not wipe the history of the managed environment.
+
+            {err}
+        ",err = display_chain(e) },
+        ManagedEnvironmentError::ReadLinksDir(path, e) => formatdoc! {"
+            Could not read links directory {path:?}: {e}
+        "},
+        ManagedEnvironmentError::SwitchGeneration(GenerationsError::GenerationNotFound(
+            generation,
+        )) => formatdoc! {"
+            Generation {generation} does not exist.
+
+            Use 'flox history' to list the generations of this environment.
+        "},
+        ManagedEnvironmentError::SwitchGeneration(e) => formatdoc! {"
+            Could not switch to the requested generation.
+
+            {err}
+        ",err = display_chain(e) },
         ManagedEnvironmentError::CanonicalizePath(canonicalize_err) => formatdoc! {"
             Invalid path to environment: {canonicalize_err}
 
             Please ensure that the path exists and that you have read permissions.
         "},
-        ManagedEnvironmentError::Build(core_environment_error) => {
+        ManagedEnvironmentError::Build(core_environment_error)
+        | ManagedEnvironmentError::Lock(core_environment_error) => {
             format_core_error(core_environment_error)
         },
+        ManagedEnvironmentError::OpenLocalGenerations(_)
+        | ManagedEnvironmentError::ImportLocalGenerations(_)
+        | ManagedEnvironmentError::DeleteLocalGenerations(_) => display_chain(err),
     }
 }
 
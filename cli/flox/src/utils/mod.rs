@@ -14,7 +14,9 @@ pub mod init;
 pub mod message;
 pub mod metrics;
 pub mod openers;
+pub mod picker;
 pub mod search;
+pub mod shell_backend;
 
 pub static TERMINAL_STDERR: Lazy<Mutex<Stderr>> = Lazy::new(|| Mutex::new(std::io::stderr()));
 
@@ -1,4 +1,5 @@
 use std::env;
+use std::ffi::OsStr;
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -8,6 +9,8 @@ use itertools::Itertools;
 use log::debug;
 use sysinfo::{Pid, System};
 
+use super::shell_backend::{self, ShellBackend};
+
 const OPENERS: &[&str] = &["xdg-open", "gnome-open", "kde-open"];
 
 const BROWSER_OPENERS: &[&str] = &["www-browser"];
@@ -75,6 +78,10 @@ impl Browser {
 pub enum Shell {
     Bash(PathBuf),
     Zsh(PathBuf),
+    Fish(PathBuf),
+    /// POSIX `sh`, e.g. `dash` or busybox `ash`
+    Posix(PathBuf),
+    Nu(PathBuf),
 }
 
 impl TryFrom<&Path> for Shell {
@@ -84,6 +91,17 @@ impl TryFrom<&Path> for Shell {
         match value.file_name() {
             Some(name) if name == "bash" => Ok(Shell::Bash(value.to_owned())),
             Some(name) if name == "zsh" => Ok(Shell::Zsh(value.to_owned())),
+            Some(name) if name == "fish" => Ok(Shell::Fish(value.to_owned())),
+            // In minimal images `sh` is often a symlink to busybox,
+            // which is what the parent process exe resolves to.
+            Some(name)
+                if ["sh", "dash", "ash", "busybox"]
+                    .map(OsStr::new)
+                    .contains(&name) =>
+            {
+                Ok(Shell::Posix(value.to_owned()))
+            },
+            Some(name) if name == "nu" => Ok(Shell::Nu(value.to_owned())),
             _ => Err(anyhow!("Unsupported shell {value:?}")),
         }
     }
@@ -91,10 +109,7 @@ impl TryFrom<&Path> for Shell {
 
 impl Display for Shell {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Shell::Bash(_) => write!(f, "bash"),
-            Shell::Zsh(_) => write!(f, "zsh"),
-        }
+        write!(f, "{}", self.backend().name())
     }
 }
 
@@ -126,6 +141,20 @@ impl Shell {
         match self {
             Shell::Bash(path) => path,
             Shell::Zsh(path) => path,
+            Shell::Fish(path) => path,
+            Shell::Posix(path) => path,
+            Shell::Nu(path) => path,
+        }
+    }
+
+    /// Get the backend rendering activation commands for this shell
+    pub fn backend(&self) -> &'static dyn ShellBackend {
+        match self {
+            Shell::Bash(_) => &shell_backend::Bash,
+            Shell::Zsh(_) => &shell_backend::Zsh,
+            Shell::Fish(_) => &shell_backend::Fish,
+            Shell::Posix(_) => &shell_backend::Posix,
+            Shell::Nu(_) => &shell_backend::Nu,
         }
     }
 }
@@ -145,8 +174,8 @@ fn get_parent_process_exe() -> Result<PathBuf> {
     // Using `argv[0]` may still be unreliable as a path to a shell executable,
     // if set manually by the calling process or the parent shell itself.
     //
-    // However, all this is only relevant once we want to detect more shells
-    // -- including `sh` -- and not just `bash` and `zsh`.
+    // As a result, `sh` symlinked to bash or zsh is activated as bash or zsh,
+    // which is compatible with, but not the same as, the POSIX activation.
     let parent_exe = parent_process
         .exe()
         .context("Failed to get parent process exe")?
@@ -0,0 +1,221 @@
+//! Interactively search for packages and pick the ones to install
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use flox_rust_sdk::flox::Flox;
+use flox_rust_sdk::models::environment::{global_manifest_path, Environment};
+use flox_rust_sdk::models::lockfile::LockedManifest;
+use flox_rust_sdk::models::manifest::PackageToInstall;
+use flox_rust_sdk::models::search::{PathOrJson, Query, SearchParams, SearchResult};
+
+use super::dialog::{Dialog, MultiSelect, Select, Spinner, Text};
+use super::search::{construct_search_params, search_packages, DEFAULT_DESCRIPTION};
+
+/// The number of search results offered by the picker
+const PICKER_SEARCH_LIMIT: u8 = 50;
+
+/// Prompt for a search term, then let the user select any of the matching
+/// packages and optionally a version for each of them
+pub async fn pick_packages(
+    flox: &Flox,
+    environment: &dyn Environment,
+) -> Result<Vec<PackageToInstall>> {
+    let term = Dialog {
+        message: "Search for packages to install:",
+        help_message: Some("Search by name or path, e.g. 'python3' or 'python310Packages.pip'"),
+        typed: Text { placeholder: None },
+    }
+    .prompt()
+    .await?;
+    let term = term.trim();
+    if term.is_empty() {
+        bail!("No search term given");
+    }
+
+    let lockfile_path = environment.lockfile_path(flox)?;
+    // Use the global lock if we don't have a lock yet
+    let lockfile = if lockfile_path.exists() {
+        PathOrJson::Path(lockfile_path)
+    } else {
+        PathOrJson::Path(LockedManifest::ensure_global_lockfile(flox)?)
+    };
+    let search_params = construct_search_params(
+        term,
+        Some(PICKER_SEARCH_LIMIT),
+        Some(environment.manifest_path(flox)?.try_into()?),
+        global_manifest_path(flox).try_into()?,
+        lockfile,
+    )?;
+
+    let (results, _) = Dialog {
+        message: &format!("Searching for '{term}'..."),
+        help_message: None,
+        typed: Spinner::new(|| search_packages(flox, &search_params)),
+    }
+    .spin()?;
+    if results.results.is_empty() {
+        bail!("No packages matched this search term: {term}");
+    }
+
+    let selected = Dialog {
+        message: "Select packages to install:",
+        help_message: Some("Type to filter, space to select, enter to install"),
+        typed: MultiSelect {
+            options: PickerOption::from_search_results(results.results),
+        },
+    }
+    .prompt()
+    .await?;
+    if selected.is_empty() {
+        bail!("No packages selected");
+    }
+
+    let mut packages = Vec::new();
+    for option in selected {
+        let mut package = PackageToInstall::from_str(&option.result.rel_path.join("."))?;
+        if option.with_input {
+            package.input = Some(option.result.input.clone());
+        }
+        package.version = pick_version(flox, &search_params, &option.result).await?;
+        packages.push(package);
+    }
+    Ok(packages)
+}
+
+/// Let the user pick a version of a package if there are multiple
+///
+/// Returns `None` for the latest version,
+/// which leaves the version unconstrained.
+async fn pick_version(
+    flox: &Flox,
+    search_params: &SearchParams,
+    package: &SearchResult,
+) -> Result<Option<String>> {
+    let pkg_path = package.rel_path.join(".");
+    let version_params = SearchParams {
+        query: Query {
+            rel_path: Some(package.rel_path.clone()),
+            ..Default::default()
+        },
+        ..search_params.clone()
+    };
+    let (results, _) = Dialog {
+        message: &format!("Looking up versions of {pkg_path}..."),
+        help_message: None,
+        typed: Spinner::new(|| search_packages(flox, &version_params)),
+    }
+    .spin()?;
+
+    let mut seen = HashSet::new();
+    let versions = results
+        .results
+        .into_iter()
+        .filter(|result| result.input == package.input)
+        .filter_map(|result| result.version)
+        .filter(|version| seen.insert(version.clone()))
+        .collect::<Vec<_>>();
+    if versions.len() <= 1 {
+        return Ok(None);
+    }
+
+    let options = std::iter::once(VersionOption::Latest)
+        .chain(versions.into_iter().map(VersionOption::Exact))
+        .collect();
+    let choice = Dialog {
+        message: &format!("Select a version of {pkg_path}:"),
+        help_message: None,
+        typed: Select { options },
+    }
+    .prompt()
+    .await?;
+
+    Ok(match choice {
+        VersionOption::Latest => None,
+        VersionOption::Exact(version) => Some(format!("={version}")),
+    })
+}
+
+/// A search result shown as a row of the picker
+struct PickerOption {
+    label: String,
+    /// Whether the package has to be installed from its input explicitly,
+    /// because the results come from multiple inputs
+    with_input: bool,
+    result: SearchResult,
+}
+
+impl PickerOption {
+    /// Render search results as aligned columns of
+    /// package path, version, license and description
+    fn from_search_results(results: Vec<SearchResult>) -> Vec<Self> {
+        let with_input = results
+            .iter()
+            .any(|result| result.input != results[0].input);
+        let pkg_path = |result: &SearchResult| {
+            let pkg_path = result.rel_path.join(".");
+            if with_input {
+                format!("{}:{pkg_path}", result.input)
+            } else {
+                pkg_path
+            }
+        };
+
+        let column_width = |column: &dyn Fn(&SearchResult) -> String| {
+            results
+                .iter()
+                .map(|result| column(result).chars().count())
+                .max()
+                .unwrap_or_default()
+        };
+        let version = |result: &SearchResult| result.version.clone().unwrap_or_default();
+        let license = |result: &SearchResult| result.license.clone().unwrap_or_default();
+        let pkg_path_width = column_width(&pkg_path);
+        let version_width = column_width(&version);
+        let license_width = column_width(&license);
+
+        results
+            .into_iter()
+            .map(|result| {
+                let description = result
+                    .description
+                    .as_deref()
+                    .unwrap_or(DEFAULT_DESCRIPTION)
+                    .replace('\n', " ");
+                let label = format!(
+                    "{:pkg_path_width$}  {:version_width$}  {:license_width$}  {description}",
+                    pkg_path(&result),
+                    version(&result),
+                    license(&result),
+                );
+                PickerOption {
+                    label,
+                    with_input,
+                    result,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Display for PickerOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)
+    }
+}
+
+enum VersionOption {
+    Latest,
+    Exact(String),
+}
+
+impl Display for VersionOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionOption::Latest => write!(f, "latest"),
+            VersionOption::Exact(version) => write!(f, "{version}"),
+        }
+    }
+}
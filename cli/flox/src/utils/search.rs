@@ -1,11 +1,21 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::path::PathBuf;
+use std::process::ExitStatus;
 
 use anyhow::Result;
 use flox_rust_sdk::flox::Flox;
+use flox_rust_sdk::models::environment::CanonicalPath;
 use flox_rust_sdk::models::lockfile::LockedManifest;
-use flox_rust_sdk::models::search::{PathOrJson, Query, SearchParams, SearchResult, SearchResults};
+use flox_rust_sdk::models::search::{
+    do_search,
+    PathOrJson,
+    Query,
+    SearchParams,
+    SearchResult,
+    SearchResults,
+};
+use flox_rust_sdk::models::search_index::{pkgdb_cache_dir, SearchIndex};
 use log::debug;
 
 use crate::commands::detect_environment;
@@ -87,6 +97,37 @@ pub(crate) fn construct_search_params(
     Ok(params)
 }
 
+/// Search for packages, preferring the [SearchIndex] over calling `pkgdb`
+///
+/// The index is used if all inputs of the lockfile have been scraped
+/// for the current system and it supports the query,
+/// otherwise this falls back to [do_search].
+/// The index always reports a successful exit status.
+pub(crate) fn search_packages(
+    flox: &Flox,
+    search_params: &SearchParams,
+) -> Result<(SearchResults, ExitStatus)> {
+    let PathOrJson::Path(ref lockfile_path) = search_params.lockfile else {
+        debug!("not using search index: lockfile is not a path");
+        return Ok(do_search(search_params)?);
+    };
+
+    let search_index = || -> Result<SearchResults> {
+        let cache_dir = pkgdb_cache_dir()?;
+        let lockfile_path = CanonicalPath::new(lockfile_path)?;
+        let index = SearchIndex::open(&cache_dir, &lockfile_path, &flox.system)?;
+        Ok(index.search(&search_params.query)?)
+    };
+
+    match search_index() {
+        Ok(results) => Ok((results, ExitStatus::default())),
+        Err(err) => {
+            debug!("not using search index: {err:#}");
+            Ok(do_search(search_params)?)
+        },
+    }
+}
+
 /// An intermediate representation of a search result used for rendering
 #[derive(Debug, PartialEq, Clone)]
 pub struct DisplayItem {
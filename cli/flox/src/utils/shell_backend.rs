@@ -0,0 +1,369 @@
+//! Shell specific rendering of activation scripts
+//!
+//! Every shell supported by `flox activate` is implemented as a [ShellBackend].
+//! The activation scripts themselves (sourcing profile scripts and setting
+//! the prompt) are generated per shell by `pkgdb buildenv` as
+//! `<env>/activate/<name>`.
+//! Backends render the glue around them,
+//! i.e. exporting variables and sourcing the activation script,
+//! and know how to start an interactive shell that runs it.
+//!
+//! csh and tcsh are out of scope:
+//! their syntax can't source the POSIX `profile.d` scripts or hooks,
+//! and `pkgdb buildenv` doesn't generate activation scripts for them.
+
+use std::borrow::Cow;
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+use itertools::Itertools;
+
+/// Shell specific parts of `flox activate`
+pub trait ShellBackend {
+    /// Name of the shell and its activation script, `<env>/activate/<name>`
+    fn name(&self) -> &'static str;
+
+    /// Quote a value so that it is not expanded by the shell
+    fn quote<'a>(&self, value: &'a str) -> Cow<'a, str>;
+
+    /// Render a command exporting `key` with `value`
+    fn export(&self, key: &str, value: &str) -> String;
+
+    /// Render a command unsetting `key`
+    fn unset(&self, key: &str) -> String;
+
+    /// Render a command sourcing the script at `path`
+    fn source(&self, path: &Path) -> String;
+
+    /// Render a command running `args`
+    ///
+    /// Args are quoted so that words don't get split,
+    /// but not all characters are escaped.
+    ///
+    /// To do this we escape `"`,
+    /// but we don't escape anything else.
+    /// We want `$` for example to be expanded by the shell.
+    fn run(&self, args: &[String]) -> String {
+        args.iter()
+            .map(|arg| format!(r#""{}""#, arg.replace('"', r#"\""#)))
+            .join(" ")
+    }
+
+    /// Configure `command` to start an interactive shell
+    /// that sources `activate_script` before accepting input
+    fn configure_interactive(&self, command: &mut Command, activate_script: &Path);
+
+    /// Render a command saving the current prompt to the variable `key`
+    ///
+    /// Shells that render the flox indicator dynamically
+    /// from `FLOX_PROMPT_ENVIRONMENTS` don't need to save their prompt.
+    fn save_prompt(&self, _key: &str) -> Option<String> {
+        None
+    }
+
+    /// Render a command restoring a prompt saved by [ShellBackend::save_prompt]
+    fn restore_prompt(&self, _prompt: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Quote a value for POSIX shells
+fn quote_posix(value: &str) -> Cow<'_, str> {
+    shell_escape::escape(Cow::Borrowed(value))
+}
+
+/// Render a command exporting `key` with `value` in POSIX shells
+fn export_posix(key: &str, value: &str) -> String {
+    format!("export {key}={}", quote_posix(value))
+}
+
+/// Render a command unsetting `key` in POSIX shells
+fn unset_posix(key: &str) -> String {
+    format!("unset {key}")
+}
+
+/// Render a command saving `PS1` to the variable `key` in POSIX shells
+fn save_prompt_posix(key: &str) -> Option<String> {
+    Some(format!("export {key}=\"${{PS1-}}\""))
+}
+
+/// Render a command restoring `PS1` to `prompt` in POSIX shells
+fn restore_prompt_posix(prompt: &str) -> Option<String> {
+    Some(format!("PS1={}", quote_posix(prompt)))
+}
+
+pub struct Bash;
+
+impl ShellBackend for Bash {
+    fn name(&self) -> &'static str {
+        "bash"
+    }
+
+    fn quote<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        quote_posix(value)
+    }
+
+    fn export(&self, key: &str, value: &str) -> String {
+        export_posix(key, value)
+    }
+
+    fn unset(&self, key: &str) -> String {
+        unset_posix(key)
+    }
+
+    fn source(&self, path: &Path) -> String {
+        format!("source {}", self.quote(&path.to_string_lossy()))
+    }
+
+    fn configure_interactive(&self, command: &mut Command, activate_script: &Path) {
+        command.arg("--rcfile").arg(activate_script);
+    }
+
+    fn save_prompt(&self, key: &str) -> Option<String> {
+        save_prompt_posix(key)
+    }
+
+    fn restore_prompt(&self, prompt: &str) -> Option<String> {
+        restore_prompt_posix(prompt)
+    }
+}
+
+pub struct Zsh;
+
+impl ShellBackend for Zsh {
+    fn name(&self) -> &'static str {
+        "zsh"
+    }
+
+    fn quote<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        quote_posix(value)
+    }
+
+    fn export(&self, key: &str, value: &str) -> String {
+        export_posix(key, value)
+    }
+
+    fn unset(&self, key: &str) -> String {
+        unset_posix(key)
+    }
+
+    fn source(&self, path: &Path) -> String {
+        format!("source {}", self.quote(&path.to_string_lossy()))
+    }
+
+    fn configure_interactive(&self, command: &mut Command, activate_script: &Path) {
+        // From man zsh:
+        // Commands are then read from $ZDOTDIR/.zshenv.  If the shell is a
+        // login shell, commands are read from /etc/zprofile and then
+        // $ZDOTDIR/.zprofile.  Then, if the shell is interactive, commands
+        // are read from /etc/zshrc and then $ZDOTDIR/.zshrc.  Finally, if
+        // the shell is a login shell, /etc/zlogin and $ZDOTDIR/.zlogin are
+        // read.
+        //
+        // We want to add our customizations as late as possible in the
+        // initialization process - if, e.g. the user has prompt
+        // customizations, we want ours to go last. So we put our
+        // customizations at the end of .zshrc, passing our customizations
+        // using FLOX_ZSH_INIT_SCRIPT.
+        // Otherwise, we want initialization to proceed as normal, so the
+        // files in our ZDOTDIR source global rcs and user rcs.
+        // We disable global rc files and instead source them manually so we
+        // can control the ZDOTDIR they are run with - this is important
+        // since macOS sets
+        // HISTFILE=${ZDOTDIR:-$HOME}/.zsh_history
+        // in /etc/zshrc.
+        if let Ok(zdotdir) = env::var("ZDOTDIR") {
+            command.env("FLOX_ORIG_ZDOTDIR", zdotdir);
+        }
+        command
+            .env("ZDOTDIR", env!("FLOX_ZDOTDIR"))
+            .env("FLOX_ZSH_INIT_SCRIPT", activate_script)
+            .arg("--no-globalrcs");
+    }
+
+    fn save_prompt(&self, key: &str) -> Option<String> {
+        save_prompt_posix(key)
+    }
+
+    fn restore_prompt(&self, prompt: &str) -> Option<String> {
+        restore_prompt_posix(prompt)
+    }
+}
+
+pub struct Fish;
+
+impl ShellBackend for Fish {
+    fn name(&self) -> &'static str {
+        "fish"
+    }
+
+    /// Unlike POSIX shells, fish treats `\` as an escape character
+    /// within single quotes, so it needs its own quoting.
+    fn quote<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let escaped = value.replace('\\', "\\\\").replace('\'', "\\'");
+        Cow::Owned(format!("'{escaped}'"))
+    }
+
+    // fish users may run `eval (flox activate)` which joins all lines,
+    // so every command is terminated explicitly.
+
+    fn export(&self, key: &str, value: &str) -> String {
+        format!("set -gx {key} {};", self.quote(value))
+    }
+
+    fn unset(&self, key: &str) -> String {
+        format!("set -e {key};")
+    }
+
+    fn source(&self, path: &Path) -> String {
+        format!("source {};", self.quote(&path.to_string_lossy()))
+    }
+
+    fn configure_interactive(&self, command: &mut Command, activate_script: &Path) {
+        // fish evaluates `--init-command` after reading the user's
+        // configuration, so our customizations (e.g. of the prompt)
+        // are applied last without having to replace config.fish.
+        command
+            .arg("--init-command")
+            .arg(self.source(activate_script));
+    }
+}
+
+/// POSIX `sh`, e.g. `dash` or busybox `ash`
+pub struct Posix;
+
+impl ShellBackend for Posix {
+    fn name(&self) -> &'static str {
+        "sh"
+    }
+
+    fn quote<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        quote_posix(value)
+    }
+
+    fn export(&self, key: &str, value: &str) -> String {
+        export_posix(key, value)
+    }
+
+    fn unset(&self, key: &str) -> String {
+        unset_posix(key)
+    }
+
+    /// `source` is not POSIX
+    fn source(&self, path: &Path) -> String {
+        format!(". {}", self.quote(&path.to_string_lossy()))
+    }
+
+    fn configure_interactive(&self, command: &mut Command, activate_script: &Path) {
+        // Interactive POSIX shells source the file named by `$ENV`.
+        // The activation script restores and sources the user's `$ENV`,
+        // or unsets it if `FLOX_ORIG_ENV` is empty.
+        command
+            .env("FLOX_ORIG_ENV", env::var("ENV").unwrap_or_default())
+            .env("ENV", activate_script)
+            .arg("-i");
+    }
+
+    fn save_prompt(&self, key: &str) -> Option<String> {
+        save_prompt_posix(key)
+    }
+
+    fn restore_prompt(&self, prompt: &str) -> Option<String> {
+        restore_prompt_posix(prompt)
+    }
+}
+
+pub struct Nu;
+
+impl ShellBackend for Nu {
+    fn name(&self) -> &'static str {
+        "nu"
+    }
+
+    /// Double quoted strings in nushell are not interpolated,
+    /// but support escapes.
+    fn quote<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+        Cow::Owned(format!("\"{escaped}\""))
+    }
+
+    /// nushell keeps `PATH` as a list
+    fn export(&self, key: &str, value: &str) -> String {
+        if key == "PATH" {
+            return format!(
+                "$env.PATH = ({} | split row (char esep))",
+                self.quote(value)
+            );
+        }
+        format!("$env.{key} = {}", self.quote(value))
+    }
+
+    fn unset(&self, key: &str) -> String {
+        format!("hide-env {key}")
+    }
+
+    /// nushell resolves `source` at parse time,
+    /// so `path` has to be a literal.
+    fn source(&self, path: &Path) -> String {
+        format!("source {}", self.quote(&path.to_string_lossy()))
+    }
+
+    /// A quoted command name has to be run explicitly as external command.
+    ///
+    /// Args are passed literally,
+    /// nushell doesn't expand variables in double quoted strings.
+    fn run(&self, args: &[String]) -> String {
+        format!("^{}", args.iter().map(|arg| self.quote(arg)).join(" "))
+    }
+
+    fn configure_interactive(&self, command: &mut Command, activate_script: &Path) {
+        // `--execute` runs after the user's config.nu and env.nu
+        command.arg("--execute").arg(self.source(activate_script));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        let value = r#"it's "a" \ $test"#;
+        assert_eq!(Bash.quote(value), r#"'it'\''s "a" \ $test'"#);
+        assert_eq!(Posix.quote(value), r#"'it'\''s "a" \ $test'"#);
+        assert_eq!(Fish.quote(value), r#"'it\'s "a" \\ $test'"#);
+        assert_eq!(Nu.quote(value), r#""it's \"a\" \\ $test""#);
+    }
+
+    #[test]
+    fn test_run() {
+        let args = ["a b".to_string(), '"'.to_string(), "$HOME".to_string()];
+        assert_eq!(Bash.run(&args), r#""a b" "\"" "$HOME""#);
+        assert_eq!(Nu.run(&args), r#"^"a b" "\"" "$HOME""#);
+    }
+
+    #[test]
+    fn test_source() {
+        let path = Path::new("/flox env/activate/sh");
+        assert_eq!(Posix.source(path), ". '/flox env/activate/sh'");
+        assert_eq!(Nu.source(path), r#"source "/flox env/activate/sh""#);
+    }
+
+    #[test]
+    fn test_export_nu() {
+        assert_eq!(Nu.export("FOO", "bar"), r#"$env.FOO = "bar""#);
+        assert_eq!(Nu.unset("FOO"), "hide-env FOO");
+    }
+
+    #[test]
+    fn test_save_prompt() {
+        assert_eq!(
+            Bash.save_prompt("SAVED").unwrap(),
+            r#"export SAVED="${PS1-}""#
+        );
+        assert_eq!(Posix.restore_prompt("$ ").unwrap(), "PS1='$ '");
+        assert_eq!(Fish.save_prompt("SAVED"), None);
+        assert_eq!(Nu.restore_prompt("$ "), None);
+    }
+}
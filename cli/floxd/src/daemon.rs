@@ -0,0 +1,445 @@
+//! The floxd service
+//!
+//! Three tasks feed a single build worker:
+//! - the socket server registers environments as they are activated,
+//! - the watcher queues a rebuild when the manifest or lockfile
+//!   of a registered environment changes,
+//! - the fetcher periodically queues fetching managed environments from FloxHub.
+//!
+//! Builds and fetches are delegated to `flox prepare`,
+//! so they use the same configuration and credentials as the flox CLI.
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use flox_rust_sdk::models::daemon::{
+    BuildState,
+    EnvironmentStatus,
+    PrepareResult,
+    Request,
+    Response,
+};
+use flox_rust_sdk::models::environment::managed_environment::GENERATION_LOCK_FILENAME;
+use flox_rust_sdk::models::environment::{
+    EnvironmentPointer,
+    ENVIRONMENT_POINTER_FILENAME,
+    ENV_DIR_NAME,
+    LOCKFILE_FILENAME,
+    MANIFEST_FILENAME,
+};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::process::Command;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+use tracing::{debug, error, info, warn};
+
+use crate::registry::Registry;
+
+/// How often registered environments are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often managed environments are fetched from FloxHub
+const FETCH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// An environment registered with the daemon
+#[derive(Debug)]
+struct Tracked {
+    status: EnvironmentStatus,
+    /// Newest modification time of the watched files
+    /// when the environment was last queued
+    seen: Option<SystemTime>,
+    /// The environment is waiting for the worker
+    queued: bool,
+    /// The next build should also fetch updates from FloxHub
+    fetch: bool,
+}
+
+impl Tracked {
+    fn new(dot_flox_path: &Path) -> Self {
+        Self {
+            status: EnvironmentStatus {
+                dot_flox_path: dot_flox_path.to_path_buf(),
+                build: BuildState::Pending,
+                upstream_changed: false,
+            },
+            seen: None,
+            queued: false,
+            fetch: false,
+        }
+    }
+}
+
+struct State {
+    registry: Registry,
+    environments: HashMap<PathBuf, Tracked>,
+}
+
+pub struct Daemon {
+    /// `flox` binary used to build and fetch environments
+    flox_bin: PathBuf,
+    state: Mutex<State>,
+    jobs: mpsc::UnboundedSender<PathBuf>,
+}
+
+/// Files of an environment that require a rebuild when they change
+fn watched_files(dot_flox_path: &Path) -> [PathBuf; 4] {
+    let env_dir = dot_flox_path.join(ENV_DIR_NAME);
+    [
+        env_dir.join(MANIFEST_FILENAME),
+        env_dir.join(LOCKFILE_FILENAME),
+        dot_flox_path.join(ENVIRONMENT_POINTER_FILENAME),
+        dot_flox_path.join(GENERATION_LOCK_FILENAME),
+    ]
+}
+
+/// Newest modification time of the watched files of an environment
+fn newest_mtime(dot_flox_path: &Path) -> Option<SystemTime> {
+    watched_files(dot_flox_path)
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+        .max()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Bind the daemon socket
+///
+/// The directory containing the socket is only accessible by the user.
+/// A socket left behind by a daemon that is no longer running is replaced.
+fn bind(socket: &Path) -> Result<UnixListener> {
+    let dir = socket.parent().context("Invalid socket path")?;
+    fs::create_dir_all(dir).with_context(|| format!("Could not create {dir:?}"))?;
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+
+    if socket.exists() {
+        if std::os::unix::net::UnixStream::connect(socket).is_ok() {
+            bail!("floxd is already running, listening on {socket:?}");
+        }
+        debug!(?socket, "removing stale socket");
+        fs::remove_file(socket)?;
+    }
+
+    UnixListener::bind(socket).with_context(|| format!("Could not listen on {socket:?}"))
+}
+
+/// Run the daemon until it receives SIGINT or SIGTERM
+pub async fn run(flox_bin: PathBuf, registry: Registry, socket: &Path) -> Result<()> {
+    let listener = bind(socket)?;
+    info!(?socket, "listening");
+
+    let (jobs, queue) = mpsc::unbounded_channel();
+    let daemon = Arc::new(Daemon {
+        flox_bin,
+        state: Mutex::new(State {
+            registry,
+            environments: HashMap::new(),
+        }),
+        jobs,
+    });
+
+    tokio::spawn(daemon.clone().work(queue));
+    tokio::spawn(daemon.clone().watch());
+    tokio::spawn(daemon.clone().fetch());
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        warn!(%err, "could not accept connection");
+                        continue;
+                    },
+                };
+                let daemon = daemon.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = daemon.serve(stream).await {
+                        debug!(%err, "could not serve request");
+                    }
+                });
+            },
+            _ = tokio::signal::ctrl_c() => break,
+            _ = terminate.recv() => break,
+        }
+    }
+
+    info!("shutting down");
+    fs::remove_file(socket)?;
+    Ok(())
+}
+
+impl Daemon {
+    /// Read a single request from `stream` and write the response
+    async fn serve(&self, stream: UnixStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).await?;
+
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => self.respond(request),
+            Err(err) => Response::Error {
+                message: format!("invalid request: {err}"),
+            },
+        };
+
+        let mut serialized = serde_json::to_string(&response)?;
+        serialized.push('\n');
+        writer.write_all(serialized.as_bytes()).await?;
+        Ok(())
+    }
+
+    fn respond(&self, request: Request) -> Response {
+        match request {
+            Request::Used { dot_flox_path } => match fs::canonicalize(&dot_flox_path) {
+                Ok(dot_flox_path) => Response::Environment {
+                    status: self.used(&dot_flox_path),
+                },
+                Err(err) => Response::Error {
+                    message: format!("could not resolve {dot_flox_path:?}: {err}"),
+                },
+            },
+            Request::List => {
+                let state = self.state.lock().unwrap();
+                let environments = state
+                    .registry
+                    .environments()
+                    .iter()
+                    .filter_map(|registered| state.environments.get(&registered.dot_flox_path))
+                    .map(|tracked| tracked.status.clone())
+                    .collect();
+                Response::Environments { environments }
+            },
+        }
+    }
+
+    /// Register the environment at `dot_flox_path` as used
+    /// and build it if it changed since it was last built
+    fn used(&self, dot_flox_path: &Path) -> EnvironmentStatus {
+        let mut state = self.state.lock().unwrap();
+
+        for evicted in state.registry.touch(dot_flox_path, now()) {
+            debug!(?evicted, "no longer keeping environment built");
+            state.environments.remove(&evicted);
+        }
+        if let Err(err) = state.registry.save() {
+            warn!(%err, "could not save registry");
+        }
+
+        let tracked = state
+            .environments
+            .entry(dot_flox_path.to_path_buf())
+            .or_insert_with(|| Tracked::new(dot_flox_path));
+        self.queue_if_changed(tracked);
+        tracked.status.clone()
+    }
+
+    /// Queue a build of `tracked` if its watched files changed
+    ///
+    /// A build that is ready but outdated is reported as pending,
+    /// so that clients don't activate it.
+    fn queue_if_changed(&self, tracked: &mut Tracked) {
+        let mtime = newest_mtime(&tracked.status.dot_flox_path);
+        if mtime == tracked.seen {
+            return;
+        }
+        tracked.seen = mtime;
+        if matches!(tracked.status.build, BuildState::Ready { .. }) {
+            tracked.status.build = BuildState::Pending;
+        }
+        self.queue(tracked);
+    }
+
+    fn queue(&self, tracked: &mut Tracked) {
+        if tracked.queued {
+            return;
+        }
+        tracked.queued = true;
+        // The worker only stops with the daemon
+        let _ = self.jobs.send(tracked.status.dot_flox_path.clone());
+    }
+
+    /// Rebuild registered environments when their files change
+    /// and forget environments that were deleted
+    async fn watch(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let mut state = self.state.lock().unwrap();
+            let State {
+                registry,
+                environments,
+            } = &mut *state;
+
+            let registered: Vec<PathBuf> = registry
+                .environments()
+                .iter()
+                .map(|registered| registered.dot_flox_path.clone())
+                .collect();
+
+            let mut deleted = false;
+            for dot_flox_path in registered {
+                if !dot_flox_path.exists() {
+                    debug!(?dot_flox_path, "environment was deleted");
+                    registry.remove(&dot_flox_path);
+                    environments.remove(&dot_flox_path);
+                    deleted = true;
+                    continue;
+                }
+
+                let tracked = environments
+                    .entry(dot_flox_path.clone())
+                    .or_insert_with(|| Tracked::new(&dot_flox_path));
+                self.queue_if_changed(tracked);
+            }
+
+            if deleted {
+                if let Err(err) = registry.save() {
+                    warn!(%err, "could not save registry");
+                }
+            }
+        }
+    }
+
+    /// Periodically fetch registered managed environments from FloxHub
+    async fn fetch(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(FETCH_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let mut state = self.state.lock().unwrap();
+            for tracked in state.environments.values_mut() {
+                let Some(project) = tracked.status.dot_flox_path.parent() else {
+                    continue;
+                };
+                if let Ok(EnvironmentPointer::Managed(_)) = EnvironmentPointer::open(project) {
+                    tracked.fetch = true;
+                    self.queue(tracked);
+                }
+            }
+        }
+    }
+
+    /// Build queued environments one at a time
+    async fn work(self: Arc<Self>, mut queue: mpsc::UnboundedReceiver<PathBuf>) {
+        while let Some(dot_flox_path) = queue.recv().await {
+            let fetch = {
+                let mut state = self.state.lock().unwrap();
+                // The environment may have been evicted while it was queued
+                let Some(tracked) = state.environments.get_mut(&dot_flox_path) else {
+                    continue;
+                };
+                tracked.queued = false;
+                tracked.status.build = BuildState::Building;
+                std::mem::take(&mut tracked.fetch)
+            };
+
+            info!(?dot_flox_path, fetch, "building environment");
+            let result = self.prepare(&dot_flox_path, fetch).await;
+
+            let mut state = self.state.lock().unwrap();
+            let Some(tracked) = state.environments.get_mut(&dot_flox_path) else {
+                continue;
+            };
+            match result {
+                Ok(result) => {
+                    info!(?dot_flox_path, activation_path = ?result.activation_path, "environment ready");
+                    // A newer build is already queued if the files changed during this one
+                    if !tracked.queued {
+                        tracked.status.build = BuildState::Ready {
+                            activation_path: result.activation_path,
+                        };
+                    }
+                    if let Some(upstream_changed) = result.upstream_changed {
+                        tracked.status.upstream_changed = upstream_changed;
+                    }
+                },
+                Err(err) => {
+                    error!(?dot_flox_path, %err, "could not build environment");
+                    tracked.status.build = BuildState::Failed {
+                        message: err.to_string(),
+                    };
+                },
+            }
+        }
+    }
+
+    /// Build the environment at `dot_flox_path` with `flox prepare`
+    async fn prepare(&self, dot_flox_path: &Path, fetch: bool) -> Result<PrepareResult> {
+        let project = dot_flox_path.parent().context("Invalid environment path")?;
+
+        let mut command = Command::new(&self.flox_bin);
+        command
+            .arg("prepare")
+            .arg("--dir")
+            .arg(project)
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        if fetch {
+            command.arg("--fetch");
+        }
+
+        let output = command
+            .output()
+            .await
+            .with_context(|| format!("Could not run {:?}", self.flox_bin))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!(
+                "{}",
+                stderr
+                    .lines()
+                    .last()
+                    .unwrap_or("flox prepare failed")
+                    .trim()
+            );
+        }
+
+        serde_json::from_slice(&output.stdout).context("Invalid output of flox prepare")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newest_mtime() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dot_flox_path = tempdir.path();
+        assert_eq!(newest_mtime(dot_flox_path), None);
+
+        fs::create_dir(dot_flox_path.join(ENV_DIR_NAME)).unwrap();
+        let manifest = dot_flox_path.join(ENV_DIR_NAME).join(MANIFEST_FILENAME);
+        fs::write(&manifest, "").unwrap();
+
+        let mtime = fs::metadata(&manifest).unwrap().modified().unwrap();
+        assert_eq!(newest_mtime(dot_flox_path), Some(mtime));
+    }
+
+    #[tokio::test]
+    async fn test_bind_replaces_stale_socket() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let socket = tempdir.path().join("floxd").join("floxd.sock");
+
+        let listener = bind(&socket).unwrap();
+        assert!(bind(&socket).is_err(), "bound while a daemon is running");
+
+        drop(listener);
+        bind(&socket).unwrap();
+    }
+}
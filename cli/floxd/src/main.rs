@@ -1,3 +1,58 @@
-fn main() {
-    println!("TODO: Write A Daemon");
+//! floxd, the per-user flox environment service
+//!
+//! floxd keeps recently activated environments built,
+//! so that `flox activate` can start without waiting for a build.
+//! See [flox_rust_sdk::models::daemon] for the protocol spoken on its socket.
+
+mod daemon;
+mod registry;
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use bpaf::Bpaf;
+use flox_rust_sdk::models::daemon::socket_path;
+use registry::{Registry, REGISTRY_FILENAME};
+use tracing_subscriber::EnvFilter;
+
+/// Keep recently used flox environments built and up to date
+#[derive(Debug, Bpaf)]
+#[bpaf(options, version)]
+struct Args {
+    /// Socket to listen on
+    /// [default: $XDG_CACHE_HOME/flox/floxd/floxd.sock]
+    #[bpaf(long, argument("path"))]
+    socket: Option<PathBuf>,
+
+    /// flox binary used to build environments
+    #[bpaf(long("flox-bin"), argument("path"), fallback("flox".into()))]
+    flox_bin: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = args().run();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_env("FLOXD_LOG").unwrap_or_else(|_| EnvFilter::new("floxd=info")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let socket = match args.socket {
+        Some(socket) => socket,
+        None => {
+            let cache_dir = xdg::BaseDirectories::with_prefix("flox")?.get_cache_home();
+            socket_path(&cache_dir)
+        },
+    };
+
+    let registry_path = socket
+        .parent()
+        .context("Invalid socket path")?
+        .join(REGISTRY_FILENAME);
+    let registry = Registry::load(registry_path)?;
+
+    daemon::run(args.flox_bin, registry, &socket).await
 }
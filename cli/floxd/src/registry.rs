@@ -0,0 +1,136 @@
+//! Environments registered with floxd
+//!
+//! Environments are registered when they are activated
+//! and kept in order of their last use.
+//! The registry is persisted, so that environments stay registered
+//! across restarts of the daemon.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub const REGISTRY_FILENAME: &str = "environments.json";
+
+/// Only the most recently used environments are kept built
+const MAX_ENVIRONMENTS: usize = 16;
+
+#[derive(Debug, Default)]
+pub struct Registry {
+    /// File the registry is persisted to
+    path: PathBuf,
+    /// Registered environments, most recently used first
+    environments: Vec<RegisteredEnvironment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegisteredEnvironment {
+    pub dot_flox_path: PathBuf,
+    /// Seconds since the Unix epoch
+    pub last_used: u64,
+}
+
+impl Registry {
+    /// Read the registry persisted at `path`,
+    /// or create an empty registry if it doesn't exist yet
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let environments = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .with_context(|| format!("Could not parse registry {path:?}"))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Could not read registry {path:?}"))
+            },
+        };
+
+        Ok(Self { path, environments })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let contents = serde_json::to_string_pretty(&self.environments)?;
+        fs::write(&self.path, contents)
+            .with_context(|| format!("Could not write registry {:?}", self.path))
+    }
+
+    /// Registered environments, most recently used first
+    pub fn environments(&self) -> &[RegisteredEnvironment] {
+        &self.environments
+    }
+
+    /// Record that the environment at `dot_flox_path` was used at `now`
+    ///
+    /// Returns the environments that are no longer registered
+    /// because more recently used environments took their place.
+    pub fn touch(&mut self, dot_flox_path: &Path, now: u64) -> Vec<PathBuf> {
+        self.remove(dot_flox_path);
+        self.environments.insert(0, RegisteredEnvironment {
+            dot_flox_path: dot_flox_path.to_path_buf(),
+            last_used: now,
+        });
+
+        let keep = self.environments.len().min(MAX_ENVIRONMENTS);
+        self.environments
+            .split_off(keep)
+            .into_iter()
+            .map(|environment| environment.dot_flox_path)
+            .collect()
+    }
+
+    pub fn remove(&mut self, dot_flox_path: &Path) {
+        self.environments
+            .retain(|environment| environment.dot_flox_path != dot_flox_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touch_orders_by_last_use() {
+        let mut registry = Registry::default();
+        registry.touch(Path::new("/a/.flox"), 1);
+        registry.touch(Path::new("/b/.flox"), 2);
+        registry.touch(Path::new("/a/.flox"), 3);
+
+        assert_eq!(registry.environments(), [
+            RegisteredEnvironment {
+                dot_flox_path: "/a/.flox".into(),
+                last_used: 3,
+            },
+            RegisteredEnvironment {
+                dot_flox_path: "/b/.flox".into(),
+                last_used: 2,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_touch_evicts_least_recently_used() {
+        let mut registry = Registry::default();
+        for i in 0..MAX_ENVIRONMENTS {
+            assert!(registry
+                .touch(&PathBuf::from(format!("/{i}/.flox")), i as u64)
+                .is_empty());
+        }
+
+        let evicted = registry.touch(Path::new("/new/.flox"), MAX_ENVIRONMENTS as u64);
+        assert_eq!(evicted, vec![PathBuf::from("/0/.flox")]);
+        assert_eq!(registry.environments().len(), MAX_ENVIRONMENTS);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join(REGISTRY_FILENAME);
+
+        let mut registry = Registry::load(path.clone()).unwrap();
+        assert!(registry.environments().is_empty());
+        registry.touch(Path::new("/a/.flox"), 1);
+        registry.save().unwrap();
+
+        let loaded = Registry::load(path).unwrap();
+        assert_eq!(loaded.environments(), registry.environments());
+    }
+}
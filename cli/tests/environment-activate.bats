@@ -309,6 +309,165 @@ env_is_activated() {
   assert_line "baz"
 }
 
+# bats test_tags=activate,activate:inplace-prints
+@test "'flox activate' prints script to modify current shell (fish)" {
+  FLOX_SHELL="fish" run "$FLOX_BIN" activate
+  assert_success
+  # check that env vars are set for compatibility with nix built software
+  assert_line --partial "set -gx NIX_SSL_CERT_FILE "
+  assert_output --regexp "source .*/activate/fish"
+}
+
+# bats test_tags=activate,activate:inplace-modifies
+@test "'flox activate' modifies the current shell (fish)" {
+
+  # set a hook
+  sed -i -e "s/^\[profile\]/${HELLO_PROFILE_SCRIPT//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+  # set vars
+  sed -i -e "s/^\[vars\]/${VARS//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+  "$FLOX_BIN" install hello
+
+  run fish -c '"$FLOX_BIN" activate | source; type hello; echo $foo'
+  assert_success
+  assert_line "Welcome to your flox environment!"
+  assert_line --partial "hello is $(realpath $PROJECT_DIR)/.flox/run/"
+  assert_line "baz"
+}
+
+# bats test_tags=activate,activate:inplace-prints
+@test "'flox activate' prints script to modify current shell (sh)" {
+  FLOX_SHELL="dash" run "$FLOX_BIN" activate
+  assert_success
+  # check that env vars are set for compatibility with nix built software
+  assert_line --partial "export NIX_SSL_CERT_FILE="
+  assert_output --regexp "\. .*/activate/sh"
+}
+
+# bats test_tags=activate,activate:inplace-modifies
+@test "'flox activate' modifies the current shell (sh)" {
+
+  # set a hook
+  sed -i -e "s/^\[profile\]/${HELLO_PROFILE_SCRIPT//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+  # set vars
+  sed -i -e "s/^\[vars\]/${VARS//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+  "$FLOX_BIN" install hello
+
+  run dash -c 'eval "$("$FLOX_BIN" activate)"; command -v hello; echo $foo'
+  assert_success
+  assert_line "Welcome to your flox environment!"
+  assert_line --partial "$(realpath $PROJECT_DIR)/.flox/run/"
+  assert_line "baz"
+}
+
+# bats test_tags=activate,activate:inplace-prints
+@test "'flox activate' prints script to modify current shell (nu)" {
+  FLOX_SHELL="nu" run "$FLOX_BIN" activate
+  assert_success
+  assert_line --partial '$env.NIX_SSL_CERT_FILE = '
+  assert_output --regexp "source .*/activate/nu"
+}
+
+# bats test_tags=activate,activate:print-env
+@test "'flox activate --json' prints changes to the environment" {
+  sed -i -e "s/^\[vars\]/${VARS//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+  "$FLOX_BIN" install hello
+
+  run --separate-stderr "$FLOX_BIN" activate --dir "$PROJECT_DIR" --json
+  assert_success
+  assert_equal "$(echo "$output" | jq -r '.set.foo')" "baz"
+  assert_equal "$(echo "$output" | jq -r '.set.FLOX_ENV')" "$(realpath "$PROJECT_DIR")/.flox/run/$NIX_SYSTEM.$PROJECT_NAME"
+  run jq -r '.prepend.PATH[]' <<< "$output"
+  assert_line --partial "/.flox/run/"
+}
+
+# bats test_tags=activate,activate:print-env
+@test "'flox activate --print-env' prints dotenv" {
+  sed -i -e "s/^\[vars\]/${VARS//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+
+  run --separate-stderr "$FLOX_BIN" activate --dir "$PROJECT_DIR" --print-env
+  assert_success
+  assert_line 'foo="baz"'
+  assert_line --regexp '^PATH=".*/.flox/run/.*"$'
+}
+
+# bats test_tags=activate,activate:direnv
+@test "'flox direnv' prints exports and watches the environment" {
+  sed -i -e "s/^\[vars\]/${VARS//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+
+  run --separate-stderr "$FLOX_BIN" direnv --dir "$PROJECT_DIR"
+  assert_success
+  assert_line --regexp "^watch_file .*/.flox/env/manifest.toml .*/.flox/env/manifest.lock .*/.flox/env.json .*/.flox/env.lock$"
+  assert_line "export foo=baz"
+  assert_line --regexp "^export _FLOX_ACTIVE_ENVIRONMENTS="
+}
+
+# bats test_tags=activate,activate:direnv
+@test "'flox direnv' only watches files of already active environments" {
+  run --separate-stderr bash -c 'eval "$("$FLOX_BIN" activate --dir "$PROJECT_DIR")"; "$FLOX_BIN" direnv --dir "$PROJECT_DIR"'
+  assert_success
+  assert_equal "${#lines[@]}" 1
+  assert_line --regexp "^watch_file "
+}
+
+# bats test_tags=activate,activate:deactivate
+@test "bash: 'flox deactivate' restores the environment" {
+  sed -i -e "s/^\[vars\]/${VARS//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+
+  FLOX_SHELL=bash run --separate-stderr bash -c '
+    before="$PATH"
+    eval "$("$FLOX_BIN" activate --dir "$PROJECT_DIR")"
+    echo "foo=${foo:-unset}"
+    eval "$("$FLOX_BIN" deactivate)"
+    [ "$PATH" = "$before" ] && echo "PATH restored"
+    echo "foo=${foo:-unset}"
+    echo "active=${_FLOX_ACTIVE_ENVIRONMENTS:-none}"
+  '
+  assert_success
+  assert_equal "${lines[0]}" "foo=baz"
+  assert_equal "${lines[1]}" "PATH restored"
+  assert_equal "${lines[2]}" "foo=unset"
+  assert_equal "${lines[3]}" "active=none"
+}
+
+# bats test_tags=activate,activate:deactivate
+@test "'flox deactivate' fails if no environment is active" {
+  run "$FLOX_BIN" deactivate
+  assert_failure
+  assert_output --partial "No environment is active"
+}
+
+# bats test_tags=activate,activate:deactivate
+@test "'flox deactivate' fails for environments activated in a subshell" {
+  FLOX_SHELL=bash run "$FLOX_BIN" activate --dir "$PROJECT_DIR" -- "$FLOX_BIN" deactivate
+  assert_failure
+  assert_output --partial "Type 'exit'"
+}
+
+# bats test_tags=activate,activate:replace
+@test "bash: 'flox activate --replace' replaces the last activated environment" {
+  "$FLOX_BIN" init -d "$PROJECT_DIR/other"
+
+  FLOX_SHELL=bash run --separate-stderr bash -c '
+    eval "$("$FLOX_BIN" activate --dir "$PROJECT_DIR")"
+    eval "$("$FLOX_BIN" activate --replace --dir "$PROJECT_DIR/other")"
+    echo "$FLOX_PROMPT_ENVIRONMENTS"
+    eval "$("$FLOX_BIN" deactivate)"
+    echo "active=${_FLOX_ACTIVE_ENVIRONMENTS:-none}"
+  '
+  assert_success
+  assert_equal "${lines[0]}" "other"
+  assert_equal "${lines[1]}" "active=none"
+}
+
+# bats test_tags=activate,activate:envVar:nu
+@test "nu: activate runs a command with env vars set" {
+  sed -i -e "s/^\[vars\]/${VARS//$'\n'/\\n}/" "$PROJECT_DIR/.flox/env/manifest.toml"
+
+  FLOX_SHELL=nu NO_COLOR=1 run "$FLOX_BIN" activate --dir "$PROJECT_DIR" -- printenv foo
+  assert_success
+  assert_line "baz"
+}
+
 # ---------------------------------------------------------------------------- #
 
 # bats test_tags=activate,activate:inplace-reactivate
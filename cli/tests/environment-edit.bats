@@ -140,6 +140,28 @@ EOF
   assert_success
 }
 
+# ---------------------------------------------------------------------------- #
+# bats test_tags=edit:manifest:file:invalid
+@test "'flox edit' reports the location of invalid manifest fields" {
+
+  "$FLOX_BIN" init
+  ORIGINAL_MANIFEST_CONTENTS="$(cat "$MANIFEST_PATH")" # for check_manifest_unchanged
+
+  cat << "EOF" > ./manifest.toml
+[install]
+hello.pkg-path = "hello"
+
+[hook]
+on-activte = "echo hello"
+EOF
+
+  run "$FLOX_BIN" edit -f ./manifest.toml
+  assert_failure
+  assert_output --partial 'invalid manifest at line 5, column 1: unknown field `on-activte`'
+  run check_manifest_unchanged
+  assert_success
+}
+
 # ---------------------------------------------------------------------------- #
 
 @test "'flox edit' fails when provided filename doesn't exist" {
@@ -0,0 +1,197 @@
+#! /usr/bin/env bats
+# -*- mode: bats; -*-
+# ============================================================================ #
+#
+# Test the local generations of path environments
+# * `flox history`
+# * `flox rollback`
+# * `flox switch-generation`
+#
+# ---------------------------------------------------------------------------- #
+
+load test_support.bash
+# bats file_tags=history
+
+# ---------------------------------------------------------------------------- #
+
+setup() {
+  common_test_setup
+  project_setup
+}
+teardown() {
+  project_teardown
+  common_test_teardown
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox init --history' creates the first generation" {
+  "$FLOX_BIN" init --history
+
+  run --separate-stderr "$FLOX_BIN" history --json
+  assert_success
+  run jq -r '.[] | "\(.generation) \(.current) \(.description)"' <<< "$output"
+  assert_output "1 true Initialize environment"
+}
+
+@test "transactions create generations" {
+  "$FLOX_BIN" init --history
+  "$FLOX_BIN" install hello
+  "$FLOX_BIN" uninstall hello
+
+  run --separate-stderr "$FLOX_BIN" history --oneline
+  assert_success
+  assert_line --index 0 --regexp '^\* 3 .*uninstalled packages'
+  assert_line --index 1 --regexp '^  2 .*installed packages'
+  assert_line --index 2 --regexp '^  1 .*Initialize environment'
+}
+
+@test "'flox rollback' restores the previous generation" {
+  "$FLOX_BIN" init --history
+  "$FLOX_BIN" install hello
+
+  run "$FLOX_BIN" rollback
+  assert_success
+  assert_output --partial "to generation 1"
+
+  run --separate-stderr "$FLOX_BIN" list --name
+  assert_success
+  assert_output ""
+
+  run "$FLOX_BIN" switch-generation 2
+  assert_success
+
+  run --separate-stderr "$FLOX_BIN" list --name
+  assert_success
+  assert_output "hello"
+}
+
+@test "environments don't keep a history by default" {
+  "$FLOX_BIN" init
+  "$FLOX_BIN" install hello
+
+  assert [ ! -e .flox/generations ]
+
+  run "$FLOX_BIN" history
+  assert_success
+  assert_output --partial "flox history --enable"
+}
+
+@test "'flox history --enable' imports the current state" {
+  "$FLOX_BIN" init
+
+  run "$FLOX_BIN" history --enable
+  assert_success
+  assert_output --partial "Enabled history"
+
+  "$FLOX_BIN" install hello
+
+  run --separate-stderr "$FLOX_BIN" history --oneline
+  assert_success
+  assert_line --index 0 --regexp '^\* 2 .*installed packages'
+  assert_line --index 1 --regexp '^  1 .*Import existing environment'
+}
+
+@test "'flox generations --json' lists package changes per generation" {
+  "$FLOX_BIN" init --history
+  "$FLOX_BIN" install hello
+  "$FLOX_BIN" uninstall hello
+
+  run --separate-stderr "$FLOX_BIN" generations --json
+  assert_success
+  generations="$output"
+
+  run jq -r '.[0] | "\(.generation) \(.changes.removed[0].installId)"' <<< "$generations"
+  assert_output "3 hello"
+
+  run jq -r '.[1] | "\(.generation) \(.changes.added[0].installId)"' <<< "$generations"
+  assert_output "2 hello"
+
+  run --separate-stderr "$FLOX_BIN" generations
+  assert_success
+  assert_output --partial "  + hello ("
+  assert_output --partial "  - hello ("
+}
+
+@test "'flox wipe-history' keeps the most recent generations" {
+  "$FLOX_BIN" init --history
+  "$FLOX_BIN" install hello
+  "$FLOX_BIN" uninstall hello
+
+  run "$FLOX_BIN" wipe-history --keep 2 --dry-run
+  assert_success
+  assert_output --partial "Would delete generation 1"
+
+  run --separate-stderr "$FLOX_BIN" history --json
+  run jq -r 'length' <<< "$output"
+  assert_output "3"
+
+  run "$FLOX_BIN" wipe-history --keep 2
+  assert_success
+  assert_output --partial "Deleted 1 generation(s)"
+
+  run --separate-stderr "$FLOX_BIN" history --json
+  run jq -r '[.[].generation] | join(" ")' <<< "$output"
+  assert_output "3 2"
+}
+
+@test "'flox diff' compares generations and the working copy" {
+  "$FLOX_BIN" init --history
+  "$FLOX_BIN" install hello
+
+  run --separate-stderr "$FLOX_BIN" diff --from 1 --json
+  assert_success
+  run jq -r '.added[0].installId' <<< "$output"
+  assert_output "hello"
+
+  run "$FLOX_BIN" diff --from 2 --to 2
+  assert_success
+  assert_output --partial "No differences."
+
+  sed -i -e '/^hello\./d' "$PROJECT_DIR/.flox/env/manifest.toml"
+  run --separate-stderr "$FLOX_BIN" diff
+  assert_success
+  assert_output --partial "  - hello ("
+}
+
+@test "'flox diff' compares the working copy without a history" {
+  "$FLOX_BIN" init
+  "$FLOX_BIN" install hello
+
+  run "$FLOX_BIN" diff
+  assert_success
+  assert_output --partial "No differences."
+
+  sed -i -e '/^hello\./d' "$PROJECT_DIR/.flox/env/manifest.toml"
+  run --separate-stderr "$FLOX_BIN" diff
+  assert_success
+  assert_output --partial "  - hello ("
+}
+
+@test "'flox diff' shows changed variables and hooks" {
+  "$FLOX_BIN" init --history
+
+  sed -i -e 's/^\[vars\]/[vars]\nfoo = "bar"/' "$PROJECT_DIR/.flox/env/manifest.toml"
+  run --separate-stderr "$FLOX_BIN" diff
+  assert_success
+  assert_output --partial "  Changed variables: foo"
+  refute_output --partial "Changed sections"
+
+  sed -i -e 's/^\[hook\]/[hook]\non-activate = "echo hi"/' "$PROJECT_DIR/.flox/env/manifest.toml"
+  run --separate-stderr "$FLOX_BIN" diff --json
+  assert_success
+  run jq -r '"\(.vars | join(",")) \(.sections | join(","))"' <<< "$output"
+  assert_output "foo hook"
+}
+
+@test "'flox diff' compares two directories" {
+  mkdir old new
+  "$FLOX_BIN" init -d old
+  "$FLOX_BIN" init -d new
+  "$FLOX_BIN" install -d new hello
+
+  run --separate-stderr "$FLOX_BIN" diff old new --json
+  assert_success
+  run jq -r '.added[0].installId' <<< "$output"
+  assert_output "hello"
+}
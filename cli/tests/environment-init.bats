@@ -137,6 +137,8 @@ function check_with_dir() {
   run cat .flox/.gitignore
   assert_success
   assert_line "run/"
+  assert_line "generations/"
+  assert_line "env.transaction.lock"
 }
 
 @test "'flox init' injects current system" {
@@ -314,3 +314,78 @@ teardown() {
   assert_line --partial "The package 'yi' is marked as broken."
   assert_output --partial "'options.allow.broken = true'"
 }
+
+@test "'flox install' picks packages interactively without arguments" {
+  "$FLOX_BIN" init
+
+  NO_COLOR=1 run -0 expect "$TESTS_DIR/install/interactive.exp"
+  run grep 'hello.pkg-path = "hello"' "$MANIFEST_PATH"
+  assert_success
+}
+
+@test "'flox install --interactive' requires a terminal" {
+  "$FLOX_BIN" init
+
+  run "$FLOX_BIN" install --interactive
+  assert_failure
+  assert_output --partial "Can't pick packages interactively without a terminal"
+}
+
+# ---------------------------------------------------------------------------- #
+
+# Create a flake in `$PROJECT_DIR/tools' providing a `linter' package
+make_tools_flake() {
+  mkdir -p "$PROJECT_DIR/tools"
+  cat > "$PROJECT_DIR/tools/flake.nix" <<- EOF
+	{
+	  outputs = { self }: {
+	    packages.$NIX_SYSTEM.linter = derivation {
+	      name = "linter-1.0.0";
+	      system = "$NIX_SYSTEM";
+	      builder = "/bin/sh";
+	      args = [ "-c" "echo linter > \$out" ];
+	    };
+	  };
+	}
+	EOF
+}
+
+@test "'flox install' installs packages from flake references" {
+  "$FLOX_BIN" init
+  make_tools_flake
+
+  run "$FLOX_BIN" install ./tools#linter
+  assert_success
+  assert_output --partial "✅ 'linter' installed to environment"
+  assert_output --partial "'./tools' refers to a flake on this machine"
+
+  run tomlq -r '.registry.inputs.tools.from' "$MANIFEST_PATH"
+  assert_output "$PROJECT_DIR/tools"
+  run tomlq -r '.install.linter."package-repository"' "$MANIFEST_PATH"
+  assert_output "tools"
+
+  # The environment is locked with both the flake and the GA registry inputs
+  run jq -r '.registry.inputs | keys | join(",")' "$LOCKFILE_PATH"
+  assert_output "nixpkgs,tools"
+}
+
+@test "'flox install' resolves relative 'path:' flake references" {
+  "$FLOX_BIN" init
+  make_tools_flake
+
+  run "$FLOX_BIN" install path:./tools#linter
+  assert_success
+  assert_output --partial "stored as 'path:$PROJECT_DIR/tools'"
+
+  run tomlq -r '.registry.inputs.tools.from' "$MANIFEST_PATH"
+  assert_output "path:$PROJECT_DIR/tools"
+}
+
+@test "'flox install' reports packages missing from flake references" {
+  "$FLOX_BIN" init
+  make_tools_flake
+
+  run "$FLOX_BIN" install ./tools#formatter
+  assert_failure
+  assert_output --partial "Could not find package formatter in $PROJECT_DIR/tools."
+}
@@ -324,6 +324,121 @@ EOF
   refute_output "vim"
 }
 
+# bats test_tags=managed,history,managed:history
+@test "m12: history lists generations of a managed environment" {
+  make_empty_remote_env
+  "$FLOX_BIN" install hello
+
+  run --separate-stderr "$FLOX_BIN" history --oneline
+  assert_success
+  assert_line --index 0 --regexp '^\* 2 .*installed packages'
+  assert_line --index 1 --regexp '^  1 .*Add first generation'
+
+  run --separate-stderr "$FLOX_BIN" history --json
+  assert_success
+  run jq -r '.[0] | "\(.generation) \(.current)"' <<< "$output"
+  assert_output "2 true"
+}
+
+# bats test_tags=managed,history,managed:history
+@test "m12: push moves the local history to the managed environment" {
+  "$FLOX_BIN" init --history
+  "$FLOX_BIN" install hello
+  "$FLOX_BIN" push --owner "$OWNER"
+
+  assert [ ! -e .flox/generations ]
+
+  run --separate-stderr "$FLOX_BIN" history --oneline
+  assert_success
+  assert_line --index 0 --regexp '^\* 3 .*Push local environment'
+  assert_line --index 1 --regexp '^  2 .*installed packages'
+  assert_line --index 2 --regexp '^  1 .*Initialize environment'
+}
+
+# bats test_tags=managed,rollback,managed:rollback
+@test "m13: rollback switches to the previous generation" {
+  make_empty_remote_env
+  "$FLOX_BIN" install hello
+
+  run "$FLOX_BIN" rollback
+  assert_success
+  assert_output --partial "to generation 1"
+
+  run --separate-stderr "$FLOX_BIN" list --name
+  assert_success
+  assert_output ""
+
+  run --separate-stderr "$FLOX_BIN" history --json
+  run jq -r '.[] | select(.current) | .generation' <<< "$output"
+  assert_output "1"
+
+  run "$FLOX_BIN" rollback
+  assert_failure
+  assert_output --partial "no generation before the current one"
+}
+
+# bats test_tags=managed,rollback,managed:switch-generation
+@test "m14: switch-generation switches to the given generation" {
+  make_empty_remote_env
+  "$FLOX_BIN" install hello
+  "$FLOX_BIN" rollback
+
+  run "$FLOX_BIN" switch-generation 2
+  assert_success
+  assert_output --partial "to generation 2"
+
+  run --separate-stderr "$FLOX_BIN" list --name
+  assert_success
+  assert_output "hello"
+
+  run "$FLOX_BIN" switch-generation 5
+  assert_failure
+  assert_output --partial "Generation 5 does not exist."
+}
+
+# bats test_tags=managed,history,managed:history
+@test "m15: wipe-history removes links of deleted generations only" {
+  make_empty_remote_env
+  "$FLOX_BIN" install hello
+  "$FLOX_BIN" uninstall hello
+
+  run "$FLOX_BIN" wipe-history --keep 1 --dry-run
+  assert_success
+  assert_output --regexp "Would remove link .*-2-link"
+
+  run "$FLOX_BIN" wipe-history --keep 1
+  assert_success
+  assert_output --partial "Deleted 2 generation(s)"
+
+  run compgen -G "$FLOX_CACHE_HOME/run/$OWNER/*-2-link"
+  assert_failure
+  run compgen -G "$FLOX_CACHE_HOME/run/$OWNER/*-3-link"
+  assert_success
+
+  "$FLOX_BIN" delete -f
+  run compgen -G "$FLOX_CACHE_HOME/run/$OWNER/*-link"
+  assert_failure
+}
+
+# bats test_tags=managed,install,managed:install
+@test "m16: concurrent installs are serialized" {
+  make_empty_remote_env
+
+  "$FLOX_BIN" install hello &
+  pid=$!
+  "$FLOX_BIN" install curl
+  wait "$pid"
+
+  run --separate-stderr "$FLOX_BIN" list --name
+  assert_success
+  assert_line "curl"
+  assert_line "hello"
+
+  run --separate-stderr "$FLOX_BIN" history --json
+  run jq -r 'length' <<< "$output"
+  assert_output "3"
+}
+
 @test "sanity check upgrade works for managed environments" {
   _PKGDB_GA_REGISTRY_REF_OR_REV="${PKGDB_NIXPKGS_REV_OLD?}" \
   make_empty_remote_env
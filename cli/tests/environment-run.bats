@@ -0,0 +1,90 @@
+#! /usr/bin/env bats
+# -*- mode: bats; -*-
+# ============================================================================ #
+#
+# Test 'flox run'
+#
+# ---------------------------------------------------------------------------- #
+
+load test_support.bash
+# bats file_tags=run
+
+# ---------------------------------------------------------------------------- #
+
+setup() {
+  common_test_setup
+  project_setup
+  "$FLOX_BIN" init -d "$PROJECT_DIR"
+}
+teardown() {
+  project_teardown
+  common_test_teardown
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox run' runs a command with the environment's vars" {
+  sed -i -e 's/^\[vars\]/[vars]\nfoo = "baz"/' "$PROJECT_DIR/.flox/env/manifest.toml"
+
+  run --separate-stderr "$FLOX_BIN" run -- printenv foo
+  assert_success
+  assert_output "baz"
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox run' propagates the exit code of the command" {
+  run "$FLOX_BIN" run -- sh -c 'exit 42'
+  assert_equal "$status" 42
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox run' propagates signals terminating the command" {
+  run "$FLOX_BIN" run -- sh -c 'kill -TERM $$'
+  # 128 + SIGTERM (15)
+  assert_equal "$status" 143
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox run' runs hooks with output on stderr" {
+  sed -i -e "s/^\[hook\]/[hook]\non-activate = \"echo 'hook ran'\"/" "$PROJECT_DIR/.flox/env/manifest.toml"
+
+  run --separate-stderr "$FLOX_BIN" run -- echo "command ran"
+  assert_success
+  assert_output "command ran"
+  [[ "$stderr" =~ "hook ran" ]]
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox run --no-hooks' skips hooks and profile scripts" {
+  sed -i \
+    -e 's/^\[vars\]/[vars]\nfoo = "baz"/' \
+    -e "s/^\[hook\]/[hook]\non-activate = \"echo 'hook ran'\"/" \
+    -e 's/^\[profile\]/[profile]\ncommon = "export foo=profile"/' \
+    "$PROJECT_DIR/.flox/env/manifest.toml"
+
+  run --separate-stderr "$FLOX_BIN" run --no-hooks -- printenv foo
+  assert_success
+  assert_output "baz"
+  [[ ! "$stderr" =~ "hook ran" ]]
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox run' puts installed packages in PATH" {
+  "$FLOX_BIN" install hello
+
+  run --separate-stderr "$FLOX_BIN" run --no-hooks -- hello
+  assert_success
+  assert_output "Hello, world!"
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox run' requires a command" {
+  run "$FLOX_BIN" run
+  assert_failure
+}
@@ -245,6 +245,43 @@ setup_file() {
 
 # ---------------------------------------------------------------------------- #
 
+@test "'flox search' uses the search index for scraped inputs" {
+  # The first search scrapes the global lockfile's inputs
+  run "$FLOX_BIN" search hello
+  assert_success
+
+  run "$FLOX_BIN" -vv search hello
+  assert_success
+  refute_output --partial "not using search index"
+  assert_output --partial "hello"
+}
+
+# ---------------------------------------------------------------------------- #
+
+# bats test_tags=search:filters
+@test "'flox search' filters by description" {
+  run --separate-stderr "$FLOX_BIN" search hello --json --description 'familiar, friendly'
+  assert_success
+  run jq -r '.[].relPath | join(".")' <<< "$output"
+  assert_output "hello"
+
+  run "$FLOX_BIN" search hello --description 'does not match anything'
+  assert_failure
+  assert_output --partial "No packages matched this search term: hello"
+}
+
+# ---------------------------------------------------------------------------- #
+
+# bats test_tags=search:filters
+@test "'flox search' filters by license" {
+  run --separate-stderr "$FLOX_BIN" search hello --all --json --license GPL-3.0-or-later
+  assert_success
+  run jq -r '[.[].license] | unique | join(",")' <<< "$output"
+  assert_output "GPL-3.0-or-later"
+}
+
+# ---------------------------------------------------------------------------- #
+
 # bats test_tags=search:hint
 @test "'flox search' includes search term in hint" {
   run --separate-stderr "$FLOX_BIN" search python
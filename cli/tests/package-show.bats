@@ -163,7 +163,7 @@ setup_file() {
 
   # Ensure the version of `nodejs' in our search results aligns with that in
   # _PKGDB_GA_REGISTRY_REF_OR_REV.
-  run --separate-stderr sh -c "$FLOX_BIN show nodejs|tail -n1"
+  run --separate-stderr sh -c "$FLOX_BIN show nodejs|sed -n 2p"
   assert_success
   assert_output "    nodejs - nodejs@$NODEJS_VERSION_NEW"
 
@@ -215,7 +215,7 @@ setup_file() {
 
   # Ensure the version of `nodejs' in our search results aligns with the
   # locked rev, instead of the `--ga-registry` default.
-  run --separate-stderr sh -c "$FLOX_BIN show nodejs|tail -n1"
+  run --separate-stderr sh -c "$FLOX_BIN show nodejs|sed -n 2p"
   assert_success
   assert_output "    nodejs - nodejs@$NODEJS_VERSION_OLD"
 
@@ -228,7 +228,7 @@ setup_file() {
   rm -f "$GLOBAL_MANIFEST_LOCK"
   run ! [ -e "$LOCKFILE_PATH" ]
   _PKGDB_GA_REGISTRY_REF_OR_REV="${PKGDB_NIXPKGS_REV_OLD?}" \
-    run --separate-stderr sh -c "$FLOX_BIN show nodejs|tail -n1"
+    run --separate-stderr sh -c "$FLOX_BIN show nodejs|sed -n 2p"
   assert_success
   assert_output "    nodejs - nodejs@$NODEJS_VERSION_OLD"
 
@@ -248,7 +248,7 @@ setup_file() {
 
   # Set new rev just to make sure we're not incidentally using old rev.
   _PKGDB_GA_REGISTRY_REF_OR_REV="${PKGDB_NIXPKGS_REV_NEW?}" \
-    run --separate-stderr sh -c "$FLOX_BIN show nodejs|tail -n1"
+    run --separate-stderr sh -c "$FLOX_BIN show nodejs|sed -n 2p"
   assert_success
   assert_output '    nodejs - nodejs@18.16.0'
 
@@ -268,7 +268,7 @@ setup_file() {
     "$FLOX_BIN" init
   "$FLOX_BIN" --debug install nodejs
 
-  run --separate-stderr sh -c "$FLOX_BIN show nodejs|tail -n1"
+  run --separate-stderr sh -c "$FLOX_BIN show nodejs|sed -n 2p"
   assert_success
   assert_output "    nodejs - nodejs@$NODEJS_VERSION_OLD"
   popd
@@ -282,7 +282,7 @@ setup_file() {
   "$FLOX_BIN" init
   "$FLOX_BIN" install nodejs
 
-  run --separate-stderr sh -c "$FLOX_BIN show nodejs|tail -n1"
+  run --separate-stderr sh -c "$FLOX_BIN show nodejs|sed -n 2p"
   assert_success
   assert_output "    nodejs - nodejs@$NODEJS_VERSION_NEW"
   popd
@@ -291,3 +291,29 @@ setup_file() {
   assert_success
   assert_output --partial "nodejs - nodejs@$NODEJS_VERSION_NEW"
 }
+
+# ---------------------------------------------------------------------------- #
+
+# bats test_tags=search:show, search:system
+@test "'flox show' lists availability on the manifest's systems" {
+  project_setup
+  echo 'options.systems = ["x86_64-linux", "aarch64-darwin"]' \
+    > "$PROJECT_DIR/.flox/env/manifest.toml"
+  "$FLOX_BIN" install hello
+
+  run --separate-stderr "$FLOX_BIN" show hello
+  assert_success
+  assert_regex "${lines[3]}" "^    Version +Input +x86_64-linux +aarch64-darwin$"
+  assert_regex "${lines[4]}" "^    2.12.1 +nixpkgs \([0-9a-f]{7}\) +✓ +✓$"
+  project_teardown
+}
+
+# ---------------------------------------------------------------------------- #
+
+# bats test_tags=search:show, search:json
+@test "'flox show --json' lists input, revision and systems" {
+  run --separate-stderr "$FLOX_BIN" show hello --json --system x86_64-linux --system aarch64-darwin
+  assert_success
+  run jq -r '.versions[0] | [.version, .input, (.systems | join(","))] | join(" ")' <<< "$output"
+  assert_output "2.12.1 nixpkgs x86_64-linux,aarch64-darwin"
+}
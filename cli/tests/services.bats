@@ -0,0 +1,158 @@
+#! /usr/bin/env bats
+# -*- mode: bats; -*-
+# ============================================================================ #
+#
+# Test 'flox services'
+#
+# ---------------------------------------------------------------------------- #
+
+load test_support.bash
+# bats file_tags=services
+
+# ---------------------------------------------------------------------------- #
+
+# Append a service read from stdin to the manifest of the project environment
+add_service() {
+  cat >> "$PROJECT_DIR/.flox/env/manifest.toml"
+}
+
+# ---------------------------------------------------------------------------- #
+
+setup() {
+  common_test_setup
+  project_setup
+  "$FLOX_BIN" init -d "$PROJECT_DIR"
+}
+teardown() {
+  # don't leave supervisors behind
+  "$FLOX_BIN" services stop -d "$PROJECT_DIR" || true
+  project_teardown
+  common_test_teardown
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox services start' fails without services" {
+  run "$FLOX_BIN" services start
+  assert_failure
+  assert_output --partial "The environment does not define any services"
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox services start' starts and 'stop' stops a service" {
+  add_service <<- 'TOML'
+	[services.sleeper]
+	command = "sleep 1000"
+	TOML
+
+  run "$FLOX_BIN" services start
+  assert_success
+  assert_output --partial "Service 'sleeper' started"
+
+  run "$FLOX_BIN" services status
+  assert_success
+  assert_output --regexp "sleeper +running +[0-9]+"
+
+  run "$FLOX_BIN" services stop
+  assert_success
+  assert_output --partial "Service 'sleeper' stopped"
+
+  run "$FLOX_BIN" services status
+  assert_output --regexp "sleeper +stopped"
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox services start' skips running services" {
+  add_service <<- 'TOML'
+	[services.sleeper]
+	command = "sleep 1000"
+	TOML
+
+  "$FLOX_BIN" services start
+  run "$FLOX_BIN" services start sleeper
+  assert_success
+  assert_output --partial "Service 'sleeper' is already running"
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "services run in the project directory with their vars" {
+  sed -i -e 's/^\[vars\]/[vars]\nfoo = "from-vars"\nbar = "from-vars"/' \
+    "$PROJECT_DIR/.flox/env/manifest.toml"
+  add_service <<- 'TOML'
+	[services.printer]
+	command = 'echo "$foo $bar $PWD" > out; sleep 1000'
+	vars = { bar = "from-service" }
+	ready-check = "test -f out"
+	TOML
+
+  run "$FLOX_BIN" services start
+  assert_success
+  run cat "$PROJECT_DIR/out"
+  assert_output "from-vars from-service $PROJECT_DIR"
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox services start' waits for the ready-check" {
+  add_service <<- 'TOML'
+	[services.slow]
+	command = "sleep 2; touch ready; sleep 1000"
+	ready-check = "test -f ready"
+	TOML
+
+  run "$FLOX_BIN" services start
+  assert_success
+  assert [ -f "$PROJECT_DIR/ready" ]
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox services start' fails if a service exits" {
+  add_service <<- 'TOML'
+	[services.failing]
+	command = "echo starting failing; exit 3"
+	ready-check = "false"
+	TOML
+
+  run "$FLOX_BIN" services start
+  assert_failure
+  assert_output --partial "failing: exited with status 3"
+
+  run "$FLOX_BIN" services logs failing
+  assert_success
+  assert_output --partial "starting failing"
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "services with restart = 'on-failure' are restarted" {
+  add_service <<- 'TOML'
+	[services.flaky]
+	command = "echo run >> runs; if [ $(wc -l < runs) -ge 2 ]; then sleep 1000; fi; exit 1"
+	ready-check = "[ $(wc -l < runs) -ge 2 ]"
+	restart = "on-failure"
+	TOML
+
+  run "$FLOX_BIN" services start
+  assert_success
+  run "$FLOX_BIN" services status
+  assert_output --regexp "flaky +running"
+}
+
+# ---------------------------------------------------------------------------- #
+
+@test "'flox services' rejects unknown services" {
+  add_service <<- 'TOML'
+	[services.sleeper]
+	command = "sleep 1000"
+	TOML
+
+  run "$FLOX_BIN" services start unknown
+  assert_failure
+  assert_output --partial "Service 'unknown' is not defined in the manifest"
+}
+
+# ---------------------------------------------------------------------------- #
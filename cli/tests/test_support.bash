@@ -152,6 +152,28 @@ common_test_teardown() {
 
 teardown() { common_test_teardown; }
 
+# ---------------------------------------------------------------------------- #
+
+# project_setup
+# -------------
+# Create an empty `PROJECT_DIR' for the current test and `cd' into it.
+# Test files that need a different layout may redefine this, and are expected
+# to call `project_setup' from `setup' and `project_teardown' from `teardown'.
+project_setup() {
+  export PROJECT_DIR="${BATS_TEST_TMPDIR?}/project-${BATS_TEST_NUMBER?}"
+  rm -rf "$PROJECT_DIR"
+  mkdir -p "$PROJECT_DIR"
+  pushd "$PROJECT_DIR" > /dev/null || return
+}
+
+project_teardown() {
+  popd > /dev/null || return
+  rm -rf "${PROJECT_DIR?}"
+  unset PROJECT_DIR
+}
+
+# ---------------------------------------------------------------------------- #
+
 # Get a system different from the current one.
 get_system_other_than_current() {
   # replace linux with darwin or darwin with linux
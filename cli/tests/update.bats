@@ -47,6 +47,8 @@ teardown() {
 
   _PKGDB_GA_REGISTRY_REF_OR_REV="${PKGDB_NIXPKGS_REV_OLD?}" \
     run "$FLOX_BIN" update
+  assert_success
+  assert_output --partial "Generated database for input 'nixpkgs'."
   run jq -r '.registry.inputs.nixpkgs.from.narHash' .flox/env/manifest.lock
   assert_success
   assert_output "$PKGDB_NIXPKGS_NAR_HASH_OLD"
@@ -663,8 +663,9 @@ public:
    *
    * If the user has explicitly defined a list of subtrees, then simply use
    * that list.
-   * If the list is undefined, pick the first of:
-   *   1. "package"
+   * If the list is undefined, use every subtree the flake provides in the
+   * order:
+   *   1. "packages"
    *   2. "legacyPackages"
    */
   [[nodiscard]] const std::vector<Subtree> &
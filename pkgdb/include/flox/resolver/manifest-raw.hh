@@ -304,9 +304,46 @@ struct ProfileScriptsRaw
 
   /** @brief A script intended to be sourced only in Zsh shells. */
   std::optional<std::string> zsh;
+
+  /** @brief A script intended to be sourced only in Fish shells. */
+  std::optional<std::string> fish;
 };
 
 
+/* -------------------------------------------------------------------------- */
+
+/** @brief Declares a long running process started by `flox services start`. */
+struct ServiceRaw
+{
+  /** @brief A shell command starting the service in the foreground. */
+  std::string command;
+
+  /** @brief Variables set for the service, in addition to `vars`. */
+  std::optional<std::unordered_map<std::string, std::string>> vars;
+
+  /** @brief A shell command that succeeds once the service is ready. */
+  std::optional<std::string> readyCheck;
+
+  /**
+   * @brief Whether the service is restarted when it exits,
+   *        one of `never`, `on-failure` or `always`.
+   */
+  std::optional<std::string> restart;
+
+
+  /**
+   * @brief Validate `Service` fields, throwing an exception if its contents
+   *        are invalid.
+   *
+   * @param name The name of the service in the `services` table.
+   */
+  void
+  check( const std::string & name ) const;
+
+
+}; /* End struct `ServiceRaw' */
+
+
 /* -------------------------------------------------------------------------- */
 
 /**
@@ -333,6 +370,8 @@ struct ManifestRaw : public GlobalManifestRaw
 
   std::optional<HookRaw> hook;
 
+  std::optional<std::unordered_map<std::string, ServiceRaw>> services;
+
 
   ~ManifestRaw() override            = default;
   ManifestRaw()                      = default;
@@ -378,6 +417,7 @@ struct ManifestRaw : public GlobalManifestRaw
    * - @a registry does not contain indirect flake references.
    * - All members of @a install are valid.
    * - @a hook is valid.
+   * - All members of @a services are valid.
    */
   void
   check() const override;
@@ -392,8 +432,9 @@ struct ManifestRaw : public GlobalManifestRaw
     this->envBase = std::nullopt;
     this->install = std::nullopt;
     this->vars    = std::nullopt;
-    this->hook    = std::nullopt;
-    this->profile = std::nullopt;
+    this->hook     = std::nullopt;
+    this->profile  = std::nullopt;
+    this->services = std::nullopt;
   }
 
   /**
@@ -523,6 +564,8 @@ struct ManifestRawGA : public GlobalManifestRawGA
 
   std::optional<HookRaw> hook;
 
+  std::optional<std::unordered_map<std::string, ServiceRaw>> services;
+
 
   ~ManifestRawGA() override              = default;
   ManifestRawGA()                        = default;
@@ -566,6 +609,7 @@ struct ManifestRawGA : public GlobalManifestRawGA
    * This asserts:
    * - All members of @a install are valid.
    * - @a hook is valid.
+   * - All members of @a services are valid.
    */
   void
   check() const override;
@@ -576,10 +620,11 @@ struct ManifestRawGA : public GlobalManifestRawGA
     /* From `GlobalManifestRawGA' */
     this->options = std::nullopt;
     /* From `ManifestRawGA' */
-    this->install = std::nullopt;
-    this->vars    = std::nullopt;
-    this->profile = std::nullopt;
-    this->hook    = std::nullopt;
+    this->install  = std::nullopt;
+    this->vars     = std::nullopt;
+    this->profile  = std::nullopt;
+    this->hook     = std::nullopt;
+    this->services = std::nullopt;
   }
 
   /**
@@ -599,6 +644,7 @@ struct ManifestRawGA : public GlobalManifestRawGA
     raw.vars     = this->vars;
     raw.profile  = this->profile;
     raw.hook     = this->hook;
+    raw.services = this->services;
     return raw;
   }
 
@@ -368,9 +368,9 @@ protected:
 
   /**
    * @brief Initialize the @a manifest member variable.
-   *        When `--ga-registry` is set it enforces a GA compliant manifest by
-   *        disallowing `registry` in its input,
-   *        and injects a hard coded `registry`.
+   *        When `--ga-registry` is set it injects the inputs of a hard coded
+   *        `registry`, and only allows the manifest's `registry` to declare
+   *        additional inputs.
    */
   [[nodiscard]] EnvironmentManifest
   initManifest( ManifestRaw manifestRaw ) override;
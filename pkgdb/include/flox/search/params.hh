@@ -65,6 +65,27 @@ struct SearchQuery
   /** Filter results by partial match on pname or '.' joined relPath. */
   std::optional<std::string> partialNameOrRelPathMatch;
 
+  /**
+   * Filter results to those with one of the given licenses.
+   * Overrides the manifest's `options.allow.licenses`.
+   */
+  std::optional<std::vector<std::string>> licenses;
+
+  /**
+   * Whether to include packages which are explicitly marked `unfree`.
+   * Overrides the manifest's `options.allow.unfree`.
+   */
+  std::optional<bool> allowUnfree;
+
+  /**
+   * Whether to include packages which are explicitly marked `broken`.
+   * Overrides the manifest's `options.allow.broken`.
+   */
+  std::optional<bool> allowBroken;
+
+  /** Systems to search instead of the manifest's `options.systems`. */
+  std::optional<std::vector<System>> systems;
+
   /** @brief Reset to default state. */
   void
   clear();
@@ -0,0 +1,33 @@
+
+# Tweak the (already customized) prompt: add a flox indicator.
+
+# Wrap the user's `fish_prompt' once; the indicator is rendered on every
+# prompt, so nested activations only need to update FLOX_PROMPT_ENVIRONMENTS.
+if not functions -q _flox_saved_fish_prompt
+    if functions -q fish_prompt
+        functions -c fish_prompt _flox_saved_fish_prompt
+    else
+        function _flox_saved_fish_prompt
+        end
+    end
+
+    function fish_prompt
+        # `flox deactivate` may have left no environment to show
+        if test -n "$FLOX_PROMPT_ENVIRONMENTS"
+            set -l _floxPrompt1 flox
+            if set -q FLOX_PROMPT
+                set _floxPrompt1 $FLOX_PROMPT
+            end
+            set -l _floxPrompt2 "[$FLOX_PROMPT_ENVIRONMENTS]"
+
+            if test -z "$NO_COLOR" -o "$NO_COLOR" = 0
+                printf '\e[1;38;5;%sm%s\e[0m \e[38;5;%sm%s\e[0m ' \
+                    $FLOX_PROMPT_COLOR_1 $_floxPrompt1 \
+                    $FLOX_PROMPT_COLOR_2 $_floxPrompt2
+            else
+                printf '%s %s ' $_floxPrompt1 $_floxPrompt2
+            end
+        end
+        _flox_saved_fish_prompt
+    end
+end
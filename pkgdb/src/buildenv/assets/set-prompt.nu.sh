@@ -0,0 +1,27 @@
+# Tweak the (already customized) prompt: add a flox indicator.
+#
+# The original prompt is saved once, the indicator is rendered on every
+# prompt, so nested activations only need to update FLOX_PROMPT_ENVIRONMENTS.
+
+$env.FLOX_SAVED_PROMPT_COMMAND = (
+    $env.FLOX_SAVED_PROMPT_COMMAND? | default ($env.PROMPT_COMMAND? | default "")
+)
+
+$env.PROMPT_COMMAND = {||
+    let floxPrompt1 = ($env.FLOX_PROMPT? | default "flox")
+    let floxPrompt2 = $"[($env.FLOX_PROMPT_ENVIRONMENTS? | default "")]"
+    let flox = if ($env.FLOX_PROMPT_ENVIRONMENTS? | default "" | is-empty) {
+        # `flox deactivate` may have left no environment to show
+        ""
+    } else if ($env.NO_COLOR? | default "0") == "0" {
+        let color1 = (ansi --escape $"1;38;5;($env.FLOX_PROMPT_COLOR_1)m")
+        let color2 = (ansi --escape $"38;5;($env.FLOX_PROMPT_COLOR_2)m")
+        $"($color1)($floxPrompt1)(ansi reset) ($color2)($floxPrompt2)(ansi reset) "
+    } else {
+        $"($floxPrompt1) ($floxPrompt2) "
+    }
+
+    let saved = $env.FLOX_SAVED_PROMPT_COMMAND
+    let original = if ($saved | describe) == "closure" { do $saved } else { $saved }
+    $"($flox)($original)"
+}
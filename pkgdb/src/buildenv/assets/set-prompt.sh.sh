@@ -0,0 +1,23 @@
+# Tweak the (already customized) prompt: add a flox indicator.
+#
+# This script is sourced by POSIX shells, e.g. dash or busybox ash,
+# so it avoids bash and zsh specific syntax.
+
+_floxPrompt1="${FLOX_PROMPT-flox}"
+_floxPrompt2="[$FLOX_PROMPT_ENVIRONMENTS]"
+
+if [ "${NO_COLOR:-0}" = "0" ]; then
+  _esc="$(printf '\033')["
+  _floxPrompt1="${_esc}1;38;5;${FLOX_PROMPT_COLOR_1}m${_floxPrompt1}${_esc}0m"
+  _floxPrompt2="${_esc}38;5;${FLOX_PROMPT_COLOR_2}m${_floxPrompt2}${_esc}0m"
+fi
+
+if [ -n "${PS1:-}" ]; then
+  # Start by saving the original value of PS1.
+  if [ -z "${FLOX_SAVE_PS1:-}" ]; then
+    export FLOX_SAVE_PS1="$PS1"
+  fi
+  PS1="$_floxPrompt1 $_floxPrompt2 $FLOX_SAVE_PS1"
+fi
+
+unset _esc _floxPrompt1 _floxPrompt2
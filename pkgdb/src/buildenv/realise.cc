@@ -51,6 +51,18 @@ namespace flox::buildenv {
 #  error "SET_PROMPT_ZSH_SH must be set to the path of `set-prompt.zsh.sh'"
 #endif
 
+#ifndef SET_PROMPT_FISH_SH
+#  error "SET_PROMPT_FISH_SH must be set to the path of `set-prompt.fish.sh'"
+#endif
+
+#ifndef SET_PROMPT_SH_SH
+#  error "SET_PROMPT_SH_SH must be set to the path of `set-prompt.sh.sh'"
+#endif
+
+#ifndef SET_PROMPT_NU_SH
+#  error "SET_PROMPT_NU_SH must be set to the path of `set-prompt.nu.sh'"
+#endif
+
 #ifndef CONTAINER_BUILDER_PATH
 #  error \
     "CONTAINER_BUILDER_PATH must be set to a store path of 'mkContainer.nix'"
@@ -113,6 +125,109 @@ setopt nohashdirs
 )";
 
 
+/* Print the exported variables of bash as NUL separated `NAME=value' pairs,
+ * using only builtins, as `env -0' isn't supported by every `env' and `env'
+ * would be looked up in the user's `PATH'. */
+#define FLOX_BASH_DUMP_ENV                                                  \
+  "dump_env() { while IFS= read -r _flox_var; do "                          \
+  "printf \"%s=%s\\0\" \"$_flox_var\" \"${!_flox_var}\"; done < <(compgen -e); }"
+
+
+/* fish can't source the POSIX `profile.d' scripts (nor `profile.common' and
+ * `hook.script'), so they are sourced by bash and the resulting environment is
+ * imported into fish.  Variables ending in `PATH' are split on colons by fish.
+ * Like zsh, fish activation calls this script after reading the user's
+ * configuration, and fish doesn't hash commands. */
+const char * const FISH_ACTIVATE_SCRIPT = R"(
+function _flox_source_posix_scripts
+    # The environment is written to a file so the scripts' output is kept.
+    set -l _flox_env_file (mktemp)
+    )" FLOX_BASH_BIN R"( -c '
+        )" FLOX_BASH_DUMP_ENV R"(
+        env_file="$1"; shift
+        for p in "$@"; do [ -f "$p" ] && . "$p"; done
+        unset FLOX_PATH_PATCHED
+        dump_env > "$env_file"' bash $_flox_env_file $argv
+    for _flox_var in (string split0 < $_flox_env_file)
+        set -l _flox_kv (string split -m 1 = -- $_flox_var)
+        if contains -- $_flox_kv[1] _ PWD SHLVL
+            continue
+        end
+        set -gx $_flox_kv[1] $_flox_kv[2]
+    end
+    rm -f $_flox_env_file
+    set -e FLOX_PATH_PATCHED
+end
+
+set -l _prof_scripts $FLOX_ENV/etc/profile.d/*.sh
+if set -q _prof_scripts[1]
+    _flox_source_posix_scripts $_prof_scripts
+end
+)";
+
+
+/* Interactive POSIX shells are started with `ENV' pointing to this script,
+ * the user's original `ENV' is passed as `FLOX_ORIG_ENV'.
+ * POSIX shells have no way to disable command hashing, but `dash' and
+ * busybox `ash' rehash when `PATH' changes. */
+const char * const SH_ACTIVATE_SCRIPT = R"(
+if [ -n "${FLOX_ORIG_ENV+x}" ]; then
+  if [ -n "$FLOX_ORIG_ENV" ]; then
+    ENV="$FLOX_ORIG_ENV"
+    if [ -f "$ENV" ]; then . "$ENV"; fi
+  else
+    unset ENV
+  fi
+  unset FLOX_ORIG_ENV
+fi
+
+for _flox_prof_script in "$FLOX_ENV/etc/profile.d"/*.sh; do
+  if [ -f "$_flox_prof_script" ]; then . "$_flox_prof_script"; fi
+done
+unset _flox_prof_script
+)";
+
+
+/* Like fish, nushell can't source the POSIX `profile.d' scripts,
+ * so they are sourced by bash and the changed variables are loaded into nushell.
+ * Only changed variables are loaded, as nushell keeps some variables, e.g.
+ * `PATH' and `NU_LIB_DIRS', as lists, which are passed to bash as strings.
+ * nushell resolves `source' at parse time, so the activation script can't
+ * source scripts relative to `$env.FLOX_ENV' but inlines them instead. */
+const char * const NU_ACTIVATE_SCRIPT = R"(
+def --env _flox_source_posix_scripts [...scripts: string] {
+    let before = (mktemp -t)
+    let after = (mktemp -t)
+    ^)" FLOX_BASH_BIN R"( -c '
+        )" FLOX_BASH_DUMP_ENV R"(
+        before="$1"; after="$2"; shift 2
+        dump_env > "$before"
+        for p in "$@"; do [ -f "$p" ] && . "$p"; done
+        unset FLOX_PATH_PATCHED
+        dump_env > "$after"' bash $before $after ...$scripts
+    let parse_env = {|file|
+        open --raw $file
+        | split row (char nul)
+        | where $it != ""
+        | parse --regex '(?s)^(?P<name>[^=]+)=(?P<value>.*)$'
+    }
+    let previous = (do $parse_env $before)
+    let changed = (do $parse_env $after
+        | where name not-in [_ PWD OLDPWD SHLVL]
+        | where {|var| not ($var in $previous) }
+        | reduce --fold {} {|var, acc| $acc | upsert $var.name $var.value })
+    rm -f $before $after
+    load-env $changed
+    if "PATH" in $changed {
+        $env.PATH = ($env.PATH | split row (char esep))
+    }
+    hide-env --ignore-errors FLOX_PATH_PATCHED
+}
+
+_flox_source_posix_scripts ...(glob $"($env.FLOX_ENV)/etc/profile.d/*.sh" | sort)
+)";
+
+
 /* -------------------------------------------------------------------------- */
 
 static nix::StorePath
@@ -685,6 +800,84 @@ appendBashCalledScript( const std::string & scriptName,
              << activationScriptEnvironmentPath( scriptName ) << '\n';
 }
 
+/** Append a POSIX script to the fish activation script, sourced by bash. */
+void
+appendPosixSourcedScript( const std::string & scriptName,
+                          std::stringstream & mainScript )
+{
+  mainScript << "_flox_source_posix_scripts "
+             << activationScriptEnvironmentPath( scriptName ) << '\n';
+}
+
+/**
+ * Single quote a value for fish.
+ * Unlike POSIX shells, fish allows escaping `\' and `'' inside single quotes.
+ */
+static std::string
+escapeFishArg( const std::string & value )
+{
+  std::string escaped = "'";
+  for ( const char chr : value )
+    {
+      if ( ( chr == '\\' ) || ( chr == '\'' ) ) { escaped.push_back( '\\' ); }
+      escaped.push_back( chr );
+    }
+  escaped.push_back( '\'' );
+  return escaped;
+}
+
+/** Append a POSIX script to a POSIX sh activation script using `.'. */
+void
+appendDotSourcedScript( const std::string & scriptName,
+                        std::stringstream & mainScript )
+{
+  mainScript << ". " << activationScriptEnvironmentPath( scriptName ) << '\n';
+}
+
+/** Path to an activation script in nushell syntax. */
+static std::string
+nuActivationScriptEnvironmentPath( const std::string & scriptName )
+{
+  return nix::fmt( "$\"($env.FLOX_ENV)/%s/%s\"",
+                   ACTIVATION_SUBDIR_NAME,
+                   scriptName );
+}
+
+/** Append a POSIX script to the nushell activation script, sourced by bash. */
+void
+appendNuPosixSourcedScript( const std::string & scriptName,
+                            std::stringstream & mainScript )
+{
+  mainScript << "_flox_source_posix_scripts "
+             << nuActivationScriptEnvironmentPath( scriptName ) << '\n';
+}
+
+/** Append a script to the nushell activation script, called by bash. */
+void
+appendNuBashCalledScript( const std::string & scriptName,
+                          std::stringstream & mainScript )
+{
+  mainScript << "^" << FLOX_BASH_BIN << " "
+             << nuActivationScriptEnvironmentPath( scriptName ) << '\n';
+}
+
+/**
+ * Double quote a value for nushell.
+ * Double quoted strings are not interpolated, but `\' and `"' are escaped.
+ */
+static std::string
+escapeNuArg( const std::string & value )
+{
+  std::string escaped = "\"";
+  for ( const char chr : value )
+    {
+      if ( ( chr == '\\' ) || ( chr == '"' ) ) { escaped.push_back( '\\' ); }
+      escaped.push_back( chr );
+    }
+  escaped.push_back( '"' );
+  return escaped;
+}
+
 
 /* -------------------------------------------------------------------------- */
 
@@ -698,21 +891,35 @@ makeActivationScripts( nix::EvalState & state, resolver::Lockfile & lockfile )
   /* Create the shell-specific activation scripts */
   std::stringstream bashScript;
   std::stringstream zshScript;
+  std::stringstream fishScript;
+  std::stringstream shScript;
+  std::stringstream nuScript;
 
   /* Add the preambles */
   bashScript << BASH_ACTIVATE_SCRIPT << "\n";
   bashScript << "source " << SET_PROMPT_BASH_SH << "\n";
   zshScript << ZSH_ACTIVATE_SCRIPT << "\n";
   zshScript << "source " << SET_PROMPT_ZSH_SH << "\n";
+  fishScript << FISH_ACTIVATE_SCRIPT << "\n";
+  fishScript << "source " << SET_PROMPT_FISH_SH << "\n";
+  shScript << SH_ACTIVATE_SCRIPT << "\n";
+  shScript << ". " << SET_PROMPT_SH_SH << "\n";
+  nuScript << NU_ACTIVATE_SCRIPT << "\n";
+  nuScript << "source " << escapeNuArg( SET_PROMPT_NU_SH ) << "\n";
 
   auto manifest = lockfile.getManifest().getManifestRaw();
 
   /* Add environment variables. */
   if ( auto vars = manifest.vars )
     {
-
       for ( auto [name, value] : vars.value() )
         {
+          /* fish allows escaping quotes within single quotes. */
+          fishScript << nix::fmt( "set -gx %s %s\n",
+                                  name,
+                                  escapeFishArg( value ) );
+          nuScript << nix::fmt( "$env.%s = %s\n", name, escapeNuArg( value ) );
+
           /* Single quote value and replace ' with '\''.
            *
            * This is the same as what nixpkgs.lib.escapeShellArg does.
@@ -729,6 +936,7 @@ makeActivationScripts( nix::EvalState & state, resolver::Lockfile & lockfile )
 
           bashScript << nix::fmt( "export %s='%s'\n", name, value );
           zshScript << nix::fmt( "export %s='%s'\n", name, value );
+          shScript << nix::fmt( "export %s='%s'\n", name, value );
         }
     }
 
@@ -742,6 +950,9 @@ makeActivationScripts( nix::EvalState & state, resolver::Lockfile & lockfile )
           addScriptToScriptsDir( *profile->common, tempDir, "profile-common" );
           appendSourcedScript( "profile-common", bashScript );
           appendSourcedScript( "profile-common", zshScript );
+          appendPosixSourcedScript( "profile-common", fishScript );
+          appendDotSourcedScript( "profile-common", shScript );
+          appendNuPosixSourcedScript( "profile-common", nuScript );
         }
       if ( profile->bash.has_value() )
         {
@@ -755,6 +966,12 @@ makeActivationScripts( nix::EvalState & state, resolver::Lockfile & lockfile )
           addScriptToScriptsDir( *profile->zsh, tempDir, "profile-zsh" );
           appendSourcedScript( "profile-zsh", zshScript );
         }
+      if ( profile->fish.has_value() )
+        {
+          debugLog( "adding 'profile.fish' to activation scripts" );
+          addScriptToScriptsDir( *profile->fish, tempDir, "profile-fish" );
+          appendSourcedScript( "profile-fish", fishScript );
+        }
     }
 
   /* Add 'on-activate' script. */
@@ -770,6 +987,9 @@ makeActivationScripts( nix::EvalState & state, resolver::Lockfile & lockfile )
           addScriptToScriptsDir( *hook->script, tempDir, "hook-script" );
           appendSourcedScript( "hook-script", bashScript );
           appendSourcedScript( "hook-script", zshScript );
+          appendPosixSourcedScript( "hook-script", fishScript );
+          appendDotSourcedScript( "hook-script", shScript );
+          appendNuPosixSourcedScript( "hook-script", nuScript );
         }
 
       if ( hook->onActivate.has_value() )
@@ -780,12 +1000,18 @@ makeActivationScripts( nix::EvalState & state, resolver::Lockfile & lockfile )
                                  "hook-on-activate" );
           appendBashCalledScript( "hook-on-activate", bashScript );
           appendBashCalledScript( "hook-on-activate", zshScript );
+          appendBashCalledScript( "hook-on-activate", fishScript );
+          appendBashCalledScript( "hook-on-activate", shScript );
+          appendNuBashCalledScript( "hook-on-activate", nuScript );
         }
     }
 
   /* Add the shell-specific scripts to the scripts directory */
   addScriptToScriptsDir( bashScript.str(), tempDir, "bash" );
   addScriptToScriptsDir( zshScript.str(), tempDir, "zsh" );
+  addScriptToScriptsDir( fishScript.str(), tempDir, "fish" );
+  addScriptToScriptsDir( shScript.str(), tempDir, "sh" );
+  addScriptToScriptsDir( nuScript.str(), tempDir, "nu" );
 
   debugLog( "adding activation scripts to store" );
   auto activationStorePath
@@ -798,6 +1024,9 @@ makeActivationScripts( nix::EvalState & state, resolver::Lockfile & lockfile )
   references.insert( activationStorePath );
   references.insert( state.store->parseStorePath( SET_PROMPT_BASH_SH ) );
   references.insert( state.store->parseStorePath( SET_PROMPT_ZSH_SH ) );
+  references.insert( state.store->parseStorePath( SET_PROMPT_FISH_SH ) );
+  references.insert( state.store->parseStorePath( SET_PROMPT_SH_SH ) );
+  references.insert( state.store->parseStorePath( SET_PROMPT_NU_SH ) );
 
 
   return { realised, references };
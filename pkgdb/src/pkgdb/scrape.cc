@@ -8,6 +8,9 @@
  * -------------------------------------------------------------------------- */
 
 #include <iostream>
+#include <string>
+
+#include <nix/globals.hh>
 
 #include "flox/pkgdb/command.hh"
 
@@ -60,20 +63,42 @@ ScrapeCommand::initInput()
 int
 ScrapeCommand::run()
 {
-  this->fixupAttrPath();
-  this->initInput();
-  assert( this->input.has_value() );
-
-  /* If `--force' was given, clear the `done' fields for the prefix and its
-   * descendants to force them to re-evaluate. */
-  if ( this->force )
+  /* Without an attribute path, scrape every subtree the flake provides. */
+  if ( this->attrPath.empty() )
     {
-      this->input->getDbReadWrite()->setPrefixDone( this->attrPath, false );
-      this->input->closeDbReadWrite();
+      this->initInput();
+      assert( this->input.has_value() );
+      std::string system = nix::settings.thisSystem.get();
+      if ( this->force )
+        {
+          for ( const auto & subtree : this->input->getSubtrees() )
+            {
+              this->input->getDbReadWrite()->setPrefixDone(
+                { static_cast<std::string>( to_string( subtree ) ), system },
+                false );
+            }
+          this->input->closeDbReadWrite();
+        }
+      this->input->scrapeSystems( { system } );
+    }
+  else
+    {
+      this->fixupAttrPath();
+      this->initInput();
+      assert( this->input.has_value() );
+
+      /* If `--force' was given, clear the `done' fields for the prefix and
+       * its descendants to force them to re-evaluate. */
+      if ( this->force )
+        {
+          this->input->getDbReadWrite()->setPrefixDone( this->attrPath,
+                                                        false );
+          this->input->closeDbReadWrite();
+        }
+
+      /* scrape it up! */
+      this->input->scrapePrefix( this->attrPath );
     }
-
-  /* scrape it up! */
-  this->input->scrapePrefix( this->attrPath );
 
   /* Print path to database. */
   std::cout << nlohmann::json(
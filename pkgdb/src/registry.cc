@@ -292,15 +292,16 @@ FloxFlakeInput::getSubtrees()
           try
             {
               auto root = this->getFlake()->openEvalCache()->getRoot();
+              std::vector<Subtree> subtrees;
               if ( root->maybeGetAttr( "packages" ) != nullptr )
                 {
-                  this->enabledSubtrees = std::vector<Subtree> { ST_PACKAGES };
+                  subtrees.emplace_back( ST_PACKAGES );
                 }
-              else if ( root->maybeGetAttr( "legacyPackages" ) != nullptr )
+              if ( root->maybeGetAttr( "legacyPackages" ) != nullptr )
                 {
-                  this->enabledSubtrees = std::vector<Subtree> { ST_LEGACY };
+                  subtrees.emplace_back( ST_LEGACY );
                 }
-              else { this->enabledSubtrees = std::vector<Subtree> {}; }
+              this->enabledSubtrees = std::move( subtrees );
             }
           catch ( const nix::EvalError & err )
             {
@@ -17,6 +17,8 @@
 #include <utility>
 #include <vector>
 
+#include <nix/attrs.hh>
+#include <nix/fetchers.hh>
 #include <nix/flake/flakeref.hh>
 #include <nix/logging.hh>
 #include <nix/ref.hh>
@@ -361,6 +363,31 @@ Environment::getCombinedBaseQueryArgs()
 }
 
 
+/* -------------------------------------------------------------------------- */
+
+/**
+ * @brief Helper function for
+ *        @a flox::resolver::Environment::tryResolveDescriptorIn.
+ *
+ * A descriptor naming a registry input in its `package-repository`,
+ * which parses as an _indirect_ flake reference, is only resolved in
+ * that input.
+ */
+[[nodiscard]] static bool
+inputSkipped( const ManifestDescriptor & descriptor,
+              const pkgdb::PkgDbInput &  input )
+{
+  if ( ! descriptor.input.has_value()
+       || ( descriptor.input->input.getType() != "indirect" ) )
+    {
+      return false;
+    }
+  auto name
+    = nix::fetchers::maybeGetStrAttr( descriptor.input->input.attrs, "id" );
+  return name != input.getName();
+}
+
+
 /* -------------------------------------------------------------------------- */
 
 std::optional<pkgdb::row_id>
@@ -386,6 +413,11 @@ Environment::tryResolveDescriptorIn( const ManifestDescriptor & descriptor,
     {
       return std::nullopt;
     }
+  if ( inputSkipped( descriptor, input ) )
+    {
+      debugLog( "skipping input not requested by descriptor" );
+      return std::nullopt;
+    }
 
   pkgdb::PkgQueryArgs args = this->getCombinedBaseQueryArgs();
   input.fillPkgQueryArgs( args );
@@ -475,6 +475,7 @@ from_json( const nlohmann::json & jfrom, ProfileScriptsRaw & profile )
   profile.common = std::nullopt;
   profile.bash   = std::nullopt;
   profile.zsh    = std::nullopt;
+  profile.fish   = std::nullopt;
 
   /* Iterate over keys of the JSON object */
   for ( const auto & [key, value] : jfrom.items() )
@@ -518,6 +519,19 @@ from_json( const nlohmann::json & jfrom, ProfileScriptsRaw & profile )
                 + value.dump() );
             }
         }
+      else if ( key == "fish" )
+        {
+          try
+            {
+              value.get_to( profile.fish );
+            }
+          catch ( const nlohmann::json::exception & )
+            {
+              throw InvalidManifestFileException(
+                "failed to parse manifest field 'profile.fish' with value: "
+                + value.dump() );
+            }
+        }
       else
         {
           throw InvalidManifestFileException(
@@ -534,6 +548,7 @@ to_json( nlohmann::json & jto, const ProfileScriptsRaw & profile )
   if ( profile.common.has_value() ) { jto["common"] = profile.common.value(); }
   if ( profile.bash.has_value() ) { jto["bash"] = profile.bash.value(); }
   if ( profile.zsh.has_value() ) { jto["zsh"] = profile.zsh.value(); }
+  if ( profile.fish.has_value() ) { jto["fish"] = profile.fish.value(); }
 }
 
 
@@ -615,6 +630,96 @@ HookRaw::check() const
 }
 
 
+/* -------------------------------------------------------------------------- */
+
+static ServiceRaw
+serviceFromJSON( const std::string & name, const nlohmann::json & jfrom )
+{
+  assertIsJSONObject<InvalidManifestFileException>(
+    jfrom,
+    "manifest field 'services." + name + "'" );
+
+  ServiceRaw service;
+  for ( const auto & [key, value] : jfrom.items() )
+    {
+      try
+        {
+          if ( key == "command" ) { value.get_to( service.command ); }
+          else if ( key == "vars" ) { value.get_to( service.vars ); }
+          else if ( key == "ready-check" )
+            {
+              value.get_to( service.readyCheck );
+            }
+          else if ( key == "restart" ) { value.get_to( service.restart ); }
+          else
+            {
+              throw InvalidManifestFileException(
+                "unrecognized manifest field 'services." + name + "." + key
+                + "'." );
+            }
+        }
+      catch ( const nlohmann::json::exception & )
+        {
+          throw InvalidManifestFileException(
+            "failed to parse manifest field 'services." + name + "." + key
+            + "' with value: " + value.dump() );
+        }
+    }
+
+  service.check( name );
+  return service;
+}
+
+
+static std::unordered_map<std::string, ServiceRaw>
+servicesFromJSON( const nlohmann::json & jfrom )
+{
+  assertIsJSONObject<InvalidManifestFileException>(
+    jfrom,
+    "manifest field 'services'" );
+  std::unordered_map<std::string, ServiceRaw> services;
+  for ( const auto & [name, value] : jfrom.items() )
+    {
+      services.emplace( name, serviceFromJSON( name, value ) );
+    }
+  return services;
+}
+
+
+static void
+to_json( nlohmann::json & jto, const ServiceRaw & service )
+{
+  jto = { { "command", service.command } };
+  if ( service.vars.has_value() ) { jto["vars"] = *service.vars; }
+  if ( service.readyCheck.has_value() )
+    {
+      jto["ready-check"] = *service.readyCheck;
+    }
+  if ( service.restart.has_value() ) { jto["restart"] = *service.restart; }
+}
+
+
+/* -------------------------------------------------------------------------- */
+
+void
+ServiceRaw::check( const std::string & name ) const
+{
+  if ( this->command.empty() )
+    {
+      throw InvalidManifestFileException( "service 'services." + name
+                                          + "' must define a 'command'." );
+    }
+  if ( this->restart.has_value() && ( *this->restart != "never" )
+       && ( *this->restart != "on-failure" ) && ( *this->restart != "always" ) )
+    {
+      throw InvalidManifestFileException(
+        "manifest field 'services." + name
+        + ".restart' must be one of \"never\", \"on-failure\" or "
+          "\"always\"." );
+    }
+}
+
+
 /* -------------------------------------------------------------------------- */
 
 static std::unordered_map<std::string, std::optional<ManifestDescriptorRaw>>
@@ -676,9 +781,10 @@ ManifestRaw::operator ManifestRawGA() const
 {
   ManifestRawGA raw( static_cast<GlobalManifestRawGA>(
     static_cast<GlobalManifestRaw>( *this ) ) );
-  raw.install = this->install;
-  raw.vars    = this->vars;
-  raw.hook    = this->hook;
+  raw.install  = this->install;
+  raw.vars     = this->vars;
+  raw.hook     = this->hook;
+  raw.services = this->services;
   return raw;
 }
 
@@ -713,6 +819,15 @@ from_json( const nlohmann::json & jfrom, ManifestRaw & manifest )
         }
       else if ( key == "profile" ) { value.get_to( manifest.profile ); }
       else if ( key == "hook" ) { value.get_to( manifest.hook ); }
+      else if ( key == "services" )
+        {
+          if ( value.is_null() )
+            {
+              manifest.services = std::nullopt;
+              continue;
+            }
+          manifest.services = servicesFromJSON( value );
+        }
       else if ( key == "options" ) { value.get_to( manifest.options ); }
       else if ( key == "env-base" ) { value.get_to( manifest.envBase ); }
       else
@@ -744,6 +859,11 @@ to_json( nlohmann::json & jto, const ManifestRaw & manifest )
   if ( manifest.profile.has_value() ) { jto["profile"] = *manifest.profile; }
 
   if ( manifest.hook.has_value() ) { jto["hook"] = *manifest.hook; }
+
+  if ( manifest.services.has_value() )
+    {
+      jto["services"] = *manifest.services;
+    }
 }
 
 
@@ -762,6 +882,13 @@ ManifestRaw::check() const
         }
     }
   if ( this->hook.has_value() ) { this->hook->check(); }
+  if ( this->services.has_value() )
+    {
+      for ( const auto & [name, service] : *this->services )
+        {
+          service.check( name );
+        }
+    }
   if ( this->registry.has_value() )
     {
       for ( const auto & [name, input] : this->registry->inputs )
@@ -844,6 +971,15 @@ from_json( const nlohmann::json & jfrom, ManifestRawGA & manifest )
           manifest.vars = varsFromJSON( value );
         }
       else if ( key == "hook" ) { value.get_to( manifest.hook ); }
+      else if ( key == "services" )
+        {
+          if ( value.is_null() )
+            {
+              manifest.services = std::nullopt;
+              continue;
+            }
+          manifest.services = servicesFromJSON( value );
+        }
       else if ( key == "options" ) { value.get_to( manifest.options ); }
       else
         {
@@ -868,6 +1004,11 @@ to_json( nlohmann::json & jto, const ManifestRawGA & manifest )
   if ( manifest.vars.has_value() ) { jto["vars"] = *manifest.vars; }
 
   if ( manifest.hook.has_value() ) { jto["hook"] = *manifest.hook; }
+
+  if ( manifest.services.has_value() )
+    {
+      jto["services"] = *manifest.services;
+    }
 }
 
 
@@ -894,6 +1035,13 @@ ManifestRawGA::check() const
         }
     }
   if ( this->hook.has_value() ) { this->hook->check(); }
+  if ( this->services.has_value() )
+    {
+      for ( const auto & [name, service] : *this->services )
+        {
+          service.check( name );
+        }
+    }
 }
 
 
@@ -7,6 +7,7 @@
  *
  * -------------------------------------------------------------------------- */
 
+#include <algorithm>
 #include <filesystem>
 #include <optional>
 #include <string>
@@ -371,6 +372,46 @@ EnvironmentMixin::addFloxDirectoryOption( argparse::ArgumentParser & parser )
 
 /* ---------------- EnvironmentMixin init overrides ------------------------- */
 
+/**
+ * @brief Add the inputs of the GA registry to a manifest's registry.
+ *
+ * Manifests may declare inputs in addition to those of the GA registry,
+ * e.g. to install packages from a flake, but may not redefine them.
+ * The GA inputs take precedence over any additional inputs.
+ */
+[[nodiscard]] static RegistryRaw
+extendGARegistry( const RegistryRaw & registry )
+{
+  RegistryRaw extended = getGARegistry();
+  for ( const auto & [name, input] : registry.inputs )
+    {
+      if ( auto gaInput = extended.inputs.find( name );
+           gaInput != extended.inputs.end() )
+        {
+          if ( gaInput->second != input )
+            {
+              throw InvalidManifestFileException(
+                "manifest 'registry.inputs." + name
+                + "' does not match the GA registry" );
+            }
+        }
+      else { extended.inputs.emplace( name, input ); }
+    }
+  for ( const auto & name : registry.priority )
+    {
+      if ( std::find( extended.priority.begin(),
+                      extended.priority.end(),
+                      name )
+           == extended.priority.end() )
+        {
+          extended.priority.emplace_back( name );
+        }
+    }
+  extended.defaults.merge( registry.defaults );
+  return extended;
+}
+
+
 GlobalManifest
 GAEnvironmentMixin::initGlobalManifest( GlobalManifestRaw manifestRaw )
 {
@@ -393,12 +434,11 @@ GAEnvironmentMixin::initManifest( ManifestRaw manifestRaw )
 {
   if ( this->gaRegistry )
     {
-      (void) static_cast<ManifestRawGA>( manifestRaw );
-
-      if ( ! manifestRaw.registry.has_value() )
+      if ( manifestRaw.registry.has_value() )
         {
-          manifestRaw.registry = getGARegistry();
+          manifestRaw.registry = extendGARegistry( *manifestRaw.registry );
         }
+      else { manifestRaw.registry = getGARegistry(); }
     }
   return this->EnvironmentMixin::initManifest( manifestRaw );
 }
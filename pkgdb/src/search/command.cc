@@ -202,6 +202,16 @@ SearchCommand::run()
   auto query = pkgdb::PkgQuery( args );
   if ( this->dumpQuery ) { std::cout << query.str() << std::endl; }
 
+  /* Systems requested by the query may not be scraped yet. */
+  if ( this->params.query.systems.has_value() )
+    {
+      for ( const auto & [name, input] :
+            *this->getEnvironment().getPkgDbRegistry() )
+        {
+          input->scrapeSystems( *this->params.query.systems );
+        }
+    }
+
   /* Collect results from each input */
   auto                                            globalResultCount = 0;
   std::vector<std::vector<pkgdb::row_id>>         globallyFoundIds;
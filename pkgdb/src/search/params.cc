@@ -26,6 +26,10 @@ SearchQuery::clear()
   this->semver           = std::nullopt;
   this->partialMatch     = std::nullopt;
   this->partialNameMatch = std::nullopt;
+  this->licenses         = std::nullopt;
+  this->allowUnfree      = std::nullopt;
+  this->allowBroken      = std::nullopt;
+  this->systems          = std::nullopt;
 }
 
 
@@ -132,6 +136,16 @@ from_json( const nlohmann::json & jfrom, SearchQuery & qry )
         {
           getOrFail( key, value, qry.partialNameOrRelPathMatch );
         }
+      else if ( key == "licenses" ) { getOrFail( key, value, qry.licenses ); }
+      else if ( key == "allow-unfree" )
+        {
+          getOrFail( key, value, qry.allowUnfree );
+        }
+      else if ( key == "allow-broken" )
+        {
+          getOrFail( key, value, qry.allowBroken );
+        }
+      else if ( key == "systems" ) { getOrFail( key, value, qry.systems ); }
       else
         {
           throw ParseSearchQueryException( "unrecognized key 'query." + key
@@ -154,6 +168,10 @@ to_json( nlohmann::json & jto, const SearchQuery & qry )
   jto["match-name-or-rel-path"] = qry.partialNameOrRelPathMatch;
   jto["limit"]                  = qry.limit;
   jto["deduplicate"]            = qry.deduplicate;
+  jto["licenses"]               = qry.licenses;
+  jto["allow-unfree"]           = qry.allowUnfree;
+  jto["allow-broken"]           = qry.allowBroken;
+  jto["systems"]                = qry.systems;
 }
 
 
@@ -173,6 +191,11 @@ SearchQuery::fillPkgQueryArgs( pkgdb::PkgQueryArgs & pqa ) const
   pqa.partialNameOrRelPathMatch = this->partialNameOrRelPathMatch;
   pqa.limit                     = this->limit;
   pqa.deduplicate               = this->deduplicate;
+  /* Override global preferences only if set. */
+  if ( this->licenses.has_value() ) { pqa.licenses = this->licenses; }
+  if ( this->allowUnfree.has_value() ) { pqa.allowUnfree = *this->allowUnfree; }
+  if ( this->allowBroken.has_value() ) { pqa.allowBroken = *this->allowBroken; }
+  if ( this->systems.has_value() ) { pqa.systems = *this->systems; }
   return pqa;
 }
 
@@ -83,6 +83,9 @@ setup_file() {
   assert_success
   assert "$TEST" -f "$BATS_TEST_TMPDIR/env/activate/bash"
   assert "$TEST" -f "$BATS_TEST_TMPDIR/env/activate/zsh"
+  assert "$TEST" -f "$BATS_TEST_TMPDIR/env/activate/fish"
+  assert "$TEST" -f "$BATS_TEST_TMPDIR/env/activate/sh"
+  assert "$TEST" -f "$BATS_TEST_TMPDIR/env/activate/nu"
   assert "$TEST" -d "$BATS_TEST_TMPDIR/env/etc/profile.d"
 }
 
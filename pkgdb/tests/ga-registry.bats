@@ -75,9 +75,11 @@ setup_file() {
 
 # bats test_tags=search:ga-registry, manifest:ga-registry
 
-@test "'pkgdb search --ga-registry' disallows 'registry' in manifests" {
+@test "'pkgdb search --ga-registry' disallows redefining GA inputs in manifests" {
   run "$PKGDB_BIN" search --ga-registry "{
-    \"manifest\": { \"registry\": {} },
+    \"manifest\": { \"registry\": { \"inputs\": { \"nixpkgs\": {
+      \"from\": \"github:NixOS/nixpkgs/$NIXPKGS_REV_OLDER\"
+    } } } },
     \"query\": { \"pname\": \"hello\" }
   }"
   assert_failure
@@ -176,6 +178,76 @@ setup_file() {
   assert_failure
 }
 
+# ---------------------------------------------------------------------------- #
+
+# bats test_tags=manifest:ga-registry, lock:ga-registry
+
+# Packages naming an additional input in `package-repository' must be resolved
+# in that input rather than in the GA `nixpkgs' input.
+@test "'pkgdb manifest lock --ga-registry' allows additional registry inputs" {
+  cat > "$BATS_TEST_TMPDIR/manifest.toml" <<- EOF
+	[options]
+	systems = ["x86_64-linux"]
+
+	[install.nodejs]
+	package-repository = "older"
+
+	[registry.inputs.older]
+	from = "github:NixOS/nixpkgs/$NIXPKGS_REV_OLDER"
+	EOF
+
+  run --separate-stderr sh -c "$PKGDB_BIN manifest lock --ga-registry       \
+      --manifest '$BATS_TEST_TMPDIR/manifest.toml'                            \
+      |jq -r '.packages[\"x86_64-linux\"].nodejs.input.attrs.rev';"
+  assert_success
+  assert_output "$NIXPKGS_REV_OLDER"
+}
+
+# ---------------------------------------------------------------------------- #
+
+# bats test_tags=manifest:ga-registry, lock:ga-registry
+
+# Additional inputs may be flakes that only provide `packages'.
+# The subtrees detected when locking are recorded in the lockfile.
+@test "'pkgdb manifest lock --ga-registry' locks packages-only inputs" {
+  mkdir -p "$BATS_TEST_TMPDIR/tools"
+  cat > "$BATS_TEST_TMPDIR/tools/flake.nix" <<- EOF
+	{
+	  outputs = _: {
+	    packages.$NIX_SYSTEM.hello = builtins.derivation {
+	      name = "hello-1.0";
+	      system = "$NIX_SYSTEM";
+	      builder = "/bin/sh";
+	      args = [ "-c" "echo hello > \$out" ];
+	    };
+	  };
+	}
+	EOF
+
+  cat > "$BATS_TEST_TMPDIR/manifest.toml" <<- EOF
+	[options]
+	systems = ["$NIX_SYSTEM"]
+
+	[install.hello]
+	package-repository = "tools"
+
+	[registry.inputs.tools]
+	from = "path:$BATS_TEST_TMPDIR/tools"
+	EOF
+
+  run --separate-stderr "$PKGDB_BIN" manifest lock --ga-registry \
+    --manifest "$BATS_TEST_TMPDIR/manifest.toml"
+  assert_success
+  lockfile="$output"
+
+  run jq -c '.registry.inputs.tools.subtrees' <<< "$lockfile"
+  assert_output '["packages"]'
+  run jq -r ".packages[\"$NIX_SYSTEM\"].hello[\"attr-path\"] | join(\".\")" \
+    <<< "$lockfile"
+  assert_output "packages.$NIX_SYSTEM.hello"
+}
+
+
 # ---------------------------------------------------------------------------- #
 
 # bats test_tags=manifest:ga-registry, lock:ga-registry, manifest:registry-cmd
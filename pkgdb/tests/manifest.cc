@@ -435,6 +435,65 @@ test_parseManifestRawWithOnActivateScript()
 }
 
 
+/* -------------------------------------------------------------------------- */
+
+bool
+test_parseManifestRawWithServices()
+{
+  std::ifstream ifs( TEST_DATA_DIR "/manifest/services.toml" );
+
+  std::string toml( ( std::istreambuf_iterator<char>( ifs ) ),
+                    ( std::istreambuf_iterator<char>() ) );
+
+  flox::resolver::ManifestRaw manifest = flox::tomlToJSON( toml );
+  EXPECT( manifest.services.has_value() );
+
+  const auto & postgres = manifest.services->at( "postgres" );
+  EXPECT_EQ( *postgres.readyCheck, "pg_isready" );
+  EXPECT_EQ( *postgres.restart, "on-failure" );
+  EXPECT_EQ( postgres.vars->at( "PGPORT" ), "15432" );
+
+  const auto & worker = manifest.services->at( "worker" );
+  EXPECT_EQ( worker.command, "./worker" );
+  EXPECT( ! worker.restart.has_value() );
+
+  /* Services are kept when serializing, e.g. to the lockfile. */
+  nlohmann::json json = manifest;
+  EXPECT_EQ( json["services"]["postgres"]["ready-check"], "pg_isready" );
+  return true;
+}
+
+
+/* -------------------------------------------------------------------------- */
+
+bool
+test_serviceRequiresCommandAndKnownRestartPolicy()
+{
+  flox::resolver::ServiceRaw service;
+  try
+    {
+      service.check( "postgres" );
+      return false;
+    }
+  catch ( const flox::resolver::InvalidManifestFileException & e )
+    {}
+
+  service.command = "postgres";
+  service.restart = "sometimes";
+  try
+    {
+      service.check( "postgres" );
+      return false;
+    }
+  catch ( const flox::resolver::InvalidManifestFileException & e )
+    {}
+
+  service.restart = "always";
+  service.check( "postgres" );
+  return true;
+}
+
+
 /* -------------------------------------------------------------------------- */
 
 int
@@ -471,6 +530,9 @@ main()
   RUN_TEST( hookAllowsAtMostOneActivationHook );
   RUN_TEST( parseManifestRawWithOnActivateScript );
 
+  RUN_TEST( parseManifestRawWithServices );
+  RUN_TEST( serviceRequiresCommandAndKnownRestartPolicy );
+
   return exitCode;
 }
 
@@ -58,7 +58,8 @@ testLockfile()
     "profile": {
       "common": "echo hello",
       "bash": "echo hello",
-      "zsh": "echo hello"
+      "zsh": "echo hello",
+      "fish": "echo hello"
     },
     "hook": {
       "on-activate": "echo hello"
@@ -156,8 +157,10 @@ test_scriptsAreAddedToScriptsDir( nix::ref<nix::EvalState> & state,
   auto scriptsDir = std::filesystem::path( output.first.path )
                     / flox::buildenv::ACTIVATION_SUBDIR_NAME;
   std::vector<std::string> scripts
-    = { "profile-common",   "profile-bash", "profile-zsh",
-        "hook-on-activate", "bash",         "zsh" };
+    = { "profile-common", "profile-bash",     "profile-zsh",
+        "profile-fish",   "hook-on-activate", "bash",
+        "zsh",            "fish",             "sh",
+        "nu" };
   for ( const auto & script : scripts )
     {
       auto path = scriptsDir / script;
@@ -176,7 +179,7 @@ test_scriptsAreSourcedOrCalled( nix::ref<nix::EvalState> & state,
   auto output     = flox::buildenv::makeActivationScripts( *state, lockfile );
   auto scriptsDir = std::filesystem::path( output.first.path )
                     / flox::buildenv::ACTIVATION_SUBDIR_NAME;
-  std::vector<std::string> shells         = { "bash", "zsh" };
+  std::vector<std::string> shells         = { "bash", "zsh", "fish" };
   std::vector<std::string> profileScripts = { "common" };
   profileScripts.insert( profileScripts.begin(), shells.begin(), shells.end() );
   for ( const auto & shell : shells )
@@ -162,6 +162,34 @@ setup_file() {
   assert_output '0'
 }
 
+# ---------------------------------------------------------------------------- #
+
+# Without an attribute path every subtree the flake provides is scraped,
+# e.g. `packages' of a flake that doesn't provide `legacyPackages'.
+@test "pkgdb scrape <PACKAGES-ONLY-FLAKE>" {
+  mkdir -p "$BATS_TEST_TMPDIR/tools"
+  cat > "$BATS_TEST_TMPDIR/tools/flake.nix" <<- EOF
+	{
+	  outputs = _: {
+	    packages.$NIX_SYSTEM.hello = builtins.derivation {
+	      name = "hello-1.0";
+	      system = "$NIX_SYSTEM";
+	      builder = "/bin/sh";
+	      args = [ "-c" "echo hello > \$out" ];
+	    };
+	  };
+	}
+	EOF
+
+  run "$PKGDB_BIN" scrape --database "$BATS_TEST_TMPDIR/tools.sqlite" \
+    "path:$BATS_TEST_TMPDIR/tools"
+  assert_success
+  run sqlite3 "$BATS_TEST_TMPDIR/tools.sqlite" \
+    "SELECT pname FROM Packages WHERE attrName = 'hello'"
+  assert_output 'hello'
+}
+
+
 # ---------------------------------------------------------------------------- #
 #
 #
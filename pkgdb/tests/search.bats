@@ -301,6 +301,57 @@ genParamsNixpkgsFlox() {
   refute_output --partial '2 '
 }
 
+# bats test_tags=search:system, search:pname
+
+# `query.systems' overrides the manifest's systems
+@test "'pkgdb search' 'query.systems'" {
+  params="$(
+    genParams '.manifest.options.systems=["x86_64-linux"]
+               |.query.pname="hello"
+               |.query.systems=["x86_64-darwin"]'
+  )"
+
+  run sh -c "$PKGDB_BIN search '$params' | jq -r '.absPath[1]'"
+  assert_success
+  assert_output 'x86_64-darwin'
+}
+
+# ---------------------------------------------------------------------------- #
+
+# bats test_tags=search:name, search:license
+
+# `query.licenses' overrides the manifest's licenses
+@test "'pkgdb search' 'pname=blobs.gg & query.licenses=[Apache-2.0]'" {
+  params="$(
+    genParams '.query.pname|="blobs.gg"
+              |.manifest.options.allow.licenses|=["MIT"]
+              |.query.licenses|=["Apache-2.0"]'
+  )"
+  run sh -c "$PKGDB_BIN search '$params' | wc -l"
+  assert_success
+  assert_output 1
+}
+
+# ---------------------------------------------------------------------------- #
+
+# bats test_tags=search:unfree
+
+# `query.allow-unfree' overrides the manifest's preference
+@test "'pkgdb search' 'query.allow-unfree=false'" {
+  params_manifest="$(genParams '.manifest.options.allow.unfree=false')"
+  run sh -c "$PKGDB_BIN search '$params_manifest' | wc -l"
+  assert_success
+
+  _count="$output"
+
+  params_query="$(
+    genParams '.manifest.options.allow.unfree=true|.query["allow-unfree"]=false'
+  )"
+  run sh -c "$PKGDB_BIN search '$params_query' | wc -l"
+  assert_success
+  assert_output "$_count"
+}
+
 # ---------------------------------------------------------------------------- #
 
 # bats test_tags=search:params, search:params:fallbacks
@@ -5,7 +5,9 @@
   lib,
   bash,
   zsh,
+  fish,
   dash,
+  nushell,
   bats,
   coreutils,
   entr,
@@ -46,7 +48,9 @@
     [
       bash
       zsh
+      fish
       dash
+      nushell
       batsWith
       coreutils
       entr
@@ -86,6 +86,24 @@
       path = ../../pkgdb/src/buildenv/assets/set-prompt.zsh.sh;
     };
 
+    # Used by `buildenv' to set shell prompts on activation.
+    SET_PROMPT_FISH_SH = builtins.path {
+      name = "set-prompt.fish.sh";
+      path = ../../pkgdb/src/buildenv/assets/set-prompt.fish.sh;
+    };
+
+    # Used by `buildenv' to set shell prompts on activation.
+    SET_PROMPT_SH_SH = builtins.path {
+      name = "set-prompt.sh.sh";
+      path = ../../pkgdb/src/buildenv/assets/set-prompt.sh.sh;
+    };
+
+    # Used by `buildenv' to set shell prompts on activation.
+    SET_PROMPT_NU_SH = builtins.path {
+      name = "set-prompt.nu.sh";
+      path = ../../pkgdb/src/buildenv/assets/set-prompt.nu.sh;
+    };
+
     # Used by `buildenv --container' to create a container builder script.
     CONTAINER_BUILDER_PATH = builtins.path {
       name = "mkContainer.nix";
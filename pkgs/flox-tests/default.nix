@@ -5,7 +5,9 @@
   lib,
   bash,
   zsh,
+  fish,
   dash,
+  nushell,
   bats,
   coreutils,
   curl,
@@ -45,7 +47,9 @@
     [
       bash
       zsh
+      fish
       dash
+      nushell
       batsWith
       coreutils
       curl